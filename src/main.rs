@@ -1,23 +1,48 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use log::{info, LevelFilter};
 use plonky2::field::types::{Field, PrimeField64};
 use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
 use rand::random;
-
-use ntt::params::N;
+use std::env;
+use std::path::Path;
 
 use crate::vtfhe::crypto::ggsw::Ggsw;
 use crate::vtfhe::crypto::glwe::Glwe;
 use crate::vtfhe::crypto::lwe::{encrypt, get_delta};
 use crate::vtfhe::crypto::{compute_bsk, get_testv};
-use crate::vtfhe::ivc_based_vpbs::{verified_pbs, verify_pbs};
+use crate::vtfhe::ivc_based_vpbs::{verified_pbs, verify_pbs, KeyCommitment};
 
 mod ntt;
 mod vec_arithmetic;
 mod vtfhe;
 
 fn main() -> Result<()> {
-    // optimized parameters, use N=1024 (see ntt/mod.rs)
+    // `cargo run --release -- ntt-params <N> [--force]` writes the NTT parameter file
+    // `src/ntt/params_<N>.rs`, and only overwrites an existing one with `--force`
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).is_some_and(|arg| arg == "ntt-params") {
+        let (ring_dim, force) = match &args[2..] {
+            [ring_dim] => (ring_dim, false),
+            [ring_dim, flag] if flag == "--force" => (ring_dim, true),
+            _ => bail!("usage: ntt-params <N> [--force]"),
+        };
+        let ring_dim: usize = ring_dim.parse()?;
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src")
+            .join("ntt");
+        let path = ntt::param_gen::write_params_file(ring_dim, &dir, force)?;
+        println!("wrote {}", path.display());
+        if ntt::static_params(ring_dim).is_none() {
+            println!("to use it, add to src/ntt/mod.rs:");
+            println!("    #[rustfmt::skip]\n    pub mod params_{ring_dim};");
+            println!("    static PARAMS_{ring_dim}: NttParams = ntt_params!(params_{ring_dim});");
+            println!("and the arm `{ring_dim} => Some(&PARAMS_{ring_dim}),` to `static_params`");
+        }
+        return Ok(());
+    }
+
+    // optimized parameters
+    const N: usize = 1024; // ring dimension
 
     // dcecomposition parameters
     const LOGB: usize = 5;
@@ -33,6 +58,7 @@ fn main() -> Result<()> {
     const D: usize = 2;
     type C = PoseidonGoldilocksConfig;
     type F = <C as GenericConfig<D>>::F;
+    const STEPS_PER_PROOF: usize = 1; // PBS steps per recursive proof
 
     simple_logging::log_to_stderr(LevelFilter::Debug);
 
@@ -51,12 +77,19 @@ fn main() -> Result<()> {
     let ct = encrypt::<F, D, n>(&s_lwe, &(delta * m), sigma_lwe);
 
     // prove a PBS
-    let (out_ct, proof, cd) = verified_pbs::<F, C, D, n, N, K, ELL, LOGB>(
-        &ct, &testv, &bsk, &ksk, &s_glwe, &s_lwe, &s_to,
-    );
+    let (out_ct, proof, cd) =
+        verified_pbs::<F, C, D, n, N, K, ELL, LOGB, STEPS_PER_PROOF>(&ct, &testv, &bsk, &ksk);
 
-    // verify the PBS
-    verify_pbs::<F, C, D, n, N, K, ELL, LOGB>(&out_ct, &ct, &testv, &bsk, &ksk, &proof, &cd);
+    // verify the PBS, the verifier commits to the keys once
+    let key_commitment = KeyCommitment::new(&bsk, &ksk);
+    verify_pbs::<F, C, D, n, N, K, ELL, LOGB>(
+        &out_ct,
+        &ct,
+        &testv,
+        &key_commitment,
+        &proof,
+        &cd.verifier_data(),
+    )?;
     let m_bar = out_ct.decrypt(&s_to).coeffs;
 
     let m_out = F::from_canonical_usize(
@@ -5,24 +5,116 @@ use plonky2::hash::hash_types::RichField;
 use plonky2::iop::ext_target::ExtensionTarget;
 use plonky2::iop::target::Target;
 use plonky2::plonk::circuit_builder::CircuitBuilder;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+pub mod param_gen;
+
+#[rustfmt::skip]
+pub mod params_1024;
+#[rustfmt::skip]
+pub mod params_128;
+#[rustfmt::skip]
+pub mod params_16;
+#[rustfmt::skip]
+pub mod params_2048;
+#[rustfmt::skip]
+pub mod params_256;
+#[rustfmt::skip]
+pub mod params_32;
+#[rustfmt::skip]
+pub mod params_512;
+#[rustfmt::skip]
+pub mod params_64;
+#[rustfmt::skip]
+pub mod params_8;
+
+/// NTT parameters (bit-reversed roots of unity, inverse roots and N^-1) for one ring dimension.
+#[derive(Debug)]
+pub struct NttParams {
+    pub n: usize,
+    pub logn: u32,
+    pub ninv: u64,
+    pub roots: Cow<'static, [u64]>,
+    pub invroots: Cow<'static, [u64]>,
+    pub testg: Cow<'static, [u64]>,
+    pub testghat: Cow<'static, [u64]>,
+}
+
+macro_rules! ntt_params {
+    ($params:ident) => {
+        NttParams {
+            n: $params::N,
+            logn: $params::LOGN,
+            ninv: $params::NINV,
+            roots: Cow::Borrowed(&$params::ROOTS),
+            invroots: Cow::Borrowed(&$params::INVROOTS),
+            testg: Cow::Borrowed(&$params::TESTG),
+            testghat: Cow::Borrowed(&$params::TESTGHAT),
+        }
+    };
+}
 
-// use this path to set the ring dimension N (i.e. for N=512 set the path to "params_512.rs")
-// make sure to adjust the value of circuit_size in line 57 of "ivc_based_vpbs.rs" if
-// using a value other than 8 or 1024
-#[path = "params_1024.rs"]
-pub mod params;
+static PARAMS_8: NttParams = ntt_params!(params_8);
+static PARAMS_16: NttParams = ntt_params!(params_16);
+static PARAMS_32: NttParams = ntt_params!(params_32);
+static PARAMS_64: NttParams = ntt_params!(params_64);
+static PARAMS_128: NttParams = ntt_params!(params_128);
+static PARAMS_256: NttParams = ntt_params!(params_256);
+static PARAMS_512: NttParams = ntt_params!(params_512);
+static PARAMS_1024: NttParams = ntt_params!(params_1024);
+static PARAMS_2048: NttParams = ntt_params!(params_2048);
+
+static GENERATED_PARAMS: OnceLock<Mutex<HashMap<usize, &'static NttParams>>> = OnceLock::new();
+
+// Parameters without a `params_{N}.rs` file are generated once and kept for the lifetime of the process.
+fn generated_params(n: usize) -> &'static NttParams {
+    let mut cache = GENERATED_PARAMS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap();
+    *cache.entry(n).or_insert_with(|| {
+        let params = param_gen::generate_params(n);
+        param_gen::check_params(&params).unwrap();
+        Box::leak(Box::new(params))
+    })
+}
+
+/// Returns the NTT parameters of the `params_{N}.rs` file for the ring dimension `n`, if there is
+/// one.
+pub fn static_params(n: usize) -> Option<&'static NttParams> {
+    match n {
+        8 => Some(&PARAMS_8),
+        16 => Some(&PARAMS_16),
+        32 => Some(&PARAMS_32),
+        64 => Some(&PARAMS_64),
+        128 => Some(&PARAMS_128),
+        256 => Some(&PARAMS_256),
+        512 => Some(&PARAMS_512),
+        1024 => Some(&PARAMS_1024),
+        2048 => Some(&PARAMS_2048),
+        _ => None,
+    }
+}
+
+/// Returns the NTT parameters for the ring dimension `n`.
+pub fn get_params(n: usize) -> &'static NttParams {
+    static_params(n).unwrap_or_else(|| generated_params(n))
+}
 
 fn ntt_fw_update<F: RichField + Extendable<D>, const D: usize>(
     cb: &mut CircuitBuilder<F, D>,
     input: &Vec<Target>,
+    params: &NttParams,
     m: usize,
 ) -> Vec<Target> {
     let mut a = input.clone();
-    let t = params::N / (2 * m);
+    let t = params.n / (2 * m);
     for i in 0..m {
         let j1 = 2 * i * t;
         let j2 = j1 + t;
-        let root = params::ROOTS[m + i];
+        let root = params.roots[m + i];
         let s = cb.constant(F::from_canonical_u64(root));
         for j in j1..j2 {
             let u = a[j];
@@ -33,16 +125,17 @@ fn ntt_fw_update<F: RichField + Extendable<D>, const D: usize>(
     }
     a
 }
-fn ntt_fw_update_native<F: RichField + Extendable<D>, const D: usize>(
+pub fn ntt_fw_update_native<F: RichField + Extendable<D>, const D: usize>(
     input: &Vec<F>,
+    params: &NttParams,
     m: usize,
 ) -> Vec<F> {
     let mut a = input.clone();
-    let t = params::N / (2 * m);
+    let t = params.n / (2 * m);
     for i in 0..m {
         let j1 = 2 * i * t;
         let j2 = j1 + t;
-        let root = params::ROOTS[m + i];
+        let root = params.roots[m + i];
         let s = F::from_canonical_u64(root);
         for j in j1..j2 {
             let u = a[j];
@@ -54,13 +147,13 @@ fn ntt_fw_update_native<F: RichField + Extendable<D>, const D: usize>(
     a
 }
 
-fn eval_ntt_fw_update<P: PackedField>(input: &Vec<P>, m: usize) -> Vec<P> {
+pub fn eval_ntt_fw_update<P: PackedField>(input: &Vec<P>, params: &NttParams, m: usize) -> Vec<P> {
     let mut a = input.clone();
-    let t = params::N / (2 * m);
+    let t = params.n / (2 * m);
     for i in 0..m {
         let j1 = 2 * i * t;
         let j2 = j1 + t;
-        let root = params::ROOTS[m + i];
+        let root = params.roots[m + i];
         let s = P::from(P::Scalar::from_canonical_u64(root));
         for j in j1..j2 {
             let u = a[j];
@@ -72,17 +165,18 @@ fn eval_ntt_fw_update<P: PackedField>(input: &Vec<P>, m: usize) -> Vec<P> {
     a
 }
 
-fn eval_ntt_fw_update_ext<F: RichField + Extendable<D>, const D: usize>(
+pub fn eval_ntt_fw_update_ext<F: RichField + Extendable<D>, const D: usize>(
     builder: &mut CircuitBuilder<F, D>,
     input: &Vec<ExtensionTarget<D>>,
+    params: &NttParams,
     m: usize,
 ) -> Vec<ExtensionTarget<D>> {
     let mut a = input.clone();
-    let t = params::N / (2 * m);
+    let t = params.n / (2 * m);
     for i in 0..m {
         let j1 = 2 * i * t;
         let j2 = j1 + t;
-        let root = params::ROOTS[m + i];
+        let root = params.roots[m + i];
         let s = builder.constant_extension(F::Extension::from_canonical_u64(root));
         for j in j1..j2 {
             let u = a[j];
@@ -98,26 +192,29 @@ pub fn ntt_forward<F: RichField + Extendable<D>, const D: usize>(
     cb: &mut CircuitBuilder<F, D>,
     input: &Vec<Target>,
 ) -> Vec<Target> {
+    let params = get_params(input.len());
     let mut current = input.clone();
-    for m in (0..params::LOGN).map(|i| 2usize.pow(i)) {
-        current = ntt_fw_update(cb, &current, m);
+    for m in (0..params.logn).map(|i| 2usize.pow(i)) {
+        current = ntt_fw_update(cb, &current, params, m);
     }
 
     current
 }
 
 pub fn ntt_forward_native<F: RichField + Extendable<D>, const D: usize>(input: &Vec<F>) -> Vec<F> {
+    let params = get_params(input.len());
     let mut current = input.clone();
-    for m in (0..params::LOGN).map(|i| 2usize.pow(i)) {
-        current = ntt_fw_update_native(&current, m);
+    for m in (0..params.logn).map(|i| 2usize.pow(i)) {
+        current = ntt_fw_update_native(&current, params, m);
     }
     current
 }
 
 pub fn eval_ntt_forward<P: PackedField>(input: &Vec<P>) -> Vec<P> {
+    let params = get_params(input.len());
     let mut current = input.clone();
-    for m in (0..params::LOGN).map(|i| 2usize.pow(i)) {
-        current = eval_ntt_fw_update(&current, m);
+    for m in (0..params.logn).map(|i| 2usize.pow(i)) {
+        current = eval_ntt_fw_update(&current, params, m);
     }
 
     current
@@ -127,9 +224,10 @@ pub fn eval_ntt_forward_ext<F: RichField + Extendable<D>, const D: usize>(
     builder: &mut CircuitBuilder<F, D>,
     input: &Vec<ExtensionTarget<D>>,
 ) -> Vec<ExtensionTarget<D>> {
+    let params = get_params(input.len());
     let mut current = input.clone();
-    for m in (0..params::LOGN).map(|i| 2usize.pow(i)) {
-        current = eval_ntt_fw_update_ext(builder, &current, m);
+    for m in (0..params.logn).map(|i| 2usize.pow(i)) {
+        current = eval_ntt_fw_update_ext(builder, &current, params, m);
     }
 
     current
@@ -138,14 +236,15 @@ pub fn eval_ntt_forward_ext<F: RichField + Extendable<D>, const D: usize>(
 fn ntt_bw_update<F: RichField + Extendable<D>, const D: usize>(
     cb: &mut CircuitBuilder<F, D>,
     input: &Vec<Target>,
+    params: &NttParams,
     m: usize,
 ) -> Vec<Target> {
     let mut a = input.clone();
-    let t = params::N / (2 * m);
+    let t = params.n / (2 * m);
     let mut j1 = 0usize;
     for i in 0..m {
         let j2 = j1 + t;
-        let root = params::INVROOTS[m + i];
+        let root = params.invroots[m + i];
         let s = cb.constant(F::from_canonical_u64(root));
         for j in j1..j2 {
             let u = a[j];
@@ -158,16 +257,17 @@ fn ntt_bw_update<F: RichField + Extendable<D>, const D: usize>(
     }
     a
 }
-fn ntt_bw_update_native<F: RichField + Extendable<D>, const D: usize>(
+pub fn ntt_bw_update_native<F: RichField + Extendable<D>, const D: usize>(
     input: &Vec<F>,
+    params: &NttParams,
     m: usize,
 ) -> Vec<F> {
     let mut a = input.clone();
-    let t = params::N / (2 * m);
+    let t = params.n / (2 * m);
     let mut j1 = 0usize;
     for i in 0..m {
         let j2 = j1 + t;
-        let root = params::INVROOTS[m + i];
+        let root = params.invroots[m + i];
         let s = F::from_canonical_u64(root);
         for j in j1..j2 {
             let u = a[j];
@@ -181,13 +281,13 @@ fn ntt_bw_update_native<F: RichField + Extendable<D>, const D: usize>(
     a
 }
 
-pub fn eval_ntt_bw_update<P: PackedField>(input: &Vec<P>, m: usize) -> Vec<P> {
+pub fn eval_ntt_bw_update<P: PackedField>(input: &Vec<P>, params: &NttParams, m: usize) -> Vec<P> {
     let mut a = input.clone();
-    let t = params::N / (2 * m);
+    let t = params.n / (2 * m);
     let mut j1 = 0usize;
     for i in 0..m {
         let j2 = j1 + t;
-        let root = params::INVROOTS[m + i];
+        let root = params.invroots[m + i];
         let s = P::from(P::Scalar::from_canonical_u64(root));
         for j in j1..j2 {
             let u = a[j];
@@ -204,14 +304,15 @@ pub fn eval_ntt_bw_update<P: PackedField>(input: &Vec<P>, m: usize) -> Vec<P> {
 pub fn eval_ntt_bw_update_ext<F: RichField + Extendable<D>, const D: usize>(
     builder: &mut CircuitBuilder<F, D>,
     input: &Vec<ExtensionTarget<D>>,
+    params: &NttParams,
     m: usize,
 ) -> Vec<ExtensionTarget<D>> {
     let mut a = input.clone();
-    let t = params::N / (2 * m);
+    let t = params.n / (2 * m);
     let mut j1 = 0usize;
     for i in 0..m {
         let j2 = j1 + t;
-        let root = params::INVROOTS[m + i];
+        let root = params.invroots[m + i];
         let s = builder.constant_extension(F::Extension::from_canonical_u64(root));
         for j in j1..j2 {
             let u = a[j];
@@ -229,32 +330,35 @@ pub fn ntt_backward<F: RichField + Extendable<D>, const D: usize>(
     cb: &mut CircuitBuilder<F, D>,
     input: &Vec<Target>,
 ) -> Vec<Target> {
+    let params = get_params(input.len());
     let mut current = input.clone();
-    for m in (0..params::LOGN).rev().map(|i| 2usize.pow(i)) {
-        current = ntt_bw_update(cb, &current, m);
+    for m in (0..params.logn).rev().map(|i| 2usize.pow(i)) {
+        current = ntt_bw_update(cb, &current, params, m);
     }
 
-    let n_inv = cb.constant(F::from_canonical_u64(params::NINV));
+    let n_inv = cb.constant(F::from_canonical_u64(params.ninv));
     current.into_iter().map(|g| cb.mul(g, n_inv)).collect()
 }
 
 pub fn ntt_backward_native<F: RichField + Extendable<D>, const D: usize>(input: &Vec<F>) -> Vec<F> {
+    let params = get_params(input.len());
     let mut current = input.clone();
-    for m in (0..params::LOGN).rev().map(|i| 2usize.pow(i)) {
-        current = ntt_bw_update_native(&current, m);
+    for m in (0..params.logn).rev().map(|i| 2usize.pow(i)) {
+        current = ntt_bw_update_native(&current, params, m);
     }
 
-    let n_inv = F::from_canonical_u64(params::NINV);
+    let n_inv = F::from_canonical_u64(params.ninv);
     current.into_iter().map(|g| g * n_inv).collect()
 }
 
 pub fn eval_ntt_backward<P: PackedField>(input: &Vec<P>) -> Vec<P> {
+    let params = get_params(input.len());
     let mut current = input.clone();
-    for m in (0..params::LOGN).rev().map(|i| 2usize.pow(i)) {
-        current = eval_ntt_bw_update(&current, m);
+    for m in (0..params.logn).rev().map(|i| 2usize.pow(i)) {
+        current = eval_ntt_bw_update(&current, params, m);
     }
 
-    let n_inv = P::from(P::Scalar::from_canonical_u64(params::NINV));
+    let n_inv = P::from(P::Scalar::from_canonical_u64(params.ninv));
     current.into_iter().map(|g| g * n_inv).collect()
 }
 
@@ -262,12 +366,13 @@ pub fn eval_ntt_backward_ext<F: RichField + Extendable<D>, const D: usize>(
     builder: &mut CircuitBuilder<F, D>,
     input: &Vec<ExtensionTarget<D>>,
 ) -> Vec<ExtensionTarget<D>> {
+    let params = get_params(input.len());
     let mut current = input.clone();
-    for m in (0..params::LOGN).rev().map(|i| 2usize.pow(i)) {
-        current = eval_ntt_bw_update_ext(builder, &current, m);
+    for m in (0..params.logn).rev().map(|i| 2usize.pow(i)) {
+        current = eval_ntt_bw_update_ext(builder, &current, params, m);
     }
 
-    let n_inv = builder.constant_extension(F::Extension::from_canonical_u64(params::NINV));
+    let n_inv = builder.constant_extension(F::Extension::from_canonical_u64(params.ninv));
     current
         .into_iter()
         .map(|g| builder.mul_extension(g, n_inv))
@@ -283,60 +388,108 @@ mod tests {
     use plonky2::plonk::circuit_data::CircuitConfig;
     use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
 
-    #[test]
-    fn test_ntt_forward() {
+    const TEST_SIZES: [usize; 4] = [8, 512, 1024, 2048];
+
+    fn check_ntt_forward(n: usize) {
         const D: usize = 2;
         type C = PoseidonGoldilocksConfig;
         type F = <C as GenericConfig<D>>::F;
-        let N = params::N;
+        let params = get_params(n);
 
         let config = CircuitConfig::standard_recursion_config();
         let mut builder = CircuitBuilder::<F, D>::new(config);
-        let x = builder.add_virtual_targets(N);
+        let x = builder.add_virtual_targets(n);
 
         let z = ntt_forward(&mut builder, &x);
         builder.register_public_inputs(&x);
         builder.register_public_inputs(&z);
         let mut pw = PartialWitness::new();
-        pw.set_target_arr(&x, &params::TESTG.map(|g| F::from_canonical_u64(g)));
+        let testg: Vec<F> = params
+            .testg
+            .iter()
+            .map(|&g| F::from_canonical_u64(g))
+            .collect();
+        pw.set_target_arr(&x, &testg);
 
         let data = builder.build::<C>();
         let proof = data.prove(pw).unwrap();
-        let out = &proof.public_inputs[N..2 * N];
+        let out = &proof.public_inputs[n..2 * n];
 
-        for (&actual, expected) in out.into_iter().zip(params::TESTGHAT) {
+        for (&actual, &expected) in out.into_iter().zip(params.testghat.iter()) {
             assert_eq!(actual, F::from_canonical_u64(expected));
         }
 
         let _ = data.verify(proof).unwrap();
     }
 
-    #[test]
-    fn test_ntt_backward() {
+    fn check_ntt_backward(n: usize) {
         const D: usize = 2;
         type C = PoseidonGoldilocksConfig;
         type F = <C as GenericConfig<D>>::F;
-        let N = params::N;
+        let params = get_params(n);
 
         let config = CircuitConfig::standard_recursion_config();
         let mut builder = CircuitBuilder::<F, D>::new(config);
-        let x = builder.add_virtual_targets(N);
+        let x = builder.add_virtual_targets(n);
 
         let z = ntt_backward(&mut builder, &x);
         // Public inputs are the initial value (provided below) and the result (which is generated).
         builder.register_public_inputs(&x);
         builder.register_public_inputs(&z);
         let mut pw = PartialWitness::new();
-        pw.set_target_arr(&x, &params::TESTGHAT.map(|g| F::from_canonical_u64(g)));
+        let testghat: Vec<F> = params
+            .testghat
+            .iter()
+            .map(|&g| F::from_canonical_u64(g))
+            .collect();
+        pw.set_target_arr(&x, &testghat);
 
         let data = builder.build::<C>();
         let proof = data.prove(pw).unwrap();
-        let out = &proof.public_inputs[N..2 * N];
+        let out = &proof.public_inputs[n..2 * n];
 
-        for (&actual, expected) in out.into_iter().zip(params::TESTG) {
+        for (&actual, &expected) in out.into_iter().zip(params.testg.iter()) {
             assert_eq!(actual, F::from_canonical_u64(expected));
         }
 
         let _ = data.verify(proof).unwrap();
     }
+
+    #[test]
+    fn test_ntt_forward() {
+        for n in TEST_SIZES {
+            check_ntt_forward(n);
+        }
+    }
+
+    #[test]
+    fn test_ntt_backward() {
+        for n in TEST_SIZES {
+            check_ntt_backward(n);
+        }
+    }
+
+    #[test]
+    fn test_eval_ntt() {
+        type F = <PoseidonGoldilocksConfig as GenericConfig<2>>::F;
+
+        for n in TEST_SIZES {
+            let params = get_params(n);
+            let g: Vec<F> = params
+                .testg
+                .iter()
+                .map(|&x| F::from_canonical_u64(x))
+                .collect();
+            let ghat: Vec<F> = params
+                .testghat
+                .iter()
+                .map(|&x| F::from_canonical_u64(x))
+                .collect();
+
+            assert_eq!(eval_ntt_forward(&g), ghat);
+            assert_eq!(eval_ntt_backward(&ghat), g);
+            assert_eq!(ntt_forward_native::<F, 2>(&g), ghat);
+            assert_eq!(ntt_backward_native::<F, 2>(&ghat), g);
+        }
+    }
 }
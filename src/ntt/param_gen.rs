@@ -0,0 +1,236 @@
+/*
+    Native generator for the negacyclic NTT parameters over the Goldilocks field.
+    It computes the same tables as the `params_{N}.rs` files and can write new ones,
+    so ring dimensions can be added without an external CAS.
+*/
+
+use std::borrow::Cow;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Result};
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::field::types::{Field, Field64, PrimeField64, Sample};
+use plonky2::util::{log2_strict, reverse_index_bits};
+
+use super::{ntt_bw_update_native, ntt_fw_update_native, NttParams};
+
+type F = GoldilocksField;
+
+/// Returns a primitive `2n`-th root of unity, i.e. a root of `X^n + 1`.
+pub fn primitive_root_of_unity(n: usize) -> F {
+    assert!(n.is_power_of_two(), "N={n} is not a power of two.");
+    let two_n = 2 * n as u64;
+    assert_eq!(
+        (F::ORDER - 1) % two_n,
+        0,
+        "Goldilocks has no primitive {two_n}-th root of unity."
+    );
+
+    let w = F::MULTIPLICATIVE_GROUP_GENERATOR.exp_u64((F::ORDER - 1) / two_n);
+    assert_eq!(w.exp_u64(n as u64), -F::ONE);
+    w
+}
+
+fn to_u64(elements: &[F]) -> Vec<u64> {
+    elements.iter().map(|x| x.to_canonical_u64()).collect()
+}
+
+fn from_u64(elements: &[u64]) -> Vec<F> {
+    elements.iter().map(|&x| F::from_canonical_u64(x)).collect()
+}
+
+fn forward(params: &NttParams, input: &[F]) -> Vec<F> {
+    let mut current = input.to_vec();
+    for m in (0..params.logn).map(|i| 2usize.pow(i)) {
+        current = ntt_fw_update_native::<F, 2>(&current, params, m);
+    }
+    current
+}
+
+fn backward(params: &NttParams, input: &[F]) -> Vec<F> {
+    let mut current = input.to_vec();
+    for m in (0..params.logn).rev().map(|i| 2usize.pow(i)) {
+        current = ntt_bw_update_native::<F, 2>(&current, params, m);
+    }
+    let n_inv = F::from_canonical_u64(params.ninv);
+    current.into_iter().map(|g| g * n_inv).collect()
+}
+
+/// Multiplies two polynomials modulo `X^n + 1` in quadratic time.
+fn negacyclic_mul_schoolbook(left: &[F], right: &[F]) -> Vec<F> {
+    let n = left.len();
+    let mut out = vec![F::ZERO; n];
+    for (i, &l) in left.iter().enumerate() {
+        for (j, &r) in right.iter().enumerate() {
+            if i + j < n {
+                out[i + j] += l * r;
+            } else {
+                out[i + j - n] -= l * r;
+            }
+        }
+    }
+    out
+}
+
+/// Computes the NTT parameters for the ring dimension `n`, including fresh test vectors.
+pub fn generate_params(n: usize) -> NttParams {
+    let w = primitive_root_of_unity(n);
+    let w_inv = w.inverse();
+    let roots: Vec<F> = w.powers().take(n).collect();
+    let invroots: Vec<F> = w_inv.powers().take(n).collect();
+
+    let mut params = NttParams {
+        n,
+        logn: log2_strict(n) as u32,
+        ninv: F::from_canonical_usize(n).inverse().to_canonical_u64(),
+        roots: Cow::Owned(to_u64(&reverse_index_bits(&roots))),
+        invroots: Cow::Owned(to_u64(&reverse_index_bits(&invroots))),
+        testg: Cow::Owned(Vec::new()),
+        testghat: Cow::Owned(Vec::new()),
+    };
+
+    let testg = F::rand_vec(n);
+    params.testghat = Cow::Owned(to_u64(&forward(&params, &testg)));
+    params.testg = Cow::Owned(to_u64(&testg));
+    params
+}
+
+/// Checks the tables against the test vectors and a schoolbook negacyclic multiplication.
+pub fn check_params(params: &NttParams) -> Result<()> {
+    let n = params.n;
+    ensure!(n == 1 << params.logn, "LOGN does not match N={n}.");
+    for table in [
+        &params.roots,
+        &params.invroots,
+        &params.testg,
+        &params.testghat,
+    ] {
+        ensure!(table.len() == n, "Table length does not match N={n}.");
+    }
+    ensure!(
+        F::from_canonical_u64(params.ninv) * F::from_canonical_usize(n) == F::ONE,
+        "NINV is not the inverse of N={n}."
+    );
+
+    let testg = from_u64(&params.testg);
+    let testghat = from_u64(&params.testghat);
+    ensure!(
+        forward(params, &testg) == testghat,
+        "Forward NTT of TESTG is not TESTGHAT."
+    );
+    ensure!(
+        backward(params, &testghat) == testg,
+        "Backward NTT of TESTGHAT is not TESTG."
+    );
+
+    let left = F::rand_vec(n);
+    let right = F::rand_vec(n);
+    let left_hat = forward(params, &left);
+    let right_hat = forward(params, &right);
+    let prod_hat: Vec<F> = left_hat
+        .iter()
+        .zip(right_hat.iter())
+        .map(|(l, r)| *l * *r)
+        .collect();
+    ensure!(
+        backward(params, &prod_hat) == negacyclic_mul_schoolbook(&left, &right),
+        "NTT multiplication does not match the schoolbook negacyclic product."
+    );
+
+    Ok(())
+}
+
+/// Renders the parameters in the format of the `params_{N}.rs` files.
+pub fn params_file_contents(params: &NttParams) -> String {
+    let n = params.n;
+    format!(
+        "pub const N: usize = {n};\n\
+         pub const LOGN: u32 = {};\n\
+         pub const NINV: u64 = {};\n\n\
+         pub const ROOTS: [u64; {n}] = {:?};\n\n\
+         pub const INVROOTS: [u64; {n}] = {:?};\n\n\
+         // Test Vectors\n\n\
+         pub const TESTG: [u64; {n}] = {:?};\n\n\
+         pub const TESTGHAT: [u64; {n}] = {:?};\n\n",
+        params.logn, params.ninv, params.roots, params.invroots, params.testg, params.testghat,
+    )
+}
+
+/// Generates and checks the parameters for `n`, writes them to `dir/params_{n}.rs` and returns
+/// the path of the written file. An existing file is only replaced if `force` is set, since its
+/// test vectors are random and would change.
+pub fn write_params_file(n: usize, dir: &Path, force: bool) -> Result<PathBuf> {
+    let path = dir.join(format!("params_{n}.rs"));
+    ensure!(
+        force || !path.exists(),
+        "{} already exists, pass --force to overwrite it.",
+        path.display()
+    );
+    let params = generate_params(n);
+    check_params(&params)?;
+    fs::write(&path, params_file_contents(&params))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ntt::{get_params, static_params};
+    use rand::random;
+
+    #[test]
+    fn test_generated_params_match_files() {
+        for n in [8, 16, 32, 64, 128, 256, 512, 1024, 2048] {
+            let stored = get_params(n);
+            let generated = generate_params(n);
+
+            assert_eq!(generated.logn, stored.logn);
+            assert_eq!(generated.ninv, stored.ninv);
+            assert_eq!(generated.roots, stored.roots);
+            assert_eq!(generated.invroots, stored.invroots);
+
+            check_params(stored).unwrap();
+            check_params(&generated).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_check_params_rejects_bad_tables() {
+        let mut params = generate_params(16);
+        let mut roots = params.roots.to_vec();
+        roots.swap(1, 2);
+        params.roots = Cow::Owned(roots);
+        assert!(check_params(&params).is_err());
+    }
+
+    #[test]
+    fn test_get_params_generates_missing_sizes() {
+        let params = get_params(4096);
+        assert_eq!(params.n, 4096);
+        assert_eq!(params.roots[0], 1);
+        assert!(std::ptr::eq(params, get_params(4096)));
+        assert!(static_params(4096).is_none());
+        assert!(std::ptr::eq(static_params(16).unwrap(), get_params(16)));
+    }
+
+    #[test]
+    fn test_write_params_file() {
+        let dir = std::env::temp_dir().join(format!("ntt_params_{}", random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = write_params_file(16, &dir, false).unwrap();
+        assert_eq!(path, dir.join("params_16.rs"));
+        let contents = fs::read_to_string(&path).unwrap();
+        let stored = get_params(16);
+
+        assert!(contents.starts_with("pub const N: usize = 16;\npub const LOGN: u32 = 4;\n"));
+        assert!(contents.contains(&format!("pub const ROOTS: [u64; 16] = {:?};", stored.roots)));
+
+        // an existing file is only replaced with `force`
+        assert!(write_params_file(16, &dir, false).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+        write_params_file(16, &dir, true).unwrap();
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
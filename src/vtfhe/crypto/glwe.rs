@@ -162,10 +162,11 @@ impl<F: RichField + Extendable<D>, const D: usize, const N: usize, const K: usiz
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::ntt::params::N;
     use crate::vtfhe::crypto::lwe::decrypt;
     use plonky2::field::goldilocks_field::GoldilocksField;
 
+    const N: usize = 8;
+
     #[test]
     fn test_glwe_ct() {
         const K: usize = 3;
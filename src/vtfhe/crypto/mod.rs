@@ -43,3 +43,64 @@ pub fn compute_bsk<
         .map(|si| Ggsw::encrypt::<LOGB>(s_glwe, &Poly::constant(si), sigma).ntt_forward())
         .collect()
 }
+
+/// The noiseless keys of a PBS, the identity test vector and the encryption `ct` of a random
+/// message `m` scaled by `delta`, shared by the PBS tests.
+#[cfg(test)]
+pub(crate) struct PbsFixture<
+    F: RichField + Extendable<D>,
+    const D: usize,
+    const N: usize,
+    const K: usize,
+    const ELL: usize,
+> {
+    pub s_to: Vec<Poly<F, D, N>>,
+    pub s_lwe: Vec<F>,
+    pub s_glwe: Vec<Poly<F, D, N>>,
+    pub bsk: Vec<Ggsw<F, D, N, K, ELL>>,
+    pub ksk: Ggsw<F, D, N, K, ELL>,
+    pub testv: Poly<F, D, N>,
+    pub delta: F,
+    pub m: F,
+    pub ct: Vec<F>,
+}
+
+#[cfg(test)]
+pub(crate) fn pbs_fixture<
+    F: RichField + Extendable<D>,
+    const D: usize,
+    const n: usize,
+    const N: usize,
+    const K: usize,
+    const ELL: usize,
+    const LOGB: usize,
+>() -> PbsFixture<F, D, N, K, ELL> {
+    use self::glwe::Glwe;
+    use self::lwe::encrypt;
+    use plonky2::util::log2_ceil;
+    use std::array::from_fn;
+
+    let s_to = Glwe::<F, D, N, K>::partial_key(n);
+    let s_lwe = Glwe::<F, D, N, K>::flatten_partial_key(&s_to, n);
+    let s_glwe = Glwe::<F, D, N, K>::key_gen();
+    let bsk = compute_bsk::<F, D, N, K, ELL, LOGB>(&s_lwe, &s_glwe, 0f64);
+    let ksk = Ggsw::<F, D, N, K, ELL>::compute_ksk::<LOGB>(&s_to, &s_glwe, 0f64);
+
+    let testv = Poly::<F, D, N> {
+        coeffs: from_fn(F::from_canonical_usize),
+    };
+    let delta = F::from_noncanonical_biguint(F::order() >> log2_ceil(2 * N));
+    let m = F::from_canonical_u64(rand::random::<u64>() % (N as u64));
+    let ct = encrypt::<F, D, n>(&s_lwe, &(delta * m), 0f64);
+    PbsFixture {
+        s_to,
+        s_lwe,
+        s_glwe,
+        bsk,
+        ksk,
+        testv,
+        delta,
+        m,
+        ct,
+    }
+}
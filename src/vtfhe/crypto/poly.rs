@@ -2,17 +2,21 @@ use std::array::from_fn;
 
 use plonky2::{field::extension::Extendable, hash::hash_types::RichField};
 
-use crate::ntt::params;
+use crate::ntt::{get_params, NttParams};
 
 use super::lwe::error_sample;
 
-fn ntt_fw_update<F: RichField + Extendable<D>, const D: usize>(input: &[F], m: usize) -> Vec<F> {
+fn ntt_fw_update<F: RichField + Extendable<D>, const D: usize>(
+    input: &[F],
+    params: &NttParams,
+    m: usize,
+) -> Vec<F> {
     let mut a = input.to_vec();
-    let t = params::N / (2 * m);
+    let t = params.n / (2 * m);
     for i in 0..m {
         let j1 = 2 * i * t;
         let j2 = j1 + t;
-        let root = params::ROOTS[m + i];
+        let root = params.roots[m + i];
         let s = F::from_canonical_u64(root);
         for j in j1..j2 {
             let u = a[j];
@@ -25,21 +29,26 @@ fn ntt_fw_update<F: RichField + Extendable<D>, const D: usize>(input: &[F], m: u
 }
 
 pub fn ntt_forward<F: RichField + Extendable<D>, const D: usize>(input: &[F]) -> Vec<F> {
+    let params = get_params(input.len());
     let mut current = input.to_vec();
-    for m in (0..params::LOGN).map(|i| 2usize.pow(i)) {
-        current = ntt_fw_update(&current, m);
+    for m in (0..params.logn).map(|i| 2usize.pow(i)) {
+        current = ntt_fw_update(&current, params, m);
     }
 
     current
 }
 
-fn ntt_bw_update<F: RichField + Extendable<D>, const D: usize>(input: &[F], m: usize) -> Vec<F> {
+fn ntt_bw_update<F: RichField + Extendable<D>, const D: usize>(
+    input: &[F],
+    params: &NttParams,
+    m: usize,
+) -> Vec<F> {
     let mut a = input.to_vec();
-    let t = params::N / (2 * m);
+    let t = params.n / (2 * m);
     let mut j1 = 0usize;
     for i in 0..m {
         let j2 = j1 + t;
-        let root = params::INVROOTS[m + i];
+        let root = params.invroots[m + i];
         let s = F::from_canonical_u64(root);
         for j in j1..j2 {
             let u = a[j];
@@ -54,12 +63,13 @@ fn ntt_bw_update<F: RichField + Extendable<D>, const D: usize>(input: &[F], m: u
 }
 
 pub fn ntt_backward<F: RichField + Extendable<D>, const D: usize>(input: &[F]) -> Vec<F> {
+    let params = get_params(input.len());
     let mut current = input.to_vec();
-    for m in (0..params::LOGN).rev().map(|i| 2usize.pow(i)) {
-        current = ntt_bw_update(&current, m);
+    for m in (0..params.logn).rev().map(|i| 2usize.pow(i)) {
+        current = ntt_bw_update(&current, params, m);
     }
 
-    let n_inv = F::from_canonical_u64(params::NINV);
+    let n_inv = F::from_canonical_u64(params.ninv);
     current.into_iter().map(|g| g * n_inv).collect()
 }
 
@@ -123,14 +133,12 @@ impl<F: RichField + Extendable<D>, const D: usize, const N: usize> Poly<F, D, N>
     }
 
     pub fn ntt_fw(&self) -> Self {
-        assert_eq!(N, params::N);
         Poly {
             coeffs: ntt_forward(&self.coeffs).try_into().unwrap(),
         }
     }
 
     pub fn ntt_bw(&self) -> Self {
-        assert_eq!(N, params::N);
         Poly {
             coeffs: ntt_backward(&self.coeffs).try_into().unwrap(),
         }
@@ -187,23 +195,30 @@ impl<F: RichField + Extendable<D>, const D: usize, const N: usize> Poly<F, D, N>
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::ntt::params::{N, TESTG, TESTGHAT};
     use plonky2::field::goldilocks_field::GoldilocksField;
     use plonky2::field::types::Field;
 
-    #[test]
-    fn test_ntt() {
+    fn check_ntt<const N: usize>() {
         const D: usize = 2;
         type F = GoldilocksField;
+        let params = get_params(N);
         let test = Poly::<F, D, N> {
-            coeffs: TESTG.map(|x| F::from_canonical_u64(x)),
+            coeffs: from_fn(|i| F::from_canonical_u64(params.testg[i])),
         };
 
         let test_hat = Poly::<F, D, N> {
-            coeffs: TESTGHAT.map(|x| F::from_canonical_u64(x)),
+            coeffs: from_fn(|i| F::from_canonical_u64(params.testghat[i])),
         };
 
         assert_eq!(test.ntt_fw().coeffs, test_hat.coeffs);
         assert_eq!(test_hat.ntt_bw().coeffs, test.coeffs);
     }
+
+    #[test]
+    fn test_ntt() {
+        check_ntt::<8>();
+        check_ntt::<512>();
+        check_ntt::<1024>();
+        check_ntt::<2048>();
+    }
 }
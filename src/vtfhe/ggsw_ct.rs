@@ -124,7 +124,6 @@ mod tests {
         config::{GenericConfig, PoseidonGoldilocksConfig},
     };
 
-    use crate::ntt::params::N;
     use crate::vtfhe::{
         crypto::{glwe::Glwe, poly::Poly},
         glwe_ct::GlweCt,
@@ -177,6 +176,8 @@ mod tests {
         assert_eq!(m_glwe.scalar_mul(&bit), m_out);
     }
 
+    const N: usize = 8;
+
     #[test]
     fn test_key_switch() {
         const LOGB: usize = 8;
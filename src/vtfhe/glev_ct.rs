@@ -113,7 +113,6 @@ impl<const N: usize, const K: usize, const ELL: usize> GlevCt<N, K, ELL> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::ntt::params::N;
     use crate::vtfhe::crypto::glwe::Glwe;
     use crate::vtfhe::crypto::lwe::decrypt;
     use crate::vtfhe::crypto::poly::Poly;
@@ -123,6 +122,8 @@ mod tests {
     use plonky2::plonk::circuit_data::CircuitConfig;
     use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
 
+    const N: usize = 8;
+
     #[test]
     fn test_glev_mul() {
         const LOGB: usize = 8;
@@ -177,8 +177,8 @@ impl<const N: usize> GlwePoly<N> {
 
 #[cfg(test)]
 mod tests {
+    use crate::vtfhe::glwe_poly::decompose;
     use crate::vtfhe::glwe_poly::GlwePoly;
-    use crate::{ntt::params::N, vtfhe::glwe_poly::decompose};
     use plonky2::field::types::Field;
     use plonky2::{
         field::goldilocks_field::GoldilocksField,
@@ -190,6 +190,8 @@ mod tests {
         },
     };
 
+    const N: usize = 8;
+
     #[test]
     fn test_poly_const_rotate() {
         const K: usize = 2;
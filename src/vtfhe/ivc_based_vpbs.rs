@@ -1,37 +1,54 @@
-use crate::ntt::params::N;
-use crate::vtfhe::crypto::lwe::mod_switch_ct;
 use crate::vtfhe::{glwe_select, rotate_glwe};
 use anyhow::{ensure, Result};
+use itertools::Itertools;
 use log::{info, Level};
 use plonky2::field::extension::Extendable;
 use plonky2::gates::noop::NoopGate;
 use plonky2::hash::hash_types::{HashOut, HashOutTarget, RichField, NUM_HASH_OUT_ELTS};
+use plonky2::hash::merkle_proofs::{MerkleProof, MerkleProofTarget};
+use plonky2::hash::merkle_tree::MerkleTree;
 use plonky2::hash::poseidon::PoseidonHash;
-use plonky2::iop::target::Target;
+use plonky2::iop::target::{BoolTarget, Target};
 use plonky2::iop::witness::{PartialWitness, WitnessWrite};
 use plonky2::plonk::circuit_builder::CircuitBuilder;
-use plonky2::plonk::circuit_data::{CircuitConfig, CircuitData, CommonCircuitData};
-use plonky2::plonk::config::{AlgebraicHasher, GenericConfig, Hasher};
-use plonky2::plonk::proof::ProofWithPublicInputs;
+use plonky2::plonk::circuit_data::{
+    CircuitConfig, CircuitData, CommonCircuitData, VerifierCircuitData, VerifierCircuitTarget,
+};
+use plonky2::plonk::config::{AlgebraicHasher, GenericConfig, GenericHashOut, Hasher};
+use plonky2::plonk::proof::{ProofWithPublicInputs, ProofWithPublicInputsTarget};
 use plonky2::plonk::prover::prove;
 use plonky2::recursion::cyclic_recursion::check_cyclic_proof_verifier_data;
 use plonky2::recursion::dummy_circuit::cyclic_base_proof;
 use plonky2::timed;
+use plonky2::util::log2_ceil;
+use plonky2::util::serialization::DefaultGateSerializer;
 use plonky2::util::timing::TimingTree;
+use serde::Serialize;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
 use std::iter::once;
+use std::ops::{ControlFlow, Range};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use super::crypto::ggsw::Ggsw;
 use super::crypto::glwe::Glwe;
 use super::crypto::poly::Poly;
 use super::ggsw_ct::GgswCt;
 use super::glwe_ct::GlweCt;
+use super::starky_ct::generate_build_circuit_input;
+use super::starky_ct::ggsw_ct::GgswCtNative;
+use super::starky_ct::glwe_ct::GlweCtNative;
 
-// Generates `CommonCircuitData` usable for recursion.
+// Generates `CommonCircuitData` usable for recursion, padded to at least `2^degree_bits` rows.
 fn common_data_for_recursion<
     F: RichField + Extendable<D>,
     C: GenericConfig<D, F = F>,
     const D: usize,
->() -> CommonCircuitData<F, D>
+>(
+    degree_bits: usize,
+) -> CommonCircuitData<F, D>
 where
     C::Hasher: AlgebraicHasher<F>,
 {
@@ -51,33 +68,192 @@ where
     let verifier_data = builder.add_virtual_verifier_data(data.common.config.fri_config.cap_height);
     builder.verify_proof::<C>(&proof, &verifier_data, &data.common);
 
-    // IMPORTANT: this number needs to be adjusted according to circuit size.
-    // For small circuits it is (1 << 12), but with growing circuit size (e.g. for large N)
-    // this can go up to (1 << 16) or even higher. Use try and error.
-    let circuit_size = if N == 8 { 1 << 12 } else { 1 << 15 };
-    while builder.num_gates() < circuit_size {
+    // the public input gate added by `build` makes it more than half of the rows
+    while builder.num_gates() < (1 << degree_bits) >> 1 {
         builder.add_gate(NoopGate, vec![]);
     }
     builder.build::<C>().common
 }
 
-fn verify_hash_output<F: RichField>(hash_data: &[Vec<F>], claimed_hash: HashOut<F>) -> Result<()> {
+// Builds the cyclic circuit which `build_circuit` builds from the `CommonCircuitData` of its inner
+// proof, together with its targets. The common data has to be the one of the circuit itself, so
+// starting from the common data of the recursive verifier alone, the degree is raised to the one
+// of the built circuit until they match.
+fn cyclic_circuit_data<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+    T,
+>(
+    build_circuit: impl Fn(CommonCircuitData<F, D>) -> (CircuitBuilder<F, D>, T),
+) -> (CircuitData<F, C, D>, T)
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    let mut degree_bits = 0;
+    loop {
+        let common_data = common_data_for_recursion::<F, C, D>(degree_bits);
+        let goal_degree_bits = common_data.degree_bits();
+        let (builder, targets) = build_circuit(common_data);
+        let (circuit_data, success) = builder.try_build_with_options::<C>(true);
+        if success {
+            return (circuit_data, targets);
+        }
+
+        degree_bits = circuit_data.common.degree_bits();
+        assert!(
+            degree_bits > goal_degree_bits,
+            "Cyclic circuit does not match the common data of degree {goal_degree_bits}."
+        );
+        info!("Cyclic circuit needs degree {degree_bits}, rebuilding.");
+    }
+}
+
+// Chains the hashes of `hash_data` from the zero hash, like the steps of the cyclic circuit.
+fn chained_hash<F: RichField>(hash_data: &[Vec<F>]) -> HashOut<F> {
     let mut hash = HashOut::ZERO;
 
     for data in hash_data {
         let data_in: Vec<F> = hash
             .elements
             .into_iter()
-            .chain(data.into_iter().map(|&d| d))
+            .chain(data.iter().copied())
             .collect();
         hash = PoseidonHash::hash_no_pad(&data_in);
     }
-    ensure!(hash == claimed_hash);
+    hash
+}
+
+fn verify_hash_output<F: RichField>(hash_data: &[Vec<F>], claimed_hash: HashOut<F>) -> Result<()> {
+    ensure!(chained_hash(hash_data) == claimed_hash);
 
     Ok(())
 }
 
-fn build_step_circuit<
+// The initial accumulator of the blind rotation: the trivial GLWE encryption of `testv`.
+fn initial_acc<F: RichField + Extendable<D>, const D: usize, const N: usize, const K: usize>(
+    testv: &Poly<F, D, N>,
+) -> Vec<F> {
+    vec![F::ZERO; N * (K - 1)]
+        .into_iter()
+        .chain(testv.coeffs)
+        .collect()
+}
+
+// The data hashed into the LWE hash by the steps: the body, the mask and a zero for the key switch.
+fn lwe_hash_data<F: RichField>(ct: &[F]) -> Vec<Vec<F>> {
+    let (body, mask) = ct.split_last().unwrap();
+    once(vec![*body])
+        .chain(mask.iter().map(|&a| vec![a]))
+        .chain(once(vec![F::ZERO]))
+        .collect()
+}
+
+// The GGSW and the mask element of every step of the PBS of `ct`, where the step with counter `c`
+// is at index `c - 1`.
+fn pbs_steps<
+    'a,
+    F: RichField + Extendable<D>,
+    const D: usize,
+    const N: usize,
+    const K: usize,
+    const ELL: usize,
+>(
+    ct: &[F],
+    bsk: &'a [Ggsw<F, D, N, K, ELL>],
+    ksk: &'a Ggsw<F, D, N, K, ELL>,
+    dummy_ggsw: &'a Ggsw<F, D, N, K, ELL>,
+) -> Vec<(&'a Ggsw<F, D, N, K, ELL>, F)> {
+    let (body, mask) = ct.split_last().unwrap();
+    once((dummy_ggsw, *body))
+        .chain(bsk.iter().zip(mask.iter().copied()))
+        .chain(once((ksk, F::ZERO)))
+        .collect()
+}
+
+// The step at `index` in `pbs_steps`.
+fn pbs_step<const n: usize>(index: usize) -> PbsStep {
+    match index {
+        0 => PbsStep::BodyRotation,
+        i if i <= n => PbsStep::Cmux(i - 1),
+        _ => PbsStep::KeySwitch,
+    }
+}
+
+// The accumulator after the step at `index` in `pbs_steps`, computed natively.
+fn native_step<
+    F: RichField + Extendable<D>,
+    const D: usize,
+    const n: usize,
+    const N: usize,
+    const K: usize,
+    const ELL: usize,
+    const LOGB: usize,
+>(
+    acc: &Glwe<F, D, N, K>,
+    ggsw: &Ggsw<F, D, N, K, ELL>,
+    mask_element: F,
+    index: usize,
+) -> Glwe<F, D, N, K> {
+    let (acc_out, _, _) = generate_build_circuit_input::<F, D, n, N, K, ELL, LOGB>(
+        &GlweCtNative::from_glwe(acc),
+        &GgswCtNative::from_ggsw(ggsw),
+        mask_element,
+        F::from_canonical_usize(index + 1),
+    );
+    Glwe::from_slice(&acc_out.flatten())
+}
+
+// The height of the Merkle tree over the GGSWs of the n + 2 steps.
+fn key_tree_height(n: usize) -> usize {
+    log2_ceil(n + 2)
+}
+
+// The Merkle tree over the dummy GGSW of the body rotation, the BSK and the KSK. The GGSW of the
+// step with counter `c` is the leaf `c - 1`, the leaves after the KSK are empty.
+fn key_tree<
+    F: RichField + Extendable<D>,
+    const D: usize,
+    const N: usize,
+    const K: usize,
+    const ELL: usize,
+>(
+    bsk: &[Ggsw<F, D, N, K, ELL>],
+    ksk: &Ggsw<F, D, N, K, ELL>,
+) -> MerkleTree<F, PoseidonHash> {
+    let mut leaves = once(Ggsw::<F, D, N, K, ELL>::dummy_ct().flatten())
+        .chain(bsk.iter().map(|ggsw| ggsw.flatten()))
+        .chain(once(ksk.flatten()))
+        .collect_vec();
+    leaves.resize(1 << key_tree_height(bsk.len()), vec![]);
+    MerkleTree::new(leaves, 0)
+}
+
+// The witness targets of one step in the cyclic circuit.
+struct StepTargets<const N: usize, const K: usize, const ELL: usize> {
+    mask_element: Target,
+    ggsw: GgswCt<N, K, ELL>,
+    key_proof: MerkleProofTarget,
+}
+
+// Selects `h0` if `b` and `h1` otherwise.
+fn select_hash<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    b: BoolTarget,
+    h0: HashOutTarget,
+    h1: HashOutTarget,
+) -> HashOutTarget {
+    HashOutTarget::from_vec(
+        h0.elements
+            .into_iter()
+            .zip(h1.elements)
+            .map(|(x0, x1)| builder.select(b, x0, x1))
+            .collect(),
+    )
+}
+
+// One step of the PBS with counter `counter`, returns the accumulator and the LWE hash after it.
+fn build_blind_rotation_step<
     F: RichField + Extendable<D>,
     const D: usize,
     const LOGB: usize,
@@ -87,20 +263,12 @@ fn build_step_circuit<
     const n: usize,
 >(
     builder: &mut CircuitBuilder<F, D>,
-) -> (
-    Target,
-    GlweCt<N, K>,
-    GgswCt<N, K, ELL>,
-    GlweCt<N, K>,
-    Target,
-    HashOutTarget,
-    HashOutTarget,
-) {
-    let acc_init = GlweCt::<N, K>::new_from_builder(builder);
+    current_acc_in: &GlweCt<N, K>,
+    counter: Target,
+    key_root: HashOutTarget,
+    current_lwe_hash_in: HashOutTarget,
+) -> (GlweCt<N, K>, HashOutTarget, StepTargets<N, K, ELL>) {
     let ggsw = GgswCt::<N, K, ELL>::new_from_builder(builder);
-    acc_init.register(builder);
-    let current_acc_in = GlweCt::<N, K>::new_from_builder(builder);
-    let counter = builder.add_virtual_public_input();
     let one = builder.one();
     let first_step = builder.is_equal(counter, one);
     let n_target = builder.constant(F::from_canonical_usize(n + 2));
@@ -111,29 +279,31 @@ fn build_step_circuit<
     let neg_mask = builder.neg(mask_element);
     let first_negated_mask = builder.select(first_step, neg_mask, mask_element);
 
-    let shifted_glwe = rotate_glwe(builder, &current_acc_in, first_negated_mask);
-    let diff_glwe = shifted_glwe.sub(builder, &current_acc_in);
-    let xprod_in = glwe_select(builder, last_step, &current_acc_in, &diff_glwe);
+    let shifted_glwe = rotate_glwe(builder, current_acc_in, first_negated_mask);
+    let diff_glwe = shifted_glwe.sub(builder, current_acc_in);
+    let xprod_in = glwe_select(builder, last_step, current_acc_in, &diff_glwe);
     let xprod_out = ggsw.external_product::<F, D, LOGB>(builder, &xprod_in);
-    let cmux_out = xprod_out.add(builder, &current_acc_in);
+    let cmux_out = xprod_out.add(builder, current_acc_in);
 
     // in the last step we don't do a cmux, but just an external product for key switch
     let cmux_or_exprod = glwe_select(builder, last_step, &xprod_out, &cmux_out);
 
     // in the first step (body) we don't apply the full CMUX, just the rotation
     let current_acc_out = glwe_select(builder, first_step, &shifted_glwe, &cmux_or_exprod);
-    current_acc_out.register(builder);
 
-    let current_bsk_hash_in = builder.add_virtual_hash();
-    let current_bsk_hash_out = builder.hash_n_to_hash_no_pad::<PoseidonHash>(
-        current_bsk_hash_in
-            .elements
-            .into_iter()
-            .chain(ggsw.flatten().into_iter())
-            .collect(),
+    // the GGSW of this step is the leaf `counter - 1` of the key tree
+    let key_proof = MerkleProofTarget {
+        siblings: builder.add_virtual_hashes(key_tree_height(n)),
+    };
+    let leaf_index = builder.sub(counter, one);
+    let leaf_index_bits = builder.split_le(leaf_index, key_tree_height(n));
+    builder.verify_merkle_proof::<PoseidonHash>(
+        ggsw.flatten(),
+        &leaf_index_bits,
+        key_root,
+        &key_proof,
     );
 
-    let current_lwe_hash_in = builder.add_virtual_hash();
     let current_lwe_hash_out = builder.hash_n_to_hash_no_pad::<PoseidonHash>(
         current_lwe_hash_in
             .elements
@@ -142,258 +312,264 @@ fn build_step_circuit<
             .collect(),
     );
 
-    builder.register_public_inputs(&current_bsk_hash_out.elements);
-    builder.register_public_inputs(&current_lwe_hash_out.elements);
-
     (
-        mask_element,
-        acc_init,
-        ggsw,
-        current_acc_in,
-        counter,
-        current_bsk_hash_in,
-        current_lwe_hash_in,
+        current_acc_out,
+        current_lwe_hash_out,
+        StepTargets {
+            mask_element,
+            ggsw,
+            key_proof,
+        },
     )
 }
 
-pub fn verified_pbs<
+// The `STEPS_PER_PROOF` consecutive steps proved by one proof of the cyclic circuit, starting with
+// the returned counter. The counter of the public inputs is the one of the last step, and the steps
+// after the key switch repeat it without changing the accumulator or the LWE hash, so that the last
+// proof of a PBS may have fewer than `STEPS_PER_PROOF` steps.
+#[allow(clippy::type_complexity)]
+fn build_step_circuit<
     F: RichField + Extendable<D>,
-    C: GenericConfig<D, F = F>,
     const D: usize,
-    const n: usize,
+    const LOGB: usize,
     const N: usize,
     const K: usize,
     const ELL: usize,
-    const LOGB: usize,
+    const n: usize,
+    const STEPS_PER_PROOF: usize,
 >(
-    ct: &[F],
-    testv: &Poly<F, D, N>,
-    bsk: &[Ggsw<F, D, N, K, ELL>],
-    ksk: &Ggsw<F, D, N, K, ELL>,
-    debug_glwe_key: &[Poly<F, D, N>],
-    debug_lwe_key: &[F],
-    debug_ksk_key: &[Poly<F, D, N>],
+    builder: &mut CircuitBuilder<F, D>,
 ) -> (
-    Glwe<F, D, N, K>,
-    ProofWithPublicInputs<F, C, D>,
-    CircuitData<F, C, D>,
-)
-where
-    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>,
-    C: 'static,
-{
-    info!(
-        "Parameters: n={n}, N={N}, k={}, logB={LOGB}, ell={ELL}",
-        K - 1
-    );
+    PbsPublicInputsTarget<N, K>,
+    GlweCt<N, K>,
+    Target,
+    HashOutTarget,
+    Vec<StepTargets<N, K, ELL>>,
+) {
+    assert!(STEPS_PER_PROOF > 0, "A proof must prove at least one step.");
+    let acc_init = GlweCt::<N, K>::new_from_builder(builder);
+    let current_acc_in = GlweCt::<N, K>::new_from_builder(builder);
+    let first_counter = builder.add_virtual_target();
+    let key_root = builder.add_virtual_hash();
+    let current_lwe_hash_in = builder.add_virtual_hash();
 
-    let config = CircuitConfig::standard_recursion_config();
-    let mut builder = CircuitBuilder::<F, D>::new(config.clone());
-    let one = builder.one();
+    // the counter and the LWE hash the base case starts from, so that a proof can cover any range
+    // of steps, see `PbsTreeProver`
+    let start_counter = builder.add_virtual_target();
+    let lwe_hash_init = builder.add_virtual_hash();
+
+    let (mut current_acc_out, mut current_lwe_hash_out, step) =
+        build_blind_rotation_step::<F, D, LOGB, N, K, ELL, n>(
+            builder,
+            &current_acc_in,
+            first_counter,
+            key_root,
+            current_lwe_hash_in,
+        );
+    let mut steps = vec![step];
+    let mut step_counter = first_counter;
+    let n_target = builder.constant(F::from_canonical_usize(n + 2));
+    for _ in 1..STEPS_PER_PROOF {
+        let after_last_step = builder.is_equal(step_counter, n_target);
+        let active = builder.not(after_last_step);
+        step_counter = builder.add(step_counter, active.target);
+        let (acc_out, lwe_hash_out, step) = build_blind_rotation_step::<F, D, LOGB, N, K, ELL, n>(
+            builder,
+            &current_acc_out,
+            step_counter,
+            key_root,
+            current_lwe_hash_out,
+        );
+        current_acc_out = glwe_select(builder, active, &acc_out, &current_acc_out);
+        current_lwe_hash_out = select_hash(builder, active, lwe_hash_out, current_lwe_hash_out);
+        steps.push(step);
+    }
 
-    let (lwe_ct, acc_init, ggsw, current_acc_in, counter, current_bsk_hash_in, current_lwe_hash_in) =
-        build_step_circuit::<F, D, LOGB, N, K, ELL, n>(&mut builder);
-    let acc_init_range = (0, GlweCt::<N, K>::num_targets());
-    let counter_idx = acc_init_range.1;
-    let latest_acc_range = (
-        counter_idx + 1,
-        counter_idx + 1 + GlweCt::<N, K>::num_targets(),
-    );
+    let pis = PbsPublicInputsTarget {
+        acc_init,
+        counter: step_counter,
+        latest_acc: current_acc_out,
+        key_root,
+        lwe_hash: current_lwe_hash_out,
+        start_counter,
+        lwe_hash_init,
+    };
+    pis.register(builder);
 
-    let hash_bsk_out_range = (latest_acc_range.1, latest_acc_range.1 + NUM_HASH_OUT_ELTS);
-    let hash_lwe_out_range = (
-        hash_bsk_out_range.1,
-        hash_bsk_out_range.1 + NUM_HASH_OUT_ELTS,
-    );
+    (
+        pis,
+        current_acc_in,
+        first_counter,
+        current_lwe_hash_in,
+        steps,
+    )
+}
 
-    let mut common_data = common_data_for_recursion::<F, C, D>();
-    let verifier_data_target = builder.add_verifier_data_public_inputs();
-    common_data.num_public_inputs = builder.num_public_inputs();
-
-    // Unpack inner proof's public inputs.
-    let inner_cyclic_proof_with_pis = builder.add_virtual_proof_with_pis(&common_data);
-    let inner_cyclic_pis = &inner_cyclic_proof_with_pis.public_inputs;
-    let inner_cyclic_acc_init = &inner_cyclic_pis[acc_init_range.0..acc_init_range.1];
-    let inner_cyclic_counter = inner_cyclic_pis[counter_idx];
-    let inner_cyclic_latest_acc =
-        GlweCt::new_from_targets(&inner_cyclic_pis[latest_acc_range.0..latest_acc_range.1]);
-    let inner_cyclic_latest_bsk_hash =
-        HashOutTarget::try_from(&inner_cyclic_pis[hash_bsk_out_range.0..hash_bsk_out_range.1])
-            .unwrap();
-    let inner_cyclic_latest_lwe_hash =
-        HashOutTarget::try_from(&inner_cyclic_pis[hash_lwe_out_range.0..hash_lwe_out_range.1])
-            .unwrap();
+/// The commitment of PBS proofs to the BSK and the KSK: the root of the Poseidon Merkle tree over
+/// the dummy GGSW of the body rotation, the BSK and the KSK. Every step of the cyclic circuit opens
+/// its GGSW against this root, which is a public input. Verifiers compute it once with `new` or
+/// load it with `from_bytes`, instead of hashing the keys for every proof.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyCommitment<F: RichField> {
+    pub root: HashOut<F>,
+}
 
-    for (initial_target, inner_cyclic_initial_target) in acc_init
-        .flatten()
-        .iter()
-        .zip(inner_cyclic_acc_init.into_iter())
+impl<F: RichField> KeyCommitment<F> {
+    pub fn new<const D: usize, const N: usize, const K: usize, const ELL: usize>(
+        bsk: &[Ggsw<F, D, N, K, ELL>],
+        ksk: &Ggsw<F, D, N, K, ELL>,
+    ) -> Self
+    where
+        F: Extendable<D>,
     {
-        builder.connect(*initial_target, *inner_cyclic_initial_target);
+        Self {
+            root: key_tree(bsk, ksk).cap.0[0],
+        }
     }
 
-    // base case or not
-    let condition = builder.add_virtual_bool_target_safe();
-    let actual_acc_in = glwe_select(&mut builder, condition, &inner_cyclic_latest_acc, &acc_init);
-    for (left, right) in current_acc_in
-        .flatten()
-        .iter()
-        .zip(actual_acc_in.flatten().iter())
-    {
-        builder.connect(*left, *right);
+    /// The little endian bytes of the canonical root elements.
+    pub fn to_bytes(self) -> Vec<u8> {
+        self.root.to_bytes()
     }
 
-    let zero = builder.zero();
-    let actual_bsk_hash_in = inner_cyclic_latest_bsk_hash
-        .elements
-        .map(|t| builder.select(condition, t, zero));
-    let actual_lwe_hash_in = inner_cyclic_latest_lwe_hash
-        .elements
-        .map(|t| builder.select(condition, t, zero));
-
-    builder.connect_hashes(
-        current_bsk_hash_in,
-        HashOutTarget::try_from(actual_bsk_hash_in).unwrap(),
-    );
-
-    builder.connect_hashes(
-        current_lwe_hash_in,
-        HashOutTarget::try_from(actual_lwe_hash_in).unwrap(),
-    );
-
-    let new_counter = builder.mul_add(condition.target, inner_cyclic_counter, one);
-    builder.connect(counter, new_counter);
-
-    builder
-        .conditionally_verify_cyclic_proof_or_dummy::<C>(
-            condition,
-            &inner_cyclic_proof_with_pis,
-            &common_data,
-        )
-        .unwrap();
-
-    let cyclic_circuit_data = builder.build::<C>();
-
-    let mut testv_check = testv.clone();
-    let ct_switched = mod_switch_ct(&ct, N);
-
-    let mut pw = PartialWitness::new();
-    let initial_pis = vec![F::ZERO; N * (K - 1)]
-        .into_iter()
-        .chain(testv.coeffs.into_iter())
-        .enumerate()
-        .collect();
-    pw.set_bool_target(condition, false);
-
-    ggsw.assign(&mut pw, &Ggsw::dummy_ct());
-
-    pw.set_target(lwe_ct, ct[n]);
-
-    pw.set_proof_with_pis_target::<C, D>(
-        &inner_cyclic_proof_with_pis,
-        &cyclic_base_proof(
-            &common_data,
-            &cyclic_circuit_data.verifier_only,
-            initial_pis,
-        ),
-    );
-    pw.set_verifier_data_target(&verifier_data_target, &cyclic_circuit_data.verifier_only);
-    let mut timing = TimingTree::new("prove step 0", Level::Info);
-    let mut proof = prove::<F, C, D>(
-        &cyclic_circuit_data.prover_only,
-        &cyclic_circuit_data.common,
-        pw,
-        &mut timing,
-    )
-    .unwrap();
-    timing.print();
-    testv_check = testv_check.left_shift(ct_switched[n]);
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        ensure!(
+            bytes.len() == 8 * NUM_HASH_OUT_ELTS,
+            "Key commitment must have {} bytes.",
+            8 * NUM_HASH_OUT_ELTS
+        );
+        let elements = bytes
+            .chunks(8)
+            .map(|chunk| {
+                let x = u64::from_le_bytes(chunk.try_into().unwrap());
+                ensure!(x < F::ORDER, "Key commitment is not canonical.");
+                Ok(F::from_canonical_u64(x))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            root: HashOut::from_vec(elements),
+        })
+    }
+}
 
-    let pi_acc_int: Glwe<F, D, N, K> =
-        Glwe::from_slice(&proof.public_inputs[acc_init_range.0..acc_init_range.1]);
+/// The public inputs of the PBS proofs of `PbsProver` and `PbsTreeProver`, in the order in which
+/// the circuits register them. The public inputs of the cyclic proofs of `PbsProver` continue with
+/// its verifier data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PbsPublicInputs<
+    F: RichField + Extendable<D>,
+    const D: usize,
+    const N: usize,
+    const K: usize,
+> {
+    /// The accumulator before the first step, the trivial encryption of the test vector for a PBS.
+    pub acc_init: Glwe<F, D, N, K>,
+    /// The counter of the last step, n + 2 for a PBS.
+    pub counter: usize,
+    /// The accumulator after the last step, the output ciphertext for a PBS.
+    pub latest_acc: Glwe<F, D, N, K>,
+    /// The root of the key tree, see `KeyCommitment`.
+    pub key_root: HashOut<F>,
+    /// The LWE hash after the last step.
+    pub lwe_hash: HashOut<F>,
+    /// The counter of the first step, 1 for a PBS.
+    pub start_counter: usize,
+    /// The LWE hash before the first step, zero for a PBS.
+    pub lwe_hash_init: HashOut<F>,
+}
 
-    // println!("acc _int {:#?}", pi_acc_int);
+impl<F: RichField + Extendable<D>, const D: usize, const N: usize, const K: usize>
+    PbsPublicInputs<F, D, N, K>
+{
+    /// The number of public inputs, without the verifier data.
+    pub fn num_elements() -> usize {
+        PbsPublicInputsTarget::<N, K>::num_elements()
+    }
 
-    let mut current_acc: Glwe<F, D, N, K> =
-        Glwe::from_slice(&proof.public_inputs[latest_acc_range.0..latest_acc_range.1]);
+    /// Parses the first `num_elements()` of `public_inputs`.
+    pub fn from_public_inputs(public_inputs: &[F]) -> Result<Self> {
+        ensure!(
+            public_inputs.len() >= Self::num_elements(),
+            "PBS proofs have at least {} public inputs.",
+            Self::num_elements()
+        );
+        let mut elements = public_inputs.iter().copied();
+        let mut next = |len: usize| elements.by_ref().take(len).collect_vec();
+        Ok(Self {
+            acc_init: Glwe::from_slice(&next(GlweCt::<N, K>::num_targets())),
+            counter: next(1)[0].to_canonical_u64() as usize,
+            latest_acc: Glwe::from_slice(&next(GlweCt::<N, K>::num_targets())),
+            key_root: HashOut::from_vec(next(NUM_HASH_OUT_ELTS)),
+            lwe_hash: HashOut::from_vec(next(NUM_HASH_OUT_ELTS)),
+            start_counter: next(1)[0].to_canonical_u64() as usize,
+            lwe_hash_init: HashOut::from_vec(next(NUM_HASH_OUT_ELTS)),
+        })
+    }
 
-    // println!("current_acc {:#?}", current_acc);
+    pub fn to_public_inputs(&self) -> Vec<F> {
+        self.acc_init
+            .flatten()
+            .into_iter()
+            .chain(once(F::from_canonical_usize(self.counter)))
+            .chain(self.latest_acc.flatten())
+            .chain(self.key_root.elements)
+            .chain(self.lwe_hash.elements)
+            .chain(once(F::from_canonical_usize(self.start_counter)))
+            .chain(self.lwe_hash_init.elements)
+            .collect()
+    }
+}
 
-    info!(
-        "Avg error: {}",
-        current_acc.get_avg_error(&debug_glwe_key, &testv_check)
-    );
-    info!(
-        "Max error: {}",
-        current_acc.get_max_error(&debug_glwe_key, &testv_check)
-    );
+// The targets of `PbsPublicInputs`.
+struct PbsPublicInputsTarget<const N: usize, const K: usize> {
+    acc_init: GlweCt<N, K>,
+    counter: Target,
+    latest_acc: GlweCt<N, K>,
+    key_root: HashOutTarget,
+    lwe_hash: HashOutTarget,
+    start_counter: Target,
+    lwe_hash_init: HashOutTarget,
+}
 
-    for x in 0..n {
-        println!("loop {x}");
-        let mut pw = PartialWitness::new();
-        pw.set_bool_target(condition, true);
-        ggsw.assign(&mut pw, &bsk[x]);
-        pw.set_target(lwe_ct, ct[x]);
-        pw.set_proof_with_pis_target(&inner_cyclic_proof_with_pis, &proof);
-        pw.set_verifier_data_target(&verifier_data_target, &cyclic_circuit_data.verifier_only);
-        let root_name = format!("prove step {x}");
-        let mut timing = TimingTree::new(&root_name, Level::Info);
-        proof = prove::<F, C, D>(
-            &cyclic_circuit_data.prover_only,
-            &cyclic_circuit_data.common,
-            pw,
-            &mut timing,
-        )
-        .unwrap();
-        timing.print();
-        testv_check = testv_check
-            .right_shift(ct_switched[x] * (debug_lwe_key[x].to_canonical_u64() as usize));
-        current_acc =
-            Glwe::from_slice(&proof.public_inputs[latest_acc_range.0..latest_acc_range.1]);
-        info!(
-            "Avg error: {}",
-            current_acc.get_avg_error(&debug_glwe_key, &testv_check)
-        );
-        info!(
-            "Max error: {}",
-            current_acc.get_max_error(&debug_glwe_key, &testv_check)
-        );
+impl<const N: usize, const K: usize> PbsPublicInputsTarget<N, K> {
+    fn num_elements() -> usize {
+        2 * GlweCt::<N, K>::num_targets() + 2 + 3 * NUM_HASH_OUT_ELTS
     }
 
-    // key switch
-    let mut pw = PartialWitness::new();
-    pw.set_bool_target(condition, true);
-    ggsw.assign(&mut pw, &ksk);
-    pw.set_target(lwe_ct, F::ZERO);
-    pw.set_proof_with_pis_target(&inner_cyclic_proof_with_pis, &proof);
-    pw.set_verifier_data_target(&verifier_data_target, &cyclic_circuit_data.verifier_only);
-    let root_name = format!("key switch");
-    let mut timing = TimingTree::new(&root_name, Level::Info);
-    proof = prove::<F, C, D>(
-        &cyclic_circuit_data.prover_only,
-        &cyclic_circuit_data.common,
-        pw,
-        &mut timing,
-    )
-    .unwrap();
-    timing.print();
-
-    current_acc = Glwe::from_slice(&proof.public_inputs[latest_acc_range.0..latest_acc_range.1]);
-    info!(
-        "Avg error: {}",
-        current_acc.get_avg_error(&debug_ksk_key, &testv_check)
-    );
-    info!(
-        "Max error: {}",
-        current_acc.get_max_error(&debug_glwe_key, &testv_check)
-    );
+    // Parses the first `num_elements()` of `public_inputs`.
+    fn from_public_inputs(public_inputs: &[Target]) -> Self {
+        let mut targets = public_inputs[..Self::num_elements()].iter().copied();
+        let mut next = |len: usize| targets.by_ref().take(len).collect_vec();
+        Self {
+            acc_init: GlweCt::new_from_targets(&next(GlweCt::<N, K>::num_targets())),
+            counter: next(1)[0],
+            latest_acc: GlweCt::new_from_targets(&next(GlweCt::<N, K>::num_targets())),
+            key_root: HashOutTarget::from_vec(next(NUM_HASH_OUT_ELTS)),
+            lwe_hash: HashOutTarget::from_vec(next(NUM_HASH_OUT_ELTS)),
+            start_counter: next(1)[0],
+            lwe_hash_init: HashOutTarget::from_vec(next(NUM_HASH_OUT_ELTS)),
+        }
+    }
 
-    let acc_out_slice = &proof.public_inputs[latest_acc_range.0..latest_acc_range.1];
-    let acc_out = Glwe::<F, D, N, K>::from_slice(&acc_out_slice);
-    (acc_out, proof, cyclic_circuit_data)
+    fn register<F: RichField + Extendable<D>, const D: usize>(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+    ) {
+        self.acc_init.register(builder);
+        builder.register_public_input(self.counter);
+        self.latest_acc.register(builder);
+        builder.register_public_inputs(&self.key_root.elements);
+        builder.register_public_inputs(&self.lwe_hash.elements);
+        builder.register_public_input(self.start_counter);
+        builder.register_public_inputs(&self.lwe_hash_init.elements);
+    }
 }
 
-pub fn verify_pbs<
+/// The cyclic PBS circuit for fixed parameters, together with the targets of its witness. The
+/// circuit is built once and proves any number of PBS with these parameters. Every proof covers
+/// `STEPS_PER_PROOF` steps, so a PBS takes (n + 2) / `STEPS_PER_PROOF` proofs, rounded up, and
+/// larger values trade the recursion overhead of small N for a larger circuit.
+pub struct PbsProver<
     F: RichField + Extendable<D>,
     C: GenericConfig<D, F = F>,
     const D: usize,
@@ -402,153 +578,1625 @@ pub fn verify_pbs<
     const K: usize,
     const ELL: usize,
     const LOGB: usize,
->(
-    out_ct: &Glwe<F, D, N, K>,
-    ct: &[F],
-    testv: &Poly<F, D, N>,
-    bsk: &[Ggsw<F, D, N, K, ELL>],
-    ksk: &Ggsw<F, D, N, K, ELL>,
-    proof: &ProofWithPublicInputs<F, C, D>,
-    cd: &CircuitData<F, C, D>,
-) where
-    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>,
-    C: 'static,
+    const STEPS_PER_PROOF: usize,
+> {
+    circuit_data: CircuitData<F, C, D>,
+    steps: Vec<StepTargets<N, K, ELL>>,
+    condition: BoolTarget,
+    inner_cyclic_proof_with_pis: ProofWithPublicInputsTarget<D>,
+    verifier_data_target: VerifierCircuitTarget,
+}
+
+/// A step of the PBS proved by `PbsProver`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "step", content = "index", rename_all = "snake_case")]
+pub enum PbsStep {
+    /// The rotation by the body of the LWE ciphertext.
+    BodyRotation,
+    /// The CMUX with the GGSW of the BSK at this index.
+    Cmux(usize),
+    /// The external product with the KSK.
+    KeySwitch,
+}
+
+/// The progress of a PBS proof after one of its recursive proofs, see `PbsObserver::progress`.
+pub struct PbsProgress<'a> {
+    /// The last step proved so far.
+    pub step: PbsStep,
+    /// The index of `step` among all `num_steps` = n + 2 steps of the PBS.
+    pub index: usize,
+    pub num_steps: usize,
+    /// The time since the prover started, or resumed, proving steps.
+    pub elapsed: Duration,
+    /// The time and the `TimingTree` of the proof of the steps up to `step`, which are
+    /// `STEPS_PER_PROOF` steps or fewer at the end. The prover does not print it, see
+    /// `TimingObserver`.
+    pub step_time: Duration,
+    pub step_timing: &'a TimingTree,
+}
+
+/// Gets the accumulator after every step of a PBS proved by `PbsProver::prove_with_observer`,
+/// e.g. to measure its noise where the secret keys are, see `NoiseObserver`.
+pub trait PbsObserver<F: RichField + Extendable<D>, const D: usize, const N: usize, const K: usize>
 {
-    let acc_init_range = (0, GlweCt::<N, K>::num_targets());
-    let counter_idx = acc_init_range.1;
-    let latest_acc_range = (
-        counter_idx + 1,
-        counter_idx + 1 + GlweCt::<N, K>::num_targets(),
-    );
-    let hash_bsk_out_range = (latest_acc_range.1, latest_acc_range.1 + NUM_HASH_OUT_ELTS);
-    let hash_lwe_out_range = (
-        hash_bsk_out_range.1,
-        hash_bsk_out_range.1 + NUM_HASH_OUT_ELTS,
-    );
+    fn observe(&mut self, step: PbsStep, acc: &Glwe<F, D, N, K>);
 
-    let claimed_testv = &proof.public_inputs[acc_init_range.0..acc_init_range.1];
-    for x in &claimed_testv[..N * (K - 1)] {
-        assert_eq!(F::ZERO, *x);
-    }
-    for (x, y) in testv
-        .coeffs
-        .into_iter()
-        .zip(claimed_testv[N * (K - 1)..].into_iter())
-    {
-        assert_eq!(x, *y);
+    /// Gets the progress after every proof, e.g. to report it to a job runner. The prover stops
+    /// with a `PbsCancelled` error if this returns `ControlFlow::Break`.
+    fn progress(&mut self, _progress: &PbsProgress) -> ControlFlow<()> {
+        ControlFlow::Continue(())
     }
+}
 
-    assert_eq!(
-        F::from_canonical_usize(n + 2),
-        proof.public_inputs[counter_idx]
-    );
+impl<F: RichField + Extendable<D>, const D: usize, const N: usize, const K: usize>
+    PbsObserver<F, D, N, K> for ()
+{
+    fn observe(&mut self, _step: PbsStep, _acc: &Glwe<F, D, N, K>) {}
+}
 
-    let claimed_out_ct =
-        Glwe::from_slice(&proof.public_inputs[latest_acc_range.0..latest_acc_range.1]);
-    assert_eq!(*out_ct, claimed_out_ct);
+/// Prints the `TimingTree` of every proof of a PBS, as `verified_pbs` does.
+pub struct TimingObserver;
 
-    let mut timing = TimingTree::new("verify", Level::Info);
-    let _ = timed!(
-        timing,
-        "verifying Step 1",
-        cd.verify(proof.clone()).unwrap()
-    );
-    let _ = timed!(
-        timing,
-        "verifying Step 2",
-        check_cyclic_proof_verifier_data(&proof, &cd.verifier_only, &cd.common,).unwrap()
-    );
-    let hash_bsk_out =
-        HashOut::try_from(&proof.public_inputs[hash_bsk_out_range.0..hash_bsk_out_range.1])
-            .unwrap();
-    let hash_lwe_out =
-        HashOut::try_from(&proof.public_inputs[hash_lwe_out_range.0..hash_lwe_out_range.1])
-            .unwrap();
-    let mut hash_bsk_data: Vec<Vec<F>> = Vec::new();
-    let mut hash_lwe_data: Vec<Vec<F>> = Vec::new();
-    hash_bsk_data.push(Ggsw::<F, D, N, K, ELL>::dummy_ct().flatten());
-    hash_lwe_data.push(vec![ct[n]]);
+impl<F: RichField + Extendable<D>, const D: usize, const N: usize, const K: usize>
+    PbsObserver<F, D, N, K> for TimingObserver
+{
+    fn observe(&mut self, _step: PbsStep, _acc: &Glwe<F, D, N, K>) {}
 
-    for (ggsw, mask) in bsk.iter().zip(ct[..n].iter()) {
-        hash_bsk_data.push(ggsw.flatten());
-        hash_lwe_data.push(vec![*mask]);
+    fn progress(&mut self, progress: &PbsProgress) -> ControlFlow<()> {
+        progress.step_timing.print();
+        ControlFlow::Continue(())
     }
+}
 
-    // add ksk to hash data
-    hash_bsk_data.push(ksk.flatten());
-    hash_lwe_data.push(vec![F::ZERO]);
-
-    // we don't include check of the BSK hash in the timing, because we assume that the hash
-    // was precomputed
-    verify_hash_output(&hash_bsk_data, hash_bsk_out).unwrap();
+/// The error of a PBS proof which was cancelled by its `PbsObserver`. If the proof saves
+/// checkpoints, the last completed step is saved, so that it can be resumed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PbsCancelled {
+    /// The number of steps proved before the cancellation.
+    pub completed_steps: usize,
+}
 
-    let _ = timed!(
-        timing,
-        "verifying Step 3",
-        verify_hash_output(&hash_lwe_data, hash_lwe_out).unwrap()
-    );
-    timing.print();
+impl Display for PbsCancelled {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "PBS proof cancelled after {} steps",
+            self.completed_steps
+        )
+    }
+}
 
-    let counter = proof.public_inputs[counter_idx];
+impl std::error::Error for PbsCancelled {}
+
+impl<
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+        const D: usize,
+        const n: usize,
+        const N: usize,
+        const K: usize,
+        const ELL: usize,
+        const LOGB: usize,
+        const STEPS_PER_PROOF: usize,
+    > PbsProver<F, C, D, n, N, K, ELL, LOGB, STEPS_PER_PROOF>
+where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>,
+    C: 'static,
+{
+    /// Builds the cyclic PBS circuit.
+    pub fn new() -> Self {
+        info!(
+            "Parameters: n={n}, N={N}, k={}, logB={LOGB}, ell={ELL}, steps/proof={STEPS_PER_PROOF}",
+            K - 1
+        );
 
-    info!("number of steps: {}", counter);
+        let (circuit_data, (steps, condition, inner_cyclic_proof_with_pis, verifier_data_target)) =
+            cyclic_circuit_data::<F, C, D, _>(|mut common_data| {
+                let config = CircuitConfig::standard_recursion_config();
+                let mut builder = CircuitBuilder::<F, D>::new(config.clone());
+                let one = builder.one();
 
-    info!("proof size: {} bytes", proof.to_bytes().len());
-}
+                let (pis, current_acc_in, counter, current_lwe_hash_in, steps) =
+                    build_step_circuit::<F, D, LOGB, N, K, ELL, n, STEPS_PER_PROOF>(&mut builder);
 
-#[cfg(test)]
-mod tests {
-    use std::array::from_fn;
+                let verifier_data_target = builder.add_verifier_data_public_inputs();
+                common_data.num_public_inputs = builder.num_public_inputs();
 
-    use super::*;
-    use crate::ntt::params::N;
-    use crate::vtfhe::crypto::compute_bsk;
-    use crate::vtfhe::crypto::glwe::Glwe;
-    use crate::vtfhe::crypto::lwe::encrypt;
-    use crate::vtfhe::crypto::poly::Poly;
-    use crate::vtfhe::starky_ct::generate_build_circuit_input;
-    use crate::vtfhe::starky_ct::ggsw_ct::GgswCtNative;
-    use crate::vtfhe::starky_ct::glwe_ct::GlweCtNative;
+                // Unpack inner proof's public inputs.
+                let inner_cyclic_proof_with_pis = builder.add_virtual_proof_with_pis(&common_data);
+                let inner_cyclic_pis = PbsPublicInputsTarget::<N, K>::from_public_inputs(
+                    &inner_cyclic_proof_with_pis.public_inputs,
+                );
+
+                for (initial_target, inner_cyclic_initial_target) in pis
+                    .acc_init
+                    .flatten()
+                    .iter()
+                    .zip(inner_cyclic_pis.acc_init.flatten().iter())
+                {
+                    builder.connect(*initial_target, *inner_cyclic_initial_target);
+                }
+                builder.connect(pis.start_counter, inner_cyclic_pis.start_counter);
+                builder.connect_hashes(pis.lwe_hash_init, inner_cyclic_pis.lwe_hash_init);
+
+                // base case or not
+                let condition = builder.add_virtual_bool_target_safe();
+                let actual_acc_in = glwe_select(
+                    &mut builder,
+                    condition,
+                    &inner_cyclic_pis.latest_acc,
+                    &pis.acc_init,
+                );
+                for (left, right) in current_acc_in
+                    .flatten()
+                    .iter()
+                    .zip(actual_acc_in.flatten().iter())
+                {
+                    builder.connect(*left, *right);
+                }
+
+                // all steps open their GGSW against the same key root
+                let actual_key_root = select_hash(
+                    &mut builder,
+                    condition,
+                    inner_cyclic_pis.key_root,
+                    pis.key_root,
+                );
+                builder.connect_hashes(pis.key_root, actual_key_root);
+
+                let actual_lwe_hash_in = select_hash(
+                    &mut builder,
+                    condition,
+                    inner_cyclic_pis.lwe_hash,
+                    pis.lwe_hash_init,
+                );
+                builder.connect_hashes(current_lwe_hash_in, actual_lwe_hash_in);
+
+                let next_counter = builder.add(inner_cyclic_pis.counter, one);
+                let new_counter = builder.select(condition, next_counter, pis.start_counter);
+                builder.connect(counter, new_counter);
+
+                builder
+                    .conditionally_verify_cyclic_proof_or_dummy::<C>(
+                        condition,
+                        &inner_cyclic_proof_with_pis,
+                        &common_data,
+                    )
+                    .unwrap();
+
+                (
+                    builder,
+                    (
+                        steps,
+                        condition,
+                        inner_cyclic_proof_with_pis,
+                        verifier_data_target,
+                    ),
+                )
+            });
+
+        Self {
+            circuit_data,
+            steps,
+            condition,
+            inner_cyclic_proof_with_pis,
+            verifier_data_target,
+        }
+    }
+
+    /// The data of the cyclic circuit.
+    pub fn circuit_data(&self) -> &CircuitData<F, C, D> {
+        &self.circuit_data
+    }
+
+    /// The data `verify_pbs` needs to verify the proofs, see `verifier_data_to_bytes`.
+    pub fn verifier_data(&self) -> VerifierCircuitData<F, C, D> {
+        self.circuit_data.verifier_data()
+    }
+
+    /// Proves the PBS of `ct` with `testv`, `bsk` and `ksk`, and returns the output GLWE
+    /// ciphertext together with the proof of the last step.
+    pub fn prove(
+        &self,
+        ct: &[F],
+        testv: &Poly<F, D, N>,
+        bsk: &[Ggsw<F, D, N, K, ELL>],
+        ksk: &Ggsw<F, D, N, K, ELL>,
+    ) -> Result<(Glwe<F, D, N, K>, ProofWithPublicInputs<F, C, D>)> {
+        self.prove_with_observer(ct, testv, bsk, ksk, &mut ())
+    }
+
+    /// Like `prove`, and passes the accumulator and the progress after every step to `observer`,
+    /// which can cancel the proof.
+    pub fn prove_with_observer(
+        &self,
+        ct: &[F],
+        testv: &Poly<F, D, N>,
+        bsk: &[Ggsw<F, D, N, K, ELL>],
+        ksk: &Ggsw<F, D, N, K, ELL>,
+        observer: &mut impl PbsObserver<F, D, N, K>,
+    ) -> Result<(Glwe<F, D, N, K>, ProofWithPublicInputs<F, C, D>)> {
+        ensure!(ct.len() == n + 1, "LWE ciphertext does not match n={n}.");
+        ensure!(bsk.len() == n, "BSK does not match n={n}.");
+
+        let dummy_ggsw = Ggsw::dummy_ct();
+        let steps = pbs_steps(ct, bsk, ksk, &dummy_ggsw);
+        let proof = self.prove_range(
+            &key_tree(bsk, ksk),
+            &steps,
+            0..steps.len(),
+            &Glwe::from_slice(&initial_acc::<F, D, N, K>(testv)),
+            HashOut::ZERO,
+            observer,
+        )?;
+
+        Ok((Self::latest_acc(&proof), proof))
+    }
+
+    // The accumulator after the last step proved by `proof`.
+    fn latest_acc(proof: &ProofWithPublicInputs<F, C, D>) -> Glwe<F, D, N, K> {
+        PbsPublicInputs::<F, D, N, K>::from_public_inputs(&proof.public_inputs)
+            .unwrap()
+            .latest_acc
+    }
+
+    // Proves the steps `steps[range]` of a PBS, starting from the accumulator `acc_in` and the LWE
+    // hash `lwe_hash_in` before the first of them.
+    fn prove_range(
+        &self,
+        key_tree: &MerkleTree<F, PoseidonHash>,
+        steps: &[(&Ggsw<F, D, N, K, ELL>, F)],
+        range: Range<usize>,
+        acc_in: &Glwe<F, D, N, K>,
+        lwe_hash_in: HashOut<F>,
+        observer: &mut impl PbsObserver<F, D, N, K>,
+    ) -> Result<ProofWithPublicInputs<F, C, D>> {
+        let base_proof = self.base_proof(range.start, acc_in, lwe_hash_in);
+        self.prove_steps(key_tree, steps, range, base_proof, false, observer, None)
+    }
+
+    // The base proof of a range starting at `steps[start]`.
+    fn base_proof(
+        &self,
+        start: usize,
+        acc_in: &Glwe<F, D, N, K>,
+        lwe_hash_in: HashOut<F>,
+    ) -> ProofWithPublicInputs<F, C, D> {
+        // only the initial accumulator, the start counter and the initial LWE hash are used
+        let initial_pis = PbsPublicInputs {
+            acc_init: acc_in.clone(),
+            counter: 0,
+            latest_acc: Glwe::dummy_ct(),
+            key_root: HashOut::ZERO,
+            lwe_hash: HashOut::ZERO,
+            start_counter: start + 1,
+            lwe_hash_init: lwe_hash_in,
+        }
+        .to_public_inputs()
+        .into_iter()
+        .enumerate()
+        .collect();
+        cyclic_base_proof(
+            &self.circuit_data.common,
+            &self.circuit_data.verifier_only,
+            initial_pis,
+        )
+    }
+
+    // Proves the steps `steps[range]` on top of `proof`, which is the base proof if `!condition`,
+    // with `STEPS_PER_PROOF` steps per proof. Saves a checkpoint after every proof which passes a
+    // multiple of `checkpoints.interval` steps.
+    #[allow(clippy::too_many_arguments)]
+    fn prove_steps(
+        &self,
+        key_tree: &MerkleTree<F, PoseidonHash>,
+        steps: &[(&Ggsw<F, D, N, K, ELL>, F)],
+        range: Range<usize>,
+        mut proof: ProofWithPublicInputs<F, C, D>,
+        condition: bool,
+        observer: &mut impl PbsObserver<F, D, N, K>,
+        checkpoints: Option<&PbsCheckpoints>,
+    ) -> Result<ProofWithPublicInputs<F, C, D>> {
+        // the steps after the key switch in the last proof repeat it, other proofs must be full
+        ensure!(
+            range.len().is_multiple_of(STEPS_PER_PROOF) || range.end == steps.len(),
+            "Range of steps must be a multiple of {STEPS_PER_PROOF} steps or end with the last one."
+        );
+
+        let start_time = Instant::now();
+        for first in range.clone().step_by(STEPS_PER_PROOF) {
+            let proof_steps = first..(first + STEPS_PER_PROOF).min(range.end);
+            let last = proof_steps.end - 1;
+            let step = pbs_step::<n>(last);
+            let name = match step {
+                _ if proof_steps.len() > 1 => format!("prove steps {first}..={last}"),
+                PbsStep::BodyRotation => "prove step 0".to_string(),
+                PbsStep::Cmux(x) => format!("prove step {x}"),
+                PbsStep::KeySwitch => "key switch".to_string(),
+            };
+            let condition = condition || first != range.start;
+
+            // the proof only has the accumulator after its last step
+            let mut accs = vec![];
+            if proof_steps.len() > 1 {
+                let pis = PbsPublicInputs::<F, D, N, K>::from_public_inputs(&proof.public_inputs)?;
+                let mut acc = if condition {
+                    pis.latest_acc
+                } else {
+                    pis.acc_init
+                };
+                for (i, &(ggsw, mask_element)) in (first..last).zip(&steps[first..last]) {
+                    acc = native_step::<F, D, n, N, K, ELL, LOGB>(&acc, ggsw, mask_element, i);
+                    accs.push(acc.clone());
+                }
+            }
+
+            let step_start_time = Instant::now();
+            let witnesses = (first..first + STEPS_PER_PROOF)
+                .map(|i| {
+                    let i = i.min(steps.len() - 1);
+                    let (ggsw, mask_element) = steps[i];
+                    (ggsw, key_tree.prove(i), mask_element)
+                })
+                .collect_vec();
+            let (next_proof, step_timing) =
+                self.prove_step(&witnesses, condition, &proof, &name)?;
+            proof = next_proof;
+            let step_time = step_start_time.elapsed();
+            accs.push(Self::latest_acc(&proof));
+            for (i, acc) in proof_steps.clone().zip(&accs) {
+                observer.observe(pbs_step::<n>(i), acc);
+            }
+            let progress = observer.progress(&PbsProgress {
+                step,
+                index: last,
+                num_steps: steps.len(),
+                elapsed: start_time.elapsed(),
+                step_time,
+                step_timing: &step_timing,
+            });
+
+            let cancelled = progress.is_break();
+            if let Some(checkpoints) = checkpoints {
+                if cancelled || (last + 1) / checkpoints.interval > first / checkpoints.interval {
+                    checkpoints.save(&proof, &Self::checkpoint_state(&proof))?;
+                }
+            }
+            if cancelled {
+                info!("PBS proof cancelled after step {}.", last + 1);
+                return Err(PbsCancelled {
+                    completed_steps: last + 1,
+                }
+                .into());
+            }
+        }
+
+        Ok(proof)
+    }
+
+    fn checkpoint_state(proof: &ProofWithPublicInputs<F, C, D>) -> CheckpointState<F> {
+        let pis = PbsPublicInputs::<F, D, N, K>::from_public_inputs(&proof.public_inputs).unwrap();
+        CheckpointState {
+            counter: pis.counter,
+            lwe_hash: pis.lwe_hash,
+            key_root: pis.key_root,
+        }
+    }
+
+    /// Like `prove`, and saves the latest proof to `checkpoints` after every
+    /// `checkpoints.interval` steps and when `observer` cancels it, so that a run which did not
+    /// finish can be continued with `resume`.
+    pub fn prove_with_checkpoints(
+        &self,
+        ct: &[F],
+        testv: &Poly<F, D, N>,
+        bsk: &[Ggsw<F, D, N, K, ELL>],
+        ksk: &Ggsw<F, D, N, K, ELL>,
+        checkpoints: &PbsCheckpoints,
+        observer: &mut impl PbsObserver<F, D, N, K>,
+    ) -> Result<(Glwe<F, D, N, K>, ProofWithPublicInputs<F, C, D>)> {
+        ensure!(ct.len() == n + 1, "LWE ciphertext does not match n={n}.");
+        ensure!(bsk.len() == n, "BSK does not match n={n}.");
+        fs::create_dir_all(&checkpoints.dir)?;
+
+        let dummy_ggsw = Ggsw::dummy_ct();
+        let steps = pbs_steps(ct, bsk, ksk, &dummy_ggsw);
+        let base_proof = self.base_proof(
+            0,
+            &Glwe::from_slice(&initial_acc::<F, D, N, K>(testv)),
+            HashOut::ZERO,
+        );
+        let proof = self.prove_steps(
+            &key_tree(bsk, ksk),
+            &steps,
+            0..steps.len(),
+            base_proof,
+            false,
+            observer,
+            Some(checkpoints),
+        )?;
+
+        Ok((Self::latest_acc(&proof), proof))
+    }
+
+    /// Continues the PBS of `prove_with_checkpoints` from the last checkpoint in `checkpoints`,
+    /// after checking that its proof verifies and belongs to this PBS.
+    pub fn resume(
+        &self,
+        ct: &[F],
+        testv: &Poly<F, D, N>,
+        bsk: &[Ggsw<F, D, N, K, ELL>],
+        ksk: &Ggsw<F, D, N, K, ELL>,
+        checkpoints: &PbsCheckpoints,
+        observer: &mut impl PbsObserver<F, D, N, K>,
+    ) -> Result<(Glwe<F, D, N, K>, ProofWithPublicInputs<F, C, D>)> {
+        ensure!(ct.len() == n + 1, "LWE ciphertext does not match n={n}.");
+        ensure!(bsk.len() == n, "BSK does not match n={n}.");
+
+        let (state, proof) = checkpoints.load(&self.circuit_data.common)?;
+        self.circuit_data.verify(proof.clone())?;
+        check_cyclic_proof_verifier_data(
+            &proof,
+            &self.circuit_data.verifier_only,
+            &self.circuit_data.common,
+        )?;
+
+        let pis = PbsPublicInputs::<F, D, N, K>::from_public_inputs(&proof.public_inputs)?;
+
+        let key_tree = key_tree(bsk, ksk);
+        let counter = state.counter;
+        ensure!(
+            (1..=n + 2).contains(&counter) && pis.counter == counter,
+            "Checkpoint counter does not match its proof."
+        );
+        ensure!(
+            pis.acc_init.flatten() == initial_acc::<F, D, N, K>(testv),
+            "Checkpoint does not start from the test vector."
+        );
+        ensure!(
+            pis.start_counter == 1 && pis.lwe_hash_init == HashOut::ZERO,
+            "Checkpoint does not start from the first step."
+        );
+        ensure!(
+            pis.key_root == key_tree.cap.0[0] && state.key_root == key_tree.cap.0[0],
+            "Checkpoint does not use the BSK and KSK."
+        );
+        let lwe_hash = chained_hash(&lwe_hash_data(ct)[..counter]);
+        ensure!(
+            pis.lwe_hash == lwe_hash && state.lwe_hash == lwe_hash,
+            "Checkpoint does not use the LWE ciphertext."
+        );
+        info!("Resuming from step {counter}.");
+
+        let dummy_ggsw = Ggsw::dummy_ct();
+        let steps = pbs_steps(ct, bsk, ksk, &dummy_ggsw);
+        let proof = self.prove_steps(
+            &key_tree,
+            &steps,
+            counter..steps.len(),
+            proof,
+            true,
+            observer,
+            Some(checkpoints),
+        )?;
+
+        Ok((Self::latest_acc(&proof), proof))
+    }
+
+    // Proves `STEPS_PER_PROOF` steps on top of `inner_proof`, which is the base proof if
+    // `!condition`. Every step has its GGSW, the Merkle proof opening it in the key tree and its
+    // mask element.
+    fn prove_step(
+        &self,
+        steps: &[(&Ggsw<F, D, N, K, ELL>, MerkleProof<F, PoseidonHash>, F)],
+        condition: bool,
+        inner_proof: &ProofWithPublicInputs<F, C, D>,
+        name: &str,
+    ) -> Result<(ProofWithPublicInputs<F, C, D>, TimingTree)> {
+        let mut pw = PartialWitness::new();
+        pw.set_bool_target(self.condition, condition);
+        for (targets, (ggsw, key_proof, mask_element)) in self.steps.iter().zip(steps) {
+            targets.ggsw.assign(&mut pw, ggsw);
+            for (&target, &sibling) in targets.key_proof.siblings.iter().zip(&key_proof.siblings) {
+                pw.set_hash_target(target, sibling);
+            }
+            pw.set_target(targets.mask_element, *mask_element);
+        }
+        pw.set_proof_with_pis_target(&self.inner_cyclic_proof_with_pis, inner_proof);
+        pw.set_verifier_data_target(&self.verifier_data_target, &self.circuit_data.verifier_only);
+
+        let mut timing = TimingTree::new(name, Level::Info);
+        let proof = prove::<F, C, D>(
+            &self.circuit_data.prover_only,
+            &self.circuit_data.common,
+            pw,
+            &mut timing,
+        )?;
+        Ok((proof, timing))
+    }
+}
+
+/// Where and how often `PbsProver::prove_with_checkpoints` saves its progress. Every checkpoint is
+/// the latest cyclic proof in `proof_<counter>.bin`, written with
+/// `ProofWithPublicInputs::to_bytes`, and the `state` file with its counter and running hashes,
+/// which is written after the proof.
+#[derive(Clone, Debug)]
+pub struct PbsCheckpoints {
+    pub dir: PathBuf,
+    pub interval: usize,
+}
+
+// The `state` file of a checkpoint.
+struct CheckpointState<F: RichField> {
+    counter: usize,
+    lwe_hash: HashOut<F>,
+    key_root: HashOut<F>,
+}
+
+impl PbsCheckpoints {
+    /// Saves a checkpoint to `dir` after every `interval` steps.
+    pub fn new(dir: impl Into<PathBuf>, interval: usize) -> Self {
+        assert!(interval > 0, "Checkpoint interval must be positive.");
+        Self {
+            dir: dir.into(),
+            interval,
+        }
+    }
+
+    fn proof_path(&self, counter: usize) -> PathBuf {
+        self.dir.join(format!("proof_{counter}.bin"))
+    }
+
+    fn state_path(&self) -> PathBuf {
+        self.dir.join("state")
+    }
+
+    // Replaces the checkpoint with `proof`. Every file is renamed into place once it is written,
+    // and the state only points to the new proof once that is complete.
+    fn save<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+        &self,
+        proof: &ProofWithPublicInputs<F, C, D>,
+        state: &CheckpointState<F>,
+    ) -> Result<()> {
+        let hash_string = |hash: HashOut<F>| {
+            hash.elements
+                .iter()
+                .map(|x| x.to_canonical_u64().to_string())
+                .join(" ")
+        };
+        let counter = state.counter;
+        let state = format!(
+            "counter {counter}\nlwe_hash {}\nkey_root {}\n",
+            hash_string(state.lwe_hash),
+            hash_string(state.key_root)
+        );
+
+        let write = |path: PathBuf, contents: &[u8]| -> Result<()> {
+            let tmp_path = path.with_extension("tmp");
+            fs::write(&tmp_path, contents)?;
+            fs::rename(tmp_path, path)?;
+            Ok(())
+        };
+        write(self.proof_path(counter), &proof.to_bytes())?;
+        write(self.state_path(), state.as_bytes())?;
+
+        // the older proofs are not needed anymore
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let name = path.file_name().unwrap().to_string_lossy();
+            if name.starts_with("proof_") && path != self.proof_path(counter) {
+                fs::remove_file(path)?;
+            }
+        }
+        info!("Saved checkpoint at step {counter}.");
+        Ok(())
+    }
+
+    // Loads the state of the checkpoint and the proof it points to.
+    fn load<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+        &self,
+        common_data: &CommonCircuitData<F, D>,
+    ) -> Result<(CheckpointState<F>, ProofWithPublicInputs<F, C, D>)> {
+        let state = fs::read_to_string(self.state_path())?;
+        let mut lines = state.lines().map(|line| line.split(' ').collect_vec());
+        let mut field = |name: &str| -> Result<Vec<u64>> {
+            let line = lines.next().unwrap_or_default();
+            ensure!(
+                line.first() == Some(&name),
+                "Checkpoint state has no {name}."
+            );
+            Ok(line[1..]
+                .iter()
+                .map(|x| x.parse::<u64>())
+                .collect::<Result<Vec<_>, _>>()?)
+        };
+        let hash = |elements: Vec<u64>| -> Result<HashOut<F>> {
+            ensure!(
+                elements.len() == NUM_HASH_OUT_ELTS && elements.iter().all(|&x| x < F::ORDER),
+                "Checkpoint state has an invalid hash."
+            );
+            Ok(HashOut::from_vec(
+                elements.into_iter().map(F::from_canonical_u64).collect(),
+            ))
+        };
+        let counter = field("counter")?;
+        ensure!(
+            counter.len() == 1,
+            "Checkpoint state has an invalid counter."
+        );
+        let state = CheckpointState {
+            counter: counter[0] as usize,
+            lwe_hash: hash(field("lwe_hash")?)?,
+            key_root: hash(field("key_root")?)?,
+        };
+
+        let proof_bytes = fs::read(self.proof_path(state.counter))?;
+        let proof = ProofWithPublicInputs::from_bytes(proof_bytes, common_data)?;
+        Ok((state, proof))
+    }
+}
+
+/// Proves a PBS with a `PbsProver` built for this call only and prints the timing of every proof.
+/// It only takes public material, the noise of the steps can be observed with
+/// `PbsProver::prove_with_observer`.
+pub fn verified_pbs<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+    const n: usize,
+    const N: usize,
+    const K: usize,
+    const ELL: usize,
+    const LOGB: usize,
+    const STEPS_PER_PROOF: usize,
+>(
+    ct: &[F],
+    testv: &Poly<F, D, N>,
+    bsk: &[Ggsw<F, D, N, K, ELL>],
+    ksk: &Ggsw<F, D, N, K, ELL>,
+) -> (
+    Glwe<F, D, N, K>,
+    ProofWithPublicInputs<F, C, D>,
+    CircuitData<F, C, D>,
+)
+where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>,
+    C: 'static,
+{
+    verified_pbs_with_observer::<F, C, D, n, N, K, ELL, LOGB, STEPS_PER_PROOF>(
+        ct,
+        testv,
+        bsk,
+        ksk,
+        &mut TimingObserver,
+    )
+    .unwrap()
+}
+
+/// Like `verified_pbs`, and passes the accumulator and the progress after every step to
+/// `observer`, which can cancel the proof with a `PbsCancelled` error.
+pub fn verified_pbs_with_observer<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+    const n: usize,
+    const N: usize,
+    const K: usize,
+    const ELL: usize,
+    const LOGB: usize,
+    const STEPS_PER_PROOF: usize,
+>(
+    ct: &[F],
+    testv: &Poly<F, D, N>,
+    bsk: &[Ggsw<F, D, N, K, ELL>],
+    ksk: &Ggsw<F, D, N, K, ELL>,
+    observer: &mut impl PbsObserver<F, D, N, K>,
+) -> Result<(
+    Glwe<F, D, N, K>,
+    ProofWithPublicInputs<F, C, D>,
+    CircuitData<F, C, D>,
+)>
+where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>,
+    C: 'static,
+{
+    let prover = PbsProver::<F, C, D, n, N, K, ELL, LOGB, STEPS_PER_PROOF>::new();
+    let (acc_out, proof) = prover.prove_with_observer(ct, testv, bsk, ksk, observer)?;
+    Ok((acc_out, proof, prover.circuit_data))
+}
+
+/// The public inputs of the proofs of `PbsBatchProver`, in the order in which its circuit registers
+/// them. They continue with its verifier data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PbsBatchPublicInputs<F: RichField> {
+    /// The root of the key tree of all PBS of the batch, see `KeyCommitment`.
+    pub key_root: HashOut<F>,
+    /// The number of PBS in the batch.
+    pub batch_size: usize,
+    /// The chained hash of the initial accumulator, the output and the LWE hash of every PBS.
+    pub batch_hash: HashOut<F>,
+}
+
+impl<F: RichField> PbsBatchPublicInputs<F> {
+    /// The number of public inputs, without the verifier data.
+    pub fn num_elements() -> usize {
+        PbsBatchPublicInputsTarget::num_elements()
+    }
+
+    /// Parses the first `num_elements()` of `public_inputs`.
+    pub fn from_public_inputs(public_inputs: &[F]) -> Result<Self> {
+        ensure!(
+            public_inputs.len() >= Self::num_elements(),
+            "PBS batch proofs have at least {} public inputs.",
+            Self::num_elements()
+        );
+        let mut elements = public_inputs.iter().copied();
+        let mut next = |len: usize| elements.by_ref().take(len).collect_vec();
+        Ok(Self {
+            key_root: HashOut::from_vec(next(NUM_HASH_OUT_ELTS)),
+            batch_size: next(1)[0].to_canonical_u64() as usize,
+            batch_hash: HashOut::from_vec(next(NUM_HASH_OUT_ELTS)),
+        })
+    }
+
+    pub fn to_public_inputs(&self) -> Vec<F> {
+        self.key_root
+            .elements
+            .into_iter()
+            .chain(once(F::from_canonical_usize(self.batch_size)))
+            .chain(self.batch_hash.elements)
+            .collect()
+    }
+}
+
+// The targets of `PbsBatchPublicInputs`.
+struct PbsBatchPublicInputsTarget {
+    key_root: HashOutTarget,
+    batch_size: Target,
+    batch_hash: HashOutTarget,
+}
+
+impl PbsBatchPublicInputsTarget {
+    fn num_elements() -> usize {
+        1 + 2 * NUM_HASH_OUT_ELTS
+    }
+
+    // Parses the first `num_elements()` of `public_inputs`.
+    fn from_public_inputs(public_inputs: &[Target]) -> Self {
+        let mut targets = public_inputs[..Self::num_elements()].iter().copied();
+        let mut next = |len: usize| targets.by_ref().take(len).collect_vec();
+        Self {
+            key_root: HashOutTarget::from_vec(next(NUM_HASH_OUT_ELTS)),
+            batch_size: next(1)[0],
+            batch_hash: HashOutTarget::from_vec(next(NUM_HASH_OUT_ELTS)),
+        }
+    }
+
+    fn register<F: RichField + Extendable<D>, const D: usize>(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+    ) {
+        builder.register_public_inputs(&self.key_root.elements);
+        builder.register_public_input(self.batch_size);
+        builder.register_public_inputs(&self.batch_hash.elements);
+    }
+}
+
+/// Aggregates the proofs of a `PbsProver` for a batch of PBS with the same keys into one proof.
+/// Every step of its cyclic circuit verifies the proof of one complete PBS, and chains its initial
+/// accumulator, its output and its LWE hash into the batch hash, see `verify_pbs_batch`.
+pub struct PbsBatchProver<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+    const n: usize,
+    const N: usize,
+    const K: usize,
+    const ELL: usize,
+    const LOGB: usize,
+    const STEPS_PER_PROOF: usize,
+> {
+    pbs_prover: PbsProver<F, C, D, n, N, K, ELL, LOGB, STEPS_PER_PROOF>,
+    circuit_data: CircuitData<F, C, D>,
+    pbs_proof_with_pis: ProofWithPublicInputsTarget<D>,
+    condition: BoolTarget,
+    inner_cyclic_proof_with_pis: ProofWithPublicInputsTarget<D>,
+    verifier_data_target: VerifierCircuitTarget,
+}
+
+impl<
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+        const D: usize,
+        const n: usize,
+        const N: usize,
+        const K: usize,
+        const ELL: usize,
+        const LOGB: usize,
+        const STEPS_PER_PROOF: usize,
+    > PbsBatchProver<F, C, D, n, N, K, ELL, LOGB, STEPS_PER_PROOF>
+where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>,
+    C: 'static,
+{
+    /// Builds the cyclic PBS circuit and the cyclic aggregation circuit on top of it.
+    pub fn new() -> Self {
+        let pbs_prover = PbsProver::<F, C, D, n, N, K, ELL, LOGB, STEPS_PER_PROOF>::new();
+        let pbs_common = &pbs_prover.circuit_data.common;
+
+        let (
+            circuit_data,
+            (pbs_proof_with_pis, condition, inner_cyclic_proof_with_pis, verifier_data_target),
+        ) = cyclic_circuit_data::<F, C, D, _>(|mut common_data| {
+            let config = CircuitConfig::standard_recursion_config();
+            let mut builder = CircuitBuilder::<F, D>::new(config);
+            let one = builder.one();
+
+            // the PBS proof verifies with the PBS circuit, and so do all the steps it recursively
+            // verified, like `check_cyclic_proof_verifier_data` checks it outside the circuit
+            let pbs_proof_with_pis = builder.add_virtual_proof_with_pis(pbs_common);
+            let pbs_verifier_data =
+                builder.constant_verifier_data(&pbs_prover.circuit_data.verifier_only);
+            builder.verify_proof::<C>(&pbs_proof_with_pis, &pbs_verifier_data, pbs_common);
+            let pbs_pis = PbsPublicInputsTarget::<N, K>::from_public_inputs(
+                &pbs_proof_with_pis.public_inputs,
+            );
+            let pbs_cyclic_verifier_data =
+                pbs_verifier_data.circuit_digest.elements.into_iter().chain(
+                    pbs_verifier_data
+                        .constants_sigmas_cap
+                        .0
+                        .iter()
+                        .flat_map(|hash| hash.elements),
+                );
+            for (&pi, target) in pbs_proof_with_pis.public_inputs
+                [PbsPublicInputsTarget::<N, K>::num_elements()..]
+                .iter()
+                .zip_eq(pbs_cyclic_verifier_data)
+            {
+                builder.connect(pi, target);
+            }
+
+            // the PBS proof covers all steps from the first one
+            let n_target = builder.constant(F::from_canonical_usize(n + 2));
+            builder.connect(pbs_pis.counter, n_target);
+            builder.connect(pbs_pis.start_counter, one);
+            let zero_hash = builder.constant_hash(HashOut::ZERO);
+            builder.connect_hashes(zero_hash, pbs_pis.lwe_hash_init);
+
+            let key_root = builder.add_virtual_hash();
+            builder.connect_hashes(key_root, pbs_pis.key_root);
+
+            let batch_size = builder.add_virtual_target();
+            let batch_hash_in = builder.add_virtual_hash();
+            let batch_hash_out = builder.hash_n_to_hash_no_pad::<PoseidonHash>(
+                batch_hash_in
+                    .elements
+                    .into_iter()
+                    .chain(pbs_pis.acc_init.flatten())
+                    .chain(pbs_pis.latest_acc.flatten())
+                    .chain(pbs_pis.lwe_hash.elements)
+                    .collect(),
+            );
+            PbsBatchPublicInputsTarget {
+                key_root,
+                batch_size,
+                batch_hash: batch_hash_out,
+            }
+            .register(&mut builder);
+
+            let verifier_data_target = builder.add_verifier_data_public_inputs();
+            common_data.num_public_inputs = builder.num_public_inputs();
+
+            // Unpack inner proof's public inputs.
+            let inner_cyclic_proof_with_pis = builder.add_virtual_proof_with_pis(&common_data);
+            let inner_cyclic_pis = PbsBatchPublicInputsTarget::from_public_inputs(
+                &inner_cyclic_proof_with_pis.public_inputs,
+            );
+
+            // base case or not
+            let condition = builder.add_virtual_bool_target_safe();
+
+            // all PBS use the same key root
+            let actual_key_root = inner_cyclic_pis
+                .key_root
+                .elements
+                .into_iter()
+                .zip(key_root.elements)
+                .map(|(inner, current)| builder.select(condition, inner, current))
+                .collect_vec();
+            builder.connect_hashes(key_root, HashOutTarget::from_vec(actual_key_root));
+
+            let zero = builder.zero();
+            let actual_batch_hash_in = inner_cyclic_pis
+                .batch_hash
+                .elements
+                .map(|t| builder.select(condition, t, zero));
+            builder.connect_hashes(batch_hash_in, HashOutTarget::from(actual_batch_hash_in));
+
+            let new_batch_size =
+                builder.mul_add(condition.target, inner_cyclic_pis.batch_size, one);
+            builder.connect(batch_size, new_batch_size);
+
+            builder
+                .conditionally_verify_cyclic_proof_or_dummy::<C>(
+                    condition,
+                    &inner_cyclic_proof_with_pis,
+                    &common_data,
+                )
+                .unwrap();
+
+            (
+                builder,
+                (
+                    pbs_proof_with_pis,
+                    condition,
+                    inner_cyclic_proof_with_pis,
+                    verifier_data_target,
+                ),
+            )
+        });
+
+        Self {
+            pbs_prover,
+            circuit_data,
+            pbs_proof_with_pis,
+            condition,
+            inner_cyclic_proof_with_pis,
+            verifier_data_target,
+        }
+    }
+
+    /// The data `verify_pbs_batch` needs to verify the proofs, see `verifier_data_to_bytes`.
+    pub fn verifier_data(&self) -> VerifierCircuitData<F, C, D> {
+        self.circuit_data.verifier_data()
+    }
+
+    /// Proves the PBS of every `(ct, testv)` of `inputs` with `bsk` and `ksk`, and returns the
+    /// output GLWE ciphertexts together with one proof for all of them.
+    pub fn prove(
+        &self,
+        inputs: &[(Vec<F>, Poly<F, D, N>)],
+        bsk: &[Ggsw<F, D, N, K, ELL>],
+        ksk: &Ggsw<F, D, N, K, ELL>,
+    ) -> Result<(Vec<Glwe<F, D, N, K>>, ProofWithPublicInputs<F, C, D>)> {
+        ensure!(!inputs.is_empty(), "Batch must not be empty.");
+
+        let mut proof = cyclic_base_proof(
+            &self.circuit_data.common,
+            &self.circuit_data.verifier_only,
+            Default::default(),
+        );
+        let mut out_cts = Vec::with_capacity(inputs.len());
+
+        for (i, (ct, testv)) in inputs.iter().enumerate() {
+            let (out_ct, pbs_proof) = self.pbs_prover.prove(ct, testv, bsk, ksk)?;
+
+            let mut pw = PartialWitness::new();
+            pw.set_bool_target(self.condition, i > 0);
+            pw.set_proof_with_pis_target(&self.pbs_proof_with_pis, &pbs_proof);
+            pw.set_proof_with_pis_target(&self.inner_cyclic_proof_with_pis, &proof);
+            pw.set_verifier_data_target(
+                &self.verifier_data_target,
+                &self.circuit_data.verifier_only,
+            );
+
+            let mut timing = TimingTree::new(&format!("aggregate PBS {i}"), Level::Info);
+            proof = prove::<F, C, D>(
+                &self.circuit_data.prover_only,
+                &self.circuit_data.common,
+                pw,
+                &mut timing,
+            )?;
+            timing.print();
+            out_cts.push(out_ct);
+        }
+
+        Ok((out_cts, proof))
+    }
+}
+
+// A circuit proving that the proofs of two neighbouring ranges of steps of a PBS chain up: the
+// accumulator, the counter and the LWE hash after the left range are the ones before the right
+// range, and both ranges use the same key root. Its proofs have the public inputs of the PBS
+// circuit without the verifier data, so they can be merged again.
+struct PbsMergeCircuit<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize> {
+    circuit_data: CircuitData<F, C, D>,
+    left_proof_with_pis: ProofWithPublicInputsTarget<D>,
+    right_proof_with_pis: ProofWithPublicInputsTarget<D>,
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+    PbsMergeCircuit<F, C, D>
+where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>,
+    C: 'static,
+{
+    // Builds the circuit merging two proofs of `child`, which is the cyclic PBS circuit if
+    // `child_is_cyclic` and a merge circuit otherwise.
+    fn new<const N: usize, const K: usize>(
+        child: &CircuitData<F, C, D>,
+        child_is_cyclic: bool,
+    ) -> Self {
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let one = builder.one();
+
+        let child_verifier_data = builder.constant_verifier_data(&child.verifier_only);
+        let left_proof_with_pis = builder.add_virtual_proof_with_pis(&child.common);
+        let right_proof_with_pis = builder.add_virtual_proof_with_pis(&child.common);
+        for proof_with_pis in [&left_proof_with_pis, &right_proof_with_pis] {
+            builder.verify_proof::<C>(proof_with_pis, &child_verifier_data, &child.common);
+
+            // like `check_cyclic_proof_verifier_data` outside the circuit
+            if child_is_cyclic {
+                let cyclic_verifier_data = child_verifier_data
+                    .circuit_digest
+                    .elements
+                    .into_iter()
+                    .chain(
+                        child_verifier_data
+                            .constants_sigmas_cap
+                            .0
+                            .iter()
+                            .flat_map(|hash| hash.elements),
+                    );
+                for (&pi, target) in proof_with_pis.public_inputs
+                    [PbsPublicInputsTarget::<N, K>::num_elements()..]
+                    .iter()
+                    .zip_eq(cyclic_verifier_data)
+                {
+                    builder.connect(pi, target);
+                }
+            }
+        }
+        let left_pis =
+            PbsPublicInputsTarget::<N, K>::from_public_inputs(&left_proof_with_pis.public_inputs);
+        let right_pis =
+            PbsPublicInputsTarget::<N, K>::from_public_inputs(&right_proof_with_pis.public_inputs);
+
+        // the right range continues the left one
+        for (left, right) in left_pis
+            .latest_acc
+            .flatten()
+            .into_iter()
+            .zip(right_pis.acc_init.flatten())
+        {
+            builder.connect(left, right);
+        }
+        let next_counter = builder.add(left_pis.counter, one);
+        builder.connect(next_counter, right_pis.start_counter);
+        builder.connect_hashes(left_pis.lwe_hash, right_pis.lwe_hash_init);
+        builder.connect_hashes(left_pis.key_root, right_pis.key_root);
+
+        PbsPublicInputsTarget {
+            acc_init: left_pis.acc_init,
+            counter: right_pis.counter,
+            latest_acc: right_pis.latest_acc,
+            key_root: left_pis.key_root,
+            lwe_hash: right_pis.lwe_hash,
+            start_counter: left_pis.start_counter,
+            lwe_hash_init: left_pis.lwe_hash_init,
+        }
+        .register(&mut builder);
+
+        Self {
+            circuit_data: builder.build::<C>(),
+            left_proof_with_pis,
+            right_proof_with_pis,
+        }
+    }
+
+    fn prove(
+        &self,
+        left: &ProofWithPublicInputs<F, C, D>,
+        right: &ProofWithPublicInputs<F, C, D>,
+        name: &str,
+    ) -> Result<ProofWithPublicInputs<F, C, D>> {
+        let mut pw = PartialWitness::new();
+        pw.set_proof_with_pis_target(&self.left_proof_with_pis, left);
+        pw.set_proof_with_pis_target(&self.right_proof_with_pis, right);
+
+        let mut timing = TimingTree::new(name, Level::Info);
+        let proof = prove::<F, C, D>(
+            &self.circuit_data.prover_only,
+            &self.circuit_data.common,
+            pw,
+            &mut timing,
+        )?;
+        timing.print();
+        Ok(proof)
+    }
+}
+
+/// Proves a PBS as `2^log_num_ranges` ranges of steps in parallel, each with the cyclic PBS
+/// circuit, and merges their proofs with a binary tree of merge circuits, one per level. The
+/// accumulator before every range is computed natively up front. Proofs are verified with
+/// `verify_pbs_tree`.
+pub struct PbsTreeProver<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+    const n: usize,
+    const N: usize,
+    const K: usize,
+    const ELL: usize,
+    const LOGB: usize,
+    const STEPS_PER_PROOF: usize,
+> {
+    pbs_prover: PbsProver<F, C, D, n, N, K, ELL, LOGB, STEPS_PER_PROOF>,
+    merge_circuits: Vec<PbsMergeCircuit<F, C, D>>,
+}
+
+impl<
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+        const D: usize,
+        const n: usize,
+        const N: usize,
+        const K: usize,
+        const ELL: usize,
+        const LOGB: usize,
+        const STEPS_PER_PROOF: usize,
+    > PbsTreeProver<F, C, D, n, N, K, ELL, LOGB, STEPS_PER_PROOF>
+where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>,
+    C: 'static,
+{
+    /// Builds the cyclic PBS circuit and the `log_num_ranges` levels of merge circuits.
+    pub fn new(log_num_ranges: usize) -> Self {
+        let num_proofs = (n + 2).div_ceil(STEPS_PER_PROOF);
+        assert!(
+            log_num_ranges > 0 && 1 << log_num_ranges <= num_proofs,
+            "Number of ranges must be in 2..={num_proofs}."
+        );
+
+        let pbs_prover = PbsProver::<F, C, D, n, N, K, ELL, LOGB, STEPS_PER_PROOF>::new();
+        let mut merge_circuits: Vec<PbsMergeCircuit<F, C, D>> = Vec::new();
+        for _ in 0..log_num_ranges {
+            let merge_circuit = match merge_circuits.last() {
+                None => PbsMergeCircuit::new::<N, K>(&pbs_prover.circuit_data, true),
+                Some(child) => PbsMergeCircuit::new::<N, K>(&child.circuit_data, false),
+            };
+            merge_circuits.push(merge_circuit);
+        }
+
+        Self {
+            pbs_prover,
+            merge_circuits,
+        }
+    }
+
+    /// The prover of the ranges of steps.
+    pub fn pbs_prover(&self) -> &PbsProver<F, C, D, n, N, K, ELL, LOGB, STEPS_PER_PROOF> {
+        &self.pbs_prover
+    }
+
+    /// The data `verify_pbs_tree` needs to verify the proofs, that is the one of the merge circuit
+    /// at the root of the tree.
+    pub fn verifier_data(&self) -> VerifierCircuitData<F, C, D> {
+        self.merge_circuits
+            .last()
+            .unwrap()
+            .circuit_data
+            .verifier_data()
+    }
+
+    /// Proves the PBS of `ct` with `testv`, `bsk` and `ksk`, and returns the output GLWE
+    /// ciphertext together with the proof at the root of the tree.
+    pub fn prove(
+        &self,
+        ct: &[F],
+        testv: &Poly<F, D, N>,
+        bsk: &[Ggsw<F, D, N, K, ELL>],
+        ksk: &Ggsw<F, D, N, K, ELL>,
+    ) -> Result<(Glwe<F, D, N, K>, ProofWithPublicInputs<F, C, D>)> {
+        ensure!(ct.len() == n + 1, "LWE ciphertext does not match n={n}.");
+        ensure!(bsk.len() == n, "BSK does not match n={n}.");
+
+        let key_tree = key_tree(bsk, ksk);
+        let dummy_ggsw = Ggsw::dummy_ct();
+        let steps = pbs_steps(ct, bsk, ksk, &dummy_ggsw);
+
+        // the accumulator and the LWE hash before every step
+        let mut accs = vec![Glwe::from_slice(&initial_acc::<F, D, N, K>(testv))];
+        let mut lwe_hashes = vec![HashOut::ZERO];
+        for (i, &(ggsw, mask_element)) in steps.iter().enumerate() {
+            accs.push(native_step::<F, D, n, N, K, ELL, LOGB>(
+                &accs[i],
+                ggsw,
+                mask_element,
+                i,
+            ));
+            lwe_hashes.push(PoseidonHash::hash_no_pad(
+                &lwe_hashes[i]
+                    .elements
+                    .into_iter()
+                    .chain(once(mask_element))
+                    .collect_vec(),
+            ));
+        }
+
+        // every range but the last one is proved by full proofs
+        let num_ranges = 1 << self.merge_circuits.len();
+        let num_proofs = steps.len().div_ceil(STEPS_PER_PROOF);
+        let range_bounds = (0..=num_ranges)
+            .map(|j| (j * num_proofs / num_ranges * STEPS_PER_PROOF).min(steps.len()))
+            .collect_vec();
+        let (key_tree, steps, accs, lwe_hashes) = (&key_tree, &steps, &accs, &lwe_hashes);
+        let mut proofs = thread::scope(|scope| {
+            range_bounds
+                .iter()
+                .tuple_windows()
+                .map(|(&start, &end)| {
+                    scope.spawn(move || {
+                        self.pbs_prover.prove_range(
+                            key_tree,
+                            steps,
+                            start..end,
+                            &accs[start],
+                            lwe_hashes[start],
+                            &mut (),
+                        )
+                    })
+                })
+                .collect_vec()
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<Result<Vec<_>>>()
+        })?;
+
+        for (level, merge_circuit) in self.merge_circuits.iter().enumerate() {
+            proofs = thread::scope(|scope| {
+                proofs
+                    .chunks(2)
+                    .enumerate()
+                    .map(|(j, pair)| {
+                        scope.spawn(move || {
+                            merge_circuit.prove(&pair[0], &pair[1], &format!("merge {level}.{j}"))
+                        })
+                    })
+                    .collect_vec()
+                    .into_iter()
+                    .map(|handle| handle.join().unwrap())
+                    .collect::<Result<Vec<_>>>()
+            })?;
+        }
+
+        let proof = proofs.pop().unwrap();
+        Ok((
+            PbsProver::<F, C, D, n, N, K, ELL, LOGB, STEPS_PER_PROOF>::latest_acc(&proof),
+            proof,
+        ))
+    }
+}
+
+/// Serializes the data to verify PBS proofs, that is the `VerifierOnlyCircuitData` and the
+/// `CommonCircuitData` of the cyclic circuit, with plonky2's `DefaultGateSerializer`.
+pub fn verifier_data_to_bytes<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    vd: &VerifierCircuitData<F, C, D>,
+) -> Result<Vec<u8>> {
+    vd.to_bytes(&DefaultGateSerializer)
+        .map_err(anyhow::Error::msg)
+}
+
+/// Reads the data to verify PBS proofs written by `verifier_data_to_bytes`.
+pub fn verifier_data_from_bytes<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    bytes: Vec<u8>,
+) -> Result<VerifierCircuitData<F, C, D>> {
+    VerifierCircuitData::from_bytes(bytes, &DefaultGateSerializer).map_err(anyhow::Error::msg)
+}
+
+/// Reads a PBS proof written by `ProofWithPublicInputs::to_bytes`. Only the `CommonCircuitData`
+/// of the verifier data is needed to parse it.
+pub fn proof_from_bytes<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    bytes: Vec<u8>,
+    vd: &VerifierCircuitData<F, C, D>,
+) -> Result<ProofWithPublicInputs<F, C, D>> {
+    ProofWithPublicInputs::from_bytes(bytes, &vd.common)
+}
+
+/// The check of `verify_pbs` that rejected a PBS proof.
+#[derive(Debug)]
+pub enum PbsVerificationError {
+    /// The proof does not start from the claimed test vector.
+    WrongTestVector,
+    /// The proof does not chain the n + 2 steps of a PBS.
+    WrongStepCount,
+    /// The output of the proof is not the claimed output ciphertext.
+    OutputMismatch,
+    /// The proof does not verify.
+    InvalidProof(anyhow::Error),
+    /// The proof was not recursively verified with the verifier data of the cyclic circuit.
+    VerifierDataMismatch(anyhow::Error),
+    /// The proof does not open the GGSWs against the root of the key commitment.
+    KeyRootMismatch,
+    /// The proof does not use the claimed LWE ciphertext.
+    LweHashMismatch,
+    /// The batch proof does not aggregate as many PBS as claimed.
+    WrongBatchSize,
+    /// The batch proof does not aggregate the claimed inputs and outputs.
+    BatchHashMismatch,
+}
+
+impl Display for PbsVerificationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WrongTestVector => write!(f, "wrong test vector"),
+            Self::WrongStepCount => write!(f, "wrong step count"),
+            Self::OutputMismatch => write!(f, "output ciphertext mismatch"),
+            Self::InvalidProof(e) => write!(f, "invalid proof: {e}"),
+            Self::VerifierDataMismatch(e) => write!(f, "recursion verifier data mismatch: {e}"),
+            Self::KeyRootMismatch => write!(f, "key root mismatch"),
+            Self::LweHashMismatch => write!(f, "LWE hash mismatch"),
+            Self::WrongBatchSize => write!(f, "wrong batch size"),
+            Self::BatchHashMismatch => write!(f, "batch hash mismatch"),
+        }
+    }
+}
+
+impl std::error::Error for PbsVerificationError {}
+
+/// Verifies that `proof` proves the PBS of `ct` with `testv` and the keys of `key_commitment` to
+/// `out_ct`, and returns the first check which failed otherwise.
+pub fn verify_pbs<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+    const n: usize,
+    const N: usize,
+    const K: usize,
+    const ELL: usize,
+    const LOGB: usize,
+>(
+    out_ct: &Glwe<F, D, N, K>,
+    ct: &[F],
+    testv: &Poly<F, D, N>,
+    key_commitment: &KeyCommitment<F>,
+    proof: &ProofWithPublicInputs<F, C, D>,
+    vd: &VerifierCircuitData<F, C, D>,
+) -> Result<(), PbsVerificationError>
+where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>,
+    C: 'static,
+{
+    // the proof is verified first, which also checks the number of public inputs
+    let mut timing = TimingTree::new("verify", Level::Info);
+    timed!(
+        timing,
+        "verifying Step 1",
+        vd.verify(proof.clone())
+            .map_err(PbsVerificationError::InvalidProof)?
+    );
+    timed!(
+        timing,
+        "verifying Step 2",
+        check_cyclic_proof_verifier_data(proof, &vd.verifier_only, &vd.common)
+            .map_err(PbsVerificationError::VerifierDataMismatch)?
+    );
+    let pis = check_pbs_claims::<F, D, n, N, K>(
+        &proof.public_inputs,
+        out_ct,
+        ct,
+        testv,
+        key_commitment,
+        &mut timing,
+    )?;
+    timing.print();
+
+    info!("number of steps: {}", pis.counter);
+
+    info!("proof size: {} bytes", proof.to_bytes().len());
+
+    Ok(())
+}
+
+// Checks the public inputs of a verified proof of the PBS circuit or of a merge circuit of
+// `PbsTreeProver` against the claimed PBS, and returns them.
+fn check_pbs_claims<
+    F: RichField + Extendable<D>,
+    const D: usize,
+    const n: usize,
+    const N: usize,
+    const K: usize,
+>(
+    public_inputs: &[F],
+    out_ct: &Glwe<F, D, N, K>,
+    ct: &[F],
+    testv: &Poly<F, D, N>,
+    key_commitment: &KeyCommitment<F>,
+    timing: &mut TimingTree,
+) -> Result<PbsPublicInputs<F, D, N, K>, PbsVerificationError> {
+    let pis = PbsPublicInputs::<F, D, N, K>::from_public_inputs(public_inputs)
+        .map_err(PbsVerificationError::InvalidProof)?;
+
+    if pis.acc_init.flatten() != initial_acc::<F, D, N, K>(testv) {
+        return Err(PbsVerificationError::WrongTestVector);
+    }
+
+    // the proof covers all steps from the first one
+    if pis.start_counter != 1 || pis.counter != n + 2 {
+        return Err(PbsVerificationError::WrongStepCount);
+    }
+
+    if *out_ct != pis.latest_acc {
+        return Err(PbsVerificationError::OutputMismatch);
+    }
+
+    // a ciphertext of the wrong length cannot match the hash
+    if ct.len() != n + 1 {
+        return Err(PbsVerificationError::LweHashMismatch);
+    }
+
+    if pis.key_root != key_commitment.root {
+        return Err(PbsVerificationError::KeyRootMismatch);
+    }
+
+    if pis.lwe_hash_init != HashOut::ZERO {
+        return Err(PbsVerificationError::LweHashMismatch);
+    }
+    timed!(
+        timing,
+        "verifying Step 3",
+        verify_hash_output(&lwe_hash_data(ct), pis.lwe_hash)
+            .map_err(|_| PbsVerificationError::LweHashMismatch)?
+    );
+
+    Ok(pis)
+}
+
+/// Verifies that `proof` proves the PBS of every `(ct, testv)` of `inputs` with the keys of
+/// `key_commitment` to the output at the same index of `out_cts`, and returns the first check which
+/// failed otherwise.
+pub fn verify_pbs_batch<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+    const n: usize,
+    const N: usize,
+    const K: usize,
+    const ELL: usize,
+    const LOGB: usize,
+>(
+    out_cts: &[Glwe<F, D, N, K>],
+    inputs: &[(Vec<F>, Poly<F, D, N>)],
+    key_commitment: &KeyCommitment<F>,
+    proof: &ProofWithPublicInputs<F, C, D>,
+    vd: &VerifierCircuitData<F, C, D>,
+) -> Result<(), PbsVerificationError>
+where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>,
+    C: 'static,
+{
+    let mut timing = TimingTree::new("verify batch", Level::Info);
+    timed!(
+        timing,
+        "verifying batch proof",
+        vd.verify(proof.clone())
+            .map_err(PbsVerificationError::InvalidProof)?
+    );
+    timed!(
+        timing,
+        "verifying batch verifier data",
+        check_cyclic_proof_verifier_data(proof, &vd.verifier_only, &vd.common)
+            .map_err(PbsVerificationError::VerifierDataMismatch)?
+    );
+
+    let pis = PbsBatchPublicInputs::from_public_inputs(&proof.public_inputs)
+        .map_err(PbsVerificationError::InvalidProof)?;
+
+    if out_cts.len() != inputs.len() || pis.batch_size != inputs.len() {
+        return Err(PbsVerificationError::WrongBatchSize);
+    }
+
+    if pis.key_root != key_commitment.root {
+        return Err(PbsVerificationError::KeyRootMismatch);
+    }
+
+    // a ciphertext of the wrong length cannot match the hash
+    if inputs.iter().any(|(ct, _)| ct.len() != n + 1) {
+        return Err(PbsVerificationError::LweHashMismatch);
+    }
+
+    let batch_data = timed!(
+        timing,
+        "hashing batch",
+        inputs
+            .iter()
+            .zip(out_cts)
+            .map(|((ct, testv), out_ct)| {
+                initial_acc::<F, D, N, K>(testv)
+                    .into_iter()
+                    .chain(out_ct.flatten())
+                    .chain(chained_hash(&lwe_hash_data(ct)).elements)
+                    .collect_vec()
+            })
+            .collect_vec()
+    );
+    if chained_hash(&batch_data) != pis.batch_hash {
+        return Err(PbsVerificationError::BatchHashMismatch);
+    }
+    timing.print();
+
+    info!("batch size: {}", inputs.len());
+
+    info!("proof size: {} bytes", proof.to_bytes().len());
+
+    Ok(())
+}
+
+/// Like `verify_pbs` for a proof of `PbsTreeProver`, whose merge circuits already checked the
+/// verifier data of the ranges.
+pub fn verify_pbs_tree<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+    const n: usize,
+    const N: usize,
+    const K: usize,
+    const ELL: usize,
+    const LOGB: usize,
+>(
+    out_ct: &Glwe<F, D, N, K>,
+    ct: &[F],
+    testv: &Poly<F, D, N>,
+    key_commitment: &KeyCommitment<F>,
+    proof: &ProofWithPublicInputs<F, C, D>,
+    vd: &VerifierCircuitData<F, C, D>,
+) -> Result<(), PbsVerificationError>
+where
+    <C as GenericConfig<D>>::Hasher: AlgebraicHasher<F>,
+    C: 'static,
+{
+    let mut timing = TimingTree::new("verify tree", Level::Info);
+    timed!(
+        timing,
+        "verifying tree proof",
+        vd.verify(proof.clone())
+            .map_err(PbsVerificationError::InvalidProof)?
+    );
+    check_pbs_claims::<F, D, n, N, K>(
+        &proof.public_inputs,
+        out_ct,
+        ct,
+        testv,
+        key_commitment,
+        &mut timing,
+    )?;
+    timing.print();
+
+    info!("proof size: {} bytes", proof.to_bytes().len());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vtfhe::crypto::glwe::Glwe;
+    use crate::vtfhe::crypto::lwe::encrypt;
+    use crate::vtfhe::crypto::{pbs_fixture, PbsFixture};
+    use crate::vtfhe::noise::NoiseObserver;
 
     use itertools::Itertools;
-    use plonky2::field::types::Field;
+    use plonky2::field::types::{Field, PrimeField64};
+    use plonky2::hash::merkle_proofs::verify_merkle_proof;
     use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
-    use plonky2::util::log2_ceil;
     use rand::random;
 
-    fn check_rotation<F: RichField + Extendable<D>, const D: usize, const N: usize>(
-        in_poly: &Poly<F, D, N>,
-        out_poly: &Poly<F, D, N>,
-        mask_element: &F,
-    ) {
-        let mut shift = mask_element.to_canonical_u64() as usize;
-        shift >>= F::BITS - log2_ceil(N) - 2;
-        let carry = shift % 2;
-        shift >>= 1;
-        shift += carry;
-
-        let mut poly = in_poly;
-        let neg_poly = &in_poly.scalar_mul(&(-F::ONE));
-        if shift > N {
-            shift = shift % N;
-            poly = neg_poly;
-        }
-
-        for i in 0..N - shift {
-            assert_eq!(
-                poly.coeffs[i],
-                out_poly.coeffs[i + shift],
-                "poly not rotated correctly."
-            )
-        }
-        for i in 0..shift {
-            assert_eq!(
-                poly.coeffs[N - shift + i],
-                -out_poly.coeffs[i],
-                "poly not rotated correctly."
-            )
-        }
-    }
+    const N: usize = 8;
 
     #[test]
     fn test_ivc_blind_rot() {
@@ -560,26 +2208,35 @@ mod tests {
         type C = PoseidonGoldilocksConfig;
         type F = <C as GenericConfig<D>>::F;
 
-        let s_to = Glwe::<F, D, N, K>::partial_key(n);
-        let s_lwe = Glwe::<F, D, N, K>::flatten_partial_key(&s_to, n);
-        println!("s_lwe: {:?}", s_lwe);
-        let s_glwe = Glwe::<F, D, N, K>::key_gen();
-        let bsk = compute_bsk::<F, D, N, K, ELL, LOGB>(&s_lwe, &s_glwe, 0f64);
-
-        let ksk = Ggsw::<F, D, N, K, ELL>::compute_ksk::<LOGB>(&s_to, &s_glwe, 0f64);
+        let PbsFixture {
+            s_to,
+            s_lwe,
+            s_glwe,
+            bsk,
+            ksk,
+            testv,
+            m,
+            ct,
+            ..
+        } = pbs_fixture::<F, D, n, N, K, ELL, LOGB>();
+        let prover = PbsProver::<F, C, D, n, N, K, ELL, LOGB, 1>::new();
+        let mut noise_observer = NoiseObserver::new(&ct, &testv, &s_glwe, &s_lwe, &s_to);
+        let (out_ct, proof) = prover
+            .prove_with_observer(&ct, &testv, &bsk, &ksk, &mut noise_observer)
+            .unwrap();
 
-        let testv = Poly::<F, D, N> {
-            coeffs: from_fn(|i| F::from_canonical_usize(i)),
-        };
-        println!("testv: {:?}", testv);
-        let delta = F::from_noncanonical_biguint(F::order() >> log2_ceil(2 * N));
-        let m = F::from_canonical_u64(random::<u64>() % (N as u64));
-        println!("message: {delta} * {m} = {}", delta * m);
-        let ct = encrypt::<F, D, n>(&s_lwe, &(delta * m), 0f64);
-        println!("{:?}", ct);
-        let (out_ct, proof, cd) = verified_pbs::<F, C, D, n, N, K, ELL, LOGB>(
-            &ct, &testv, &bsk, &ksk, &s_glwe, &s_lwe, &s_to,
-        );
+        let report = noise_observer.report();
+        assert_eq!(report.steps.len(), n + 2);
+        assert_eq!(report.steps[0].step, PbsStep::BodyRotation);
+        assert_eq!(report.steps[n + 1].step, PbsStep::KeySwitch);
+        // the keys are noiseless, so every step stays within the decryption margin 1/(4N)
+        for step_noise in &report.steps {
+            assert!(step_noise.avg_error <= step_noise.max_error);
+            assert!(step_noise.max_error < 1.0 / (4 * N) as f64);
+        }
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["steps"].as_array().unwrap().len(), n + 2);
+        assert_eq!(json["steps"][n + 1]["step"], "key_switch");
 
         let expt_out_ct = GlweCtNative::from_glwe(&out_ct);
 
@@ -590,7 +2247,7 @@ mod tests {
                 .collect_vec();
             let mut current_acc_in = GlweCtNative::new_from_slice(&coeffs);
             let dummy_ggsw_ct = GgswCtNative::<F, D, N, K, ELL>::dummy_ct();
-            (current_acc_in, _) = generate_build_circuit_input::<F, D, n, N, K, ELL, LOGB>(
+            (current_acc_in, _, _) = generate_build_circuit_input::<F, D, n, N, K, ELL, LOGB>(
                 &current_acc_in,
                 &dummy_ggsw_ct,
                 ct[n],
@@ -601,7 +2258,7 @@ mod tests {
                 let counter = F::from_canonical_usize(x + 2);
 
                 let ggsw_ct = GgswCtNative::from_ggsw(&bsk[x]);
-                (current_acc_in, _) = generate_build_circuit_input::<F, D, n, N, K, ELL, LOGB>(
+                (current_acc_in, _, _) = generate_build_circuit_input::<F, D, n, N, K, ELL, LOGB>(
                     &current_acc_in,
                     &ggsw_ct,
                     ct[x],
@@ -612,7 +2269,7 @@ mod tests {
             let ksk_native = GgswCtNative::from_ggsw(&ksk);
 
             let counter = F::from_canonical_usize(n + 2);
-            (current_acc_in, _) = generate_build_circuit_input::<F, D, n, N, K, ELL, LOGB>(
+            (current_acc_in, _, _) = generate_build_circuit_input::<F, D, n, N, K, ELL, LOGB>(
                 &current_acc_in,
                 &ksk_native,
                 F::ZERO,
@@ -621,12 +2278,695 @@ mod tests {
 
             assert_eq!(expt_out_ct, current_acc_in);
         }
-        verify_pbs::<F, C, D, n, N, K, ELL, LOGB>(&out_ct, &ct, &testv, &bsk, &ksk, &proof, &cd);
+        verify_pbs::<F, C, D, n, N, K, ELL, LOGB>(
+            &out_ct,
+            &ct,
+            &testv,
+            &KeyCommitment::new(&bsk, &ksk),
+            &proof,
+            &prover.verifier_data(),
+        )
+        .unwrap();
         let m_out = out_ct.decrypt(&s_to);
         println!("output ct: {:?}", out_ct);
         println!("output poly: {:?}", m_out);
         println!("in: {m} out: {}", m_out.coeffs[0]);
 
-        check_rotation(&testv, &m_out, &(-delta * m));
+        // the keys are noiseless, so the output decrypts to the test vector rotated by the
+        // mod switched LWE ciphertext
+        assert_eq!(
+            m_out
+                .coeffs
+                .iter()
+                .map(|c| c.to_canonical_u64())
+                .collect_vec(),
+            report.steps[n + 1].expected_testv
+        );
+    }
+
+    #[test]
+    fn test_cyclic_circuit_data() {
+        const LOGB: usize = 8;
+        const ELL: usize = 8;
+        const K: usize = 4;
+        const D: usize = 2;
+        const n: usize = 4;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        // the cyclic circuit is its own common data for other N than the one of the other tests
+        let prover = PbsProver::<F, C, D, n, 16, K, ELL, LOGB, 1>::new();
+        let common = &prover.circuit_data().common;
+        let mut common_data = common_data_for_recursion::<F, C, D>(common.degree_bits());
+        common_data.num_public_inputs = common.num_public_inputs;
+        assert_eq!(&common_data, common);
+    }
+
+    #[test]
+    fn test_key_tree() {
+        const LOGB: usize = 8;
+        const ELL: usize = 8;
+        const K: usize = 4;
+        const D: usize = 2;
+        const n: usize = 4;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let PbsFixture { bsk, ksk, .. } = pbs_fixture::<F, D, n, N, K, ELL, LOGB>();
+
+        let tree = key_tree(&bsk, &ksk);
+        let root = KeyCommitment::new(&bsk, &ksk).root;
+        let ggsws = once(Ggsw::<F, D, N, K, ELL>::dummy_ct())
+            .chain(bsk)
+            .chain(once(ksk))
+            .collect_vec();
+
+        // the GGSW of every step opens at its own leaf only
+        for (i, ggsw) in ggsws.iter().enumerate() {
+            let proof = tree.prove(i);
+            assert_eq!(proof.siblings.len(), key_tree_height(n));
+            verify_merkle_proof(ggsw.flatten(), i, root, &proof).unwrap();
+            assert!(verify_merkle_proof(ggsw.flatten(), (i + 1) % (n + 2), root, &proof).is_err());
+        }
+    }
+
+    #[test]
+    fn test_pbs_prover() {
+        const LOGB: usize = 8;
+        const ELL: usize = 8;
+        const K: usize = 4;
+        const D: usize = 2;
+        const n: usize = 4;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let PbsFixture {
+            s_lwe,
+            bsk,
+            ksk,
+            testv,
+            delta,
+            ..
+        } = pbs_fixture::<F, D, n, N, K, ELL, LOGB>();
+
+        // the circuit is built once and proves PBS of several ciphertexts
+        let prover = PbsProver::<F, C, D, n, N, K, ELL, LOGB, 1>::new();
+        let vd_bytes = verifier_data_to_bytes(&prover.verifier_data()).unwrap();
+        let vd = verifier_data_from_bytes::<F, C, D>(vd_bytes).unwrap();
+        assert_eq!(vd, prover.verifier_data());
+        let key_commitment = KeyCommitment::new(&bsk, &ksk);
+        let key_commitment_bytes = key_commitment.to_bytes();
+        assert_eq!(
+            KeyCommitment::from_bytes(&key_commitment_bytes).unwrap(),
+            key_commitment
+        );
+        assert!(KeyCommitment::<F>::from_bytes(&key_commitment_bytes[1..]).is_err());
+
+        for m in [F::ZERO, F::ONE] {
+            let ct = encrypt::<F, D, n>(&s_lwe, &(delta * m), 0f64);
+            let (out_ct, proof) = prover.prove(&ct, &testv, &bsk, &ksk).unwrap();
+
+            // the verifier only gets the bytes of the proof and of the verifier data
+            let proof = proof_from_bytes(proof.to_bytes(), &vd).unwrap();
+            verify_pbs::<F, C, D, n, N, K, ELL, LOGB>(
+                &out_ct,
+                &ct,
+                &testv,
+                &key_commitment,
+                &proof,
+                &vd,
+            )
+            .unwrap();
+
+            // the typed public inputs are the claims of the proof
+            let pis =
+                PbsPublicInputs::<F, D, N, K>::from_public_inputs(&proof.public_inputs).unwrap();
+            assert_eq!(pis.acc_init.flatten(), initial_acc::<F, D, N, K>(&testv));
+            assert_eq!((pis.start_counter, pis.counter), (1, n + 2));
+            assert_eq!(pis.latest_acc, out_ct);
+            assert_eq!(pis.key_root, key_commitment.root);
+            assert_eq!(pis.lwe_hash, chained_hash(&lwe_hash_data(&ct)));
+            assert_eq!(pis.lwe_hash_init, HashOut::ZERO);
+            let num_elements = PbsPublicInputs::<F, D, N, K>::num_elements();
+            assert_eq!(pis.to_public_inputs(), proof.public_inputs[..num_elements]);
+            assert!(PbsPublicInputs::<F, D, N, K>::from_public_inputs(
+                &proof.public_inputs[..num_elements - 1]
+            )
+            .is_err());
+
+            // wrong claims are rejected
+            let other_testv = testv.left_shift(1);
+            assert!(matches!(
+                verify_pbs::<F, C, D, n, N, K, ELL, LOGB>(
+                    &out_ct,
+                    &ct,
+                    &other_testv,
+                    &key_commitment,
+                    &proof,
+                    &vd,
+                ),
+                Err(PbsVerificationError::WrongTestVector)
+            ));
+            let other_out_ct =
+                Glwe::from_slice(&out_ct.flatten().iter().map(|x| *x + F::ONE).collect_vec());
+            assert!(matches!(
+                verify_pbs::<F, C, D, n, N, K, ELL, LOGB>(
+                    &other_out_ct,
+                    &ct,
+                    &testv,
+                    &key_commitment,
+                    &proof,
+                    &vd,
+                ),
+                Err(PbsVerificationError::OutputMismatch)
+            ));
+            assert!(matches!(
+                verify_pbs::<F, C, D, n, N, K, ELL, LOGB>(
+                    &out_ct,
+                    &ct,
+                    &testv,
+                    &KeyCommitment::new(&bsk, &bsk[0]),
+                    &proof,
+                    &vd,
+                ),
+                Err(PbsVerificationError::KeyRootMismatch)
+            ));
+            let mut other_ct = ct.clone();
+            other_ct[0] += F::ONE;
+            assert!(matches!(
+                verify_pbs::<F, C, D, n, N, K, ELL, LOGB>(
+                    &out_ct,
+                    &other_ct,
+                    &testv,
+                    &key_commitment,
+                    &proof,
+                    &vd,
+                ),
+                Err(PbsVerificationError::LweHashMismatch)
+            ));
+
+            // so is a proof with tampered public inputs
+            let mut other_proof = proof.clone();
+            other_proof.public_inputs[0] += F::ONE;
+            assert!(matches!(
+                verify_pbs::<F, C, D, n, N, K, ELL, LOGB>(
+                    &out_ct,
+                    &ct,
+                    &testv,
+                    &key_commitment,
+                    &other_proof,
+                    &vd,
+                ),
+                Err(PbsVerificationError::InvalidProof(_))
+            ));
+        }
+
+        // a ciphertext of the wrong LWE dimension is rejected
+        let ct = encrypt::<F, D, n>(&s_lwe, &delta, 0f64);
+        assert!(prover.prove(&ct[1..], &testv, &bsk, &ksk).is_err());
+    }
+
+    #[test]
+    fn test_pbs_batch_prover() {
+        const LOGB: usize = 8;
+        const ELL: usize = 8;
+        const K: usize = 4;
+        const D: usize = 2;
+        const n: usize = 4;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let PbsFixture {
+            s_lwe,
+            bsk,
+            ksk,
+            testv,
+            delta,
+            ..
+        } = pbs_fixture::<F, D, n, N, K, ELL, LOGB>();
+
+        // the PBS of the batch may use different test vectors
+        let inputs = [(F::ZERO, testv.clone()), (F::ONE, testv.left_shift(1))]
+            .into_iter()
+            .map(|(m, testv)| (encrypt::<F, D, n>(&s_lwe, &(delta * m), 0f64), testv))
+            .collect_vec();
+
+        let prover = PbsBatchProver::<F, C, D, n, N, K, ELL, LOGB, 1>::new();
+        let (out_cts, proof) = prover.prove(&inputs, &bsk, &ksk).unwrap();
+        assert_eq!(out_cts.len(), inputs.len());
+
+        let vd = verifier_data_from_bytes::<F, C, D>(
+            verifier_data_to_bytes(&prover.verifier_data()).unwrap(),
+        )
+        .unwrap();
+        let proof = proof_from_bytes(proof.to_bytes(), &vd).unwrap();
+        let key_commitment = KeyCommitment::new(&bsk, &ksk);
+        verify_pbs_batch::<F, C, D, n, N, K, ELL, LOGB>(
+            &out_cts,
+            &inputs,
+            &key_commitment,
+            &proof,
+            &vd,
+        )
+        .unwrap();
+
+        // the typed public inputs are the claims of the proof
+        let pis = PbsBatchPublicInputs::from_public_inputs(&proof.public_inputs).unwrap();
+        assert_eq!(pis.key_root, key_commitment.root);
+        assert_eq!(pis.batch_size, inputs.len());
+        let num_elements = PbsBatchPublicInputs::<F>::num_elements();
+        assert_eq!(pis.to_public_inputs(), proof.public_inputs[..num_elements]);
+        assert!(PbsBatchPublicInputs::<F>::from_public_inputs(
+            &proof.public_inputs[..num_elements - 1]
+        )
+        .is_err());
+
+        // wrong claims are rejected
+        assert!(matches!(
+            verify_pbs_batch::<F, C, D, n, N, K, ELL, LOGB>(
+                &out_cts[..1],
+                &inputs[..1],
+                &key_commitment,
+                &proof,
+                &vd,
+            ),
+            Err(PbsVerificationError::WrongBatchSize)
+        ));
+        assert!(matches!(
+            verify_pbs_batch::<F, C, D, n, N, K, ELL, LOGB>(
+                &out_cts,
+                &inputs,
+                &KeyCommitment::new(&bsk, &bsk[0]),
+                &proof,
+                &vd,
+            ),
+            Err(PbsVerificationError::KeyRootMismatch)
+        ));
+        let swapped_out_cts = out_cts.iter().rev().cloned().collect_vec();
+        assert!(matches!(
+            verify_pbs_batch::<F, C, D, n, N, K, ELL, LOGB>(
+                &swapped_out_cts,
+                &inputs,
+                &key_commitment,
+                &proof,
+                &vd,
+            ),
+            Err(PbsVerificationError::BatchHashMismatch)
+        ));
+        let mut other_inputs = inputs.clone();
+        other_inputs[1].0[0] += F::ONE;
+        assert!(matches!(
+            verify_pbs_batch::<F, C, D, n, N, K, ELL, LOGB>(
+                &out_cts,
+                &other_inputs,
+                &key_commitment,
+                &proof,
+                &vd,
+            ),
+            Err(PbsVerificationError::BatchHashMismatch)
+        ));
+
+        // an empty batch has no proof
+        assert!(prover.prove(&[], &bsk, &ksk).is_err());
+    }
+
+    #[test]
+    fn test_pbs_tree_prover() {
+        const LOGB: usize = 8;
+        const ELL: usize = 8;
+        const K: usize = 4;
+        const D: usize = 2;
+        const n: usize = 4;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let PbsFixture {
+            bsk,
+            ksk,
+            testv,
+            ct,
+            ..
+        } = pbs_fixture::<F, D, n, N, K, ELL, LOGB>();
+
+        // the n + 2 = 6 steps are proved as 4 ranges of 1 or 2 steps
+        let prover = PbsTreeProver::<F, C, D, n, N, K, ELL, LOGB, 1>::new(2);
+        let (out_ct, proof) = prover.prove(&ct, &testv, &bsk, &ksk).unwrap();
+        let (expected_out_ct, _) = prover.pbs_prover().prove(&ct, &testv, &bsk, &ksk).unwrap();
+        assert_eq!(out_ct, expected_out_ct);
+
+        let vd = prover.verifier_data();
+        let key_commitment = KeyCommitment::new(&bsk, &ksk);
+        verify_pbs_tree::<F, C, D, n, N, K, ELL, LOGB>(
+            &out_ct,
+            &ct,
+            &testv,
+            &key_commitment,
+            &proof,
+            &vd,
+        )
+        .unwrap();
+
+        // wrong claims are rejected
+        assert!(matches!(
+            verify_pbs_tree::<F, C, D, n, N, K, ELL, LOGB>(
+                &out_ct,
+                &ct,
+                &testv.left_shift(1),
+                &key_commitment,
+                &proof,
+                &vd,
+            ),
+            Err(PbsVerificationError::WrongTestVector)
+        ));
+        let mut other_ct = ct.clone();
+        other_ct[0] += F::ONE;
+        assert!(matches!(
+            verify_pbs_tree::<F, C, D, n, N, K, ELL, LOGB>(
+                &out_ct,
+                &other_ct,
+                &testv,
+                &key_commitment,
+                &proof,
+                &vd,
+            ),
+            Err(PbsVerificationError::LweHashMismatch)
+        ));
+
+        // a range which does not start at the first step is no PBS proof, even if it starts from
+        // the test vector and ends with the last step
+        let dummy_ggsw = Ggsw::dummy_ct();
+        let steps = pbs_steps(&ct, &bsk, &ksk, &dummy_ggsw);
+        let range_proof = prover
+            .pbs_prover()
+            .prove_range(
+                &key_tree(&bsk, &ksk),
+                &steps,
+                n + 1..n + 2,
+                &Glwe::from_slice(&initial_acc::<F, D, N, K>(&testv)),
+                HashOut::ZERO,
+                &mut (),
+            )
+            .unwrap();
+        assert!(matches!(
+            verify_pbs::<F, C, D, n, N, K, ELL, LOGB>(
+                &PbsProver::<F, C, D, n, N, K, ELL, LOGB, 1>::latest_acc(&range_proof),
+                &ct,
+                &testv,
+                &key_commitment,
+                &range_proof,
+                &prover.pbs_prover().verifier_data(),
+            ),
+            Err(PbsVerificationError::WrongStepCount)
+        ));
+    }
+
+    #[test]
+    fn test_pbs_checkpoints() {
+        const LOGB: usize = 8;
+        const ELL: usize = 8;
+        const K: usize = 4;
+        const D: usize = 2;
+        const n: usize = 4;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let PbsFixture {
+            bsk,
+            ksk,
+            testv,
+            ct,
+            ..
+        } = pbs_fixture::<F, D, n, N, K, ELL, LOGB>();
+
+        let dir = std::env::temp_dir().join(format!("pbs_checkpoints_{}", random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        let checkpoints = PbsCheckpoints::new(&dir, 3);
+        let prover = PbsProver::<F, C, D, n, N, K, ELL, LOGB, 1>::new();
+
+        // nothing to resume from yet
+        assert!(prover
+            .resume(&ct, &testv, &bsk, &ksk, &checkpoints, &mut ())
+            .is_err());
+
+        // a run which stops after the first 3 of the n + 2 = 6 steps
+        let dummy_ggsw = Ggsw::dummy_ct();
+        let steps = pbs_steps(&ct, &bsk, &ksk, &dummy_ggsw);
+        let base_proof = prover.base_proof(
+            0,
+            &Glwe::from_slice(&initial_acc::<F, D, N, K>(&testv)),
+            HashOut::ZERO,
+        );
+        prover
+            .prove_steps(
+                &key_tree(&bsk, &ksk),
+                &steps,
+                0..3,
+                base_proof,
+                false,
+                &mut (),
+                Some(&checkpoints),
+            )
+            .unwrap();
+        assert!(checkpoints.proof_path(3).exists());
+
+        // the checkpoint only continues the PBS it was made for
+        let mut other_ct = ct.clone();
+        other_ct[0] += F::ONE;
+        assert!(prover
+            .resume(&other_ct, &testv, &bsk, &ksk, &checkpoints, &mut ())
+            .is_err());
+        assert!(prover
+            .resume(&ct, &testv.left_shift(1), &bsk, &ksk, &checkpoints, &mut ())
+            .is_err());
+
+        let (out_ct, proof) = prover
+            .resume(&ct, &testv, &bsk, &ksk, &checkpoints, &mut ())
+            .unwrap();
+        assert!(checkpoints.proof_path(6).exists());
+        assert!(!checkpoints.proof_path(3).exists());
+        let (expected_out_ct, _) = prover.prove(&ct, &testv, &bsk, &ksk).unwrap();
+        assert_eq!(out_ct, expected_out_ct);
+        verify_pbs::<F, C, D, n, N, K, ELL, LOGB>(
+            &out_ct,
+            &ct,
+            &testv,
+            &KeyCommitment::new(&bsk, &ksk),
+            &proof,
+            &prover.verifier_data(),
+        )
+        .unwrap();
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // Records the progress and cancels the proof after `cancel_after` steps.
+    struct CancellingObserver {
+        cancel_after: usize,
+        indices: Vec<usize>,
+        elapsed: Vec<Duration>,
+    }
+
+    impl<F: RichField + Extendable<D>, const D: usize, const N: usize, const K: usize>
+        PbsObserver<F, D, N, K> for CancellingObserver
+    {
+        fn observe(&mut self, _step: PbsStep, _acc: &Glwe<F, D, N, K>) {}
+
+        fn progress(&mut self, progress: &PbsProgress) -> ControlFlow<()> {
+            assert_eq!(progress.step, pbs_step::<4>(progress.index));
+            assert_eq!(progress.num_steps, 6);
+            assert!(progress.step_time <= progress.elapsed);
+            // one step per proof, so the timing is named after the step
+            let name = match progress.step {
+                PbsStep::BodyRotation => "prove step 0".to_string(),
+                PbsStep::Cmux(x) => format!("prove step {x}"),
+                PbsStep::KeySwitch => "key switch".to_string(),
+            };
+            assert_eq!(progress.step_timing.open_stack(), name);
+            self.indices.push(progress.index);
+            self.elapsed.push(progress.elapsed);
+            if self.indices.len() == self.cancel_after {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        }
+    }
+
+    #[test]
+    fn test_pbs_progress() {
+        const LOGB: usize = 8;
+        const ELL: usize = 8;
+        const K: usize = 4;
+        const D: usize = 2;
+        const n: usize = 4;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let PbsFixture {
+            bsk,
+            ksk,
+            testv,
+            ct,
+            ..
+        } = pbs_fixture::<F, D, n, N, K, ELL, LOGB>();
+
+        // a cancelled proof saves the steps proved so far
+        let dir = std::env::temp_dir().join(format!("pbs_progress_{}", random::<u64>()));
+        let checkpoints = PbsCheckpoints::new(&dir, 100);
+        let prover = PbsProver::<F, C, D, n, N, K, ELL, LOGB, 1>::new();
+        let mut observer = CancellingObserver {
+            cancel_after: 2,
+            indices: vec![],
+            elapsed: vec![],
+        };
+        let err = prover
+            .prove_with_checkpoints(&ct, &testv, &bsk, &ksk, &checkpoints, &mut observer)
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<PbsCancelled>(),
+            Some(&PbsCancelled { completed_steps: 2 })
+        );
+        assert_eq!(observer.indices, vec![0, 1]);
+        assert!(observer.elapsed[0] <= observer.elapsed[1]);
+        assert!(checkpoints.proof_path(2).exists());
+
+        // and the remaining steps are reported when it is resumed
+        let mut observer = CancellingObserver {
+            cancel_after: usize::MAX,
+            indices: vec![],
+            elapsed: vec![],
+        };
+        let (out_ct, proof) = prover
+            .resume(&ct, &testv, &bsk, &ksk, &checkpoints, &mut observer)
+            .unwrap();
+        assert_eq!(observer.indices, vec![2, 3, 4, 5]);
+        verify_pbs::<F, C, D, n, N, K, ELL, LOGB>(
+            &out_ct,
+            &ct,
+            &testv,
+            &KeyCommitment::new(&bsk, &ksk),
+            &proof,
+            &prover.verifier_data(),
+        )
+        .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        // cancelling without checkpoints
+        let mut observer = CancellingObserver {
+            cancel_after: 1,
+            indices: vec![],
+            elapsed: vec![],
+        };
+        let err = verified_pbs_with_observer::<F, C, D, n, N, K, ELL, LOGB, 1>(
+            &ct,
+            &testv,
+            &bsk,
+            &ksk,
+            &mut observer,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<PbsCancelled>(),
+            Some(&PbsCancelled { completed_steps: 1 })
+        );
+    }
+
+    // Records the accumulators after the steps and the indices of the progress reports.
+    #[derive(Default)]
+    struct RecordingObserver<
+        F: RichField + Extendable<D>,
+        const D: usize,
+        const N: usize,
+        const K: usize,
+    > {
+        accs: Vec<(PbsStep, Glwe<F, D, N, K>)>,
+        indices: Vec<usize>,
+    }
+
+    impl<F: RichField + Extendable<D>, const D: usize, const N: usize, const K: usize>
+        PbsObserver<F, D, N, K> for RecordingObserver<F, D, N, K>
+    {
+        fn observe(&mut self, step: PbsStep, acc: &Glwe<F, D, N, K>) {
+            self.accs.push((step, acc.clone()));
+        }
+
+        fn progress(&mut self, progress: &PbsProgress) -> ControlFlow<()> {
+            self.indices.push(progress.index);
+            ControlFlow::Continue(())
+        }
+    }
+
+    #[test]
+    fn test_steps_per_proof() {
+        const LOGB: usize = 8;
+        const ELL: usize = 8;
+        const K: usize = 4;
+        const D: usize = 2;
+        const n: usize = 4;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let PbsFixture {
+            bsk,
+            ksk,
+            testv,
+            ct,
+            ..
+        } = pbs_fixture::<F, D, n, N, K, ELL, LOGB>();
+        let key_commitment = KeyCommitment::new(&bsk, &ksk);
+
+        let dummy_ggsw = Ggsw::dummy_ct();
+        let steps = pbs_steps(&ct, &bsk, &ksk, &dummy_ggsw);
+        let mut expected_accs = vec![];
+        let mut acc = Glwe::from_slice(&initial_acc::<F, D, N, K>(&testv));
+        for (i, &(ggsw, mask_element)) in steps.iter().enumerate() {
+            acc = native_step::<F, D, n, N, K, ELL, LOGB>(&acc, ggsw, mask_element, i);
+            expected_accs.push((pbs_step::<n>(i), acc.clone()));
+        }
+
+        // the n + 2 = 6 steps in two proofs, the second one repeats the key switch twice
+        let prover = PbsProver::<F, C, D, n, N, K, ELL, LOGB, 4>::new();
+        let mut observer = RecordingObserver::default();
+        let (out_ct, proof) = prover
+            .prove_with_observer(&ct, &testv, &bsk, &ksk, &mut observer)
+            .unwrap();
+        assert_eq!(observer.indices, vec![3, 5]);
+        assert_eq!(observer.accs, expected_accs);
+        assert_eq!(out_ct, expected_accs[n + 1].1);
+        verify_pbs::<F, C, D, n, N, K, ELL, LOGB>(
+            &out_ct,
+            &ct,
+            &testv,
+            &key_commitment,
+            &proof,
+            &prover.verifier_data(),
+        )
+        .unwrap();
+
+        // only the range with the last step may be shorter than a proof
+        assert!(prover
+            .prove_range(
+                &key_tree(&bsk, &ksk),
+                &steps,
+                0..3,
+                &Glwe::from_slice(&initial_acc::<F, D, N, K>(&testv)),
+                HashOut::ZERO,
+                &mut (),
+            )
+            .is_err());
+
+        // the 3 proofs of 2 steps are split into the ranges 0..2 and 2..6
+        let prover = PbsTreeProver::<F, C, D, n, N, K, ELL, LOGB, 2>::new(1);
+        let (out_ct, proof) = prover.prove(&ct, &testv, &bsk, &ksk).unwrap();
+        assert_eq!(out_ct, expected_accs[n + 1].1);
+        verify_pbs_tree::<F, C, D, n, N, K, ELL, LOGB>(
+            &out_ct,
+            &ct,
+            &testv,
+            &key_commitment,
+            &proof,
+            &prover.verifier_data(),
+        )
+        .unwrap();
     }
 }
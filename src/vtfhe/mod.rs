@@ -32,6 +32,7 @@ pub mod glwe_ct;
 pub mod glwe_poly;
 pub mod ivc_based_vpbs;
 pub mod lev_ct;
+pub mod noise;
 pub mod starky_ct;
 
 pub const NUM_BITS: usize = 64;
@@ -150,6 +151,7 @@ pub fn eval_le_sum<P: PackedField>(yield_constr: &mut ConstraintConsumer<P>, bit
     let mut rev_bits = bits.into_iter().rev();
     let mut sum = rev_bits.next().unwrap();
     let two = P::from(P::Scalar::from_canonical_u8(2));
+    yield_constr.constraint(sum * sum - sum);
 
     for bit in rev_bits {
         yield_constr.constraint(bit * bit - bit);
@@ -179,7 +181,6 @@ pub fn eval_le_sum_without_ext<F: RichField + Extendable<D>, const D: usize>(
     let two = builder.constant_extension(F::Extension::from_canonical_u8(2));
 
     for bit in rev_bits {
-        let constr = builder.mul_sub_extension(bit, bit, bit);
         sum = builder.mul_add_extension(two, sum, bit);
     }
     sum
@@ -193,6 +194,8 @@ pub fn eval_le_sum_ext<F: RichField + Extendable<D>, const D: usize>(
     let mut rev_bits = bits.into_iter().rev();
     let mut sum = rev_bits.next().unwrap();
     let two = builder.constant_extension(F::Extension::from_canonical_u8(2));
+    let constr = builder.mul_sub_extension(sum, sum, sum);
+    yield_constr.constraint(builder, constr);
 
     for bit in rev_bits {
         let constr = builder.mul_sub_extension(bit, bit, bit);
@@ -257,53 +260,6 @@ pub fn rotate_poly_native<F: RichField + Extendable<D>, const D: usize, const N:
     }
     current_poly
 }
-pub fn eval_rotate_poly<P: PackedField, const N: usize>(
-    poly: &GlwePolyExp<N, P>,
-    shift_bit_dec: [P; NUM_BITS],
-) -> GlwePolyExp<N, P> {
-    let log2_N = log2_ceil(N) + 1;
-
-    let it = shift_bit_dec[NUM_BITS - log2_N..].iter();
-    let carry_shift = poly.rotate(1);
-
-    let mut current_poly =
-        eval_poly_select(shift_bit_dec[NUM_BITS - log2_N - 1], &carry_shift, poly);
-
-    for (log_shift, bit) in it.enumerate() {
-        let current_shift = 2usize.pow((log_shift) as u32);
-        let shifted_poly = current_poly.rotate(current_shift);
-        current_poly = eval_poly_select(*bit, &shifted_poly, &current_poly);
-    }
-
-    current_poly
-}
-
-pub fn eval_rotate_poly_ext<F: RichField + Extendable<D>, const D: usize, const N: usize>(
-    builder: &mut CircuitBuilder<F, D>,
-    poly: &GlwePolyExp<N, ExtensionTarget<D>>,
-    shift_bit_dec: [ExtensionTarget<D>; NUM_BITS],
-) -> GlwePolyExp<N, ExtensionTarget<D>> {
-    let log2_N = log2_ceil(N) + 1;
-
-    let it = shift_bit_dec[NUM_BITS - log2_N..].iter();
-    let carry_shift = poly.rotate_ext(builder, 1);
-
-    let mut current_poly = eval_poly_select_ext(
-        builder,
-        shift_bit_dec[NUM_BITS - log2_N - 1],
-        &carry_shift,
-        poly,
-    );
-
-    for (log_shift, bit) in it.enumerate() {
-        let current_shift = 2usize.pow((log_shift) as u32);
-        let shifted_poly = current_poly.rotate_ext(builder, current_shift);
-        current_poly = eval_poly_select_ext(builder, *bit, &shifted_poly, &current_poly);
-    }
-
-    current_poly
-}
-
 pub fn rotate_glwe<F: RichField + Extendable<D>, const D: usize, const N: usize, const K: usize>(
     cb: &mut CircuitBuilder<F, D>,
     glwe: &GlweCt<N, K>,
@@ -328,21 +284,84 @@ pub fn rotate_glwe_native<
     }
 }
 
-pub fn eval_rotate_glwe<P: PackedField, const N: usize, const K: usize>(
+/// Number of selections of a rotation by a mod switched shift: the rounding carry and the
+/// log2(2N) bits of the shift.
+pub const fn num_rotation_stages<const N: usize>() -> usize {
+    N.ilog2() as usize + 2
+}
+
+/// Same as `rotate_glwe_native`, returning the GLWE after each selection.
+pub fn rotate_glwe_stages_native<
+    F: RichField + Extendable<D>,
+    const D: usize,
+    const N: usize,
+    const K: usize,
+>(
+    glwe: &GlweCtNative<F, D, N, K>,
+    shift_bit_dec: [F; NUM_BITS],
+) -> Vec<GlweCtNative<F, D, N, K>> {
+    let log2_N = log2_ceil(N) + 1;
+    let mut current = glwe.clone();
+
+    (0..num_rotation_stages::<N>())
+        .map(|stage| {
+            let (bit, shift) = if stage == 0 {
+                (shift_bit_dec[NUM_BITS - log2_N - 1], 1)
+            } else {
+                (
+                    shift_bit_dec[NUM_BITS - log2_N + stage - 1],
+                    1 << (stage - 1),
+                )
+            };
+            if bit == F::ONE {
+                current = GlweCtNative {
+                    polys: from_fn(|i| current.polys[i].rotate(shift)),
+                };
+            }
+            current.clone()
+        })
+        .collect()
+}
+
+/// Constrains the rotation of `glwe` by the mod switched `shift`, with the GLWE after each
+/// selection committed in `stages`, which keeps the degree of the rotation at 2.
+pub fn eval_rotate_glwe_stages<P: PackedField, const N: usize, const K: usize>(
     yield_constr: &mut ConstraintConsumer<P>,
     filter: P,
     glwe: &GlweCtExp<N, K, P>,
     shift: P,
     shift_bit_dec: [P; NUM_BITS],
+    stages: &[GlweCtExp<N, K, P>],
 ) -> GlweCtExp<N, K, P> {
+    assert_eq!(stages.len(), num_rotation_stages::<N>());
     let cal_shift = eval_le_sum(yield_constr, shift_bit_dec.to_vec());
     yield_constr.constraint(filter * (shift - cal_shift));
-    GlweCtExp {
-        polys: from_fn(|i| eval_rotate_poly(&glwe.polys[i], shift_bit_dec)),
+
+    let log2_N = log2_ceil(N) + 1;
+    let mut current = glwe;
+    for (stage, committed) in stages.iter().enumerate() {
+        let (bit, shift) = if stage == 0 {
+            (shift_bit_dec[NUM_BITS - log2_N - 1], 1)
+        } else {
+            (
+                shift_bit_dec[NUM_BITS - log2_N + stage - 1],
+                1 << (stage - 1),
+            )
+        };
+        let shifted = GlweCtExp {
+            polys: from_fn(|i| current.polys[i].rotate(shift)),
+        };
+        let selected = eval_glwe_select(bit, &shifted, current);
+        for (c, s) in committed.flatten().into_iter().zip(selected.flatten()) {
+            yield_constr.constraint(c - s);
+        }
+        current = committed;
     }
+
+    current.clone()
 }
 
-pub fn eval_rotate_glwe_ext<
+pub fn eval_rotate_glwe_stages_ext<
     F: RichField + Extendable<D>,
     const D: usize,
     const N: usize,
@@ -354,14 +373,41 @@ pub fn eval_rotate_glwe_ext<
     glwe: &GlweCtExp<N, K, ExtensionTarget<D>>,
     shift: ExtensionTarget<D>,
     shift_bit_dec: [ExtensionTarget<D>; NUM_BITS],
+    stages: &[GlweCtExp<N, K, ExtensionTarget<D>>],
 ) -> GlweCtExp<N, K, ExtensionTarget<D>> {
+    assert_eq!(stages.len(), num_rotation_stages::<N>());
     let cal_shift = eval_le_sum_ext(builder, yield_constr, shift_bit_dec.to_vec());
     let diff = builder.sub_extension(shift, cal_shift);
     let constr = builder.mul_extension(filter, diff);
     yield_constr.constraint(builder, constr);
-    GlweCtExp {
-        polys: from_fn(|i| eval_rotate_poly_ext(builder, &glwe.polys[i], shift_bit_dec)),
+
+    let log2_N = log2_ceil(N) + 1;
+    let mut current = glwe;
+    for (stage, committed) in stages.iter().enumerate() {
+        let (bit, shift) = if stage == 0 {
+            (shift_bit_dec[NUM_BITS - log2_N - 1], 1)
+        } else {
+            (
+                shift_bit_dec[NUM_BITS - log2_N + stage - 1],
+                1 << (stage - 1),
+            )
+        };
+        let shifted = GlweCtExp {
+            polys: from_fn(|i| current.polys[i].rotate_ext(builder, shift)),
+        };
+        let selected = eval_glwe_select_ext(builder, bit, &shifted, current);
+        for (c, s) in committed
+            .flatten_ext()
+            .into_iter()
+            .zip(selected.flatten_ext())
+        {
+            let constr = builder.sub_extension(c, s);
+            yield_constr.constraint(builder, constr);
+        }
+        current = committed;
     }
+
+    current.clone()
 }
 
 pub fn blind_rotation_step<
@@ -386,7 +432,6 @@ pub fn blind_rotation_step<
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::ntt::params::N;
     use crate::vtfhe::crypto::ggsw::Ggsw;
     use crate::vtfhe::crypto::glwe::Glwe;
     use crate::vtfhe::crypto::poly::Poly;
@@ -399,6 +444,8 @@ mod tests {
     use tests::crypto::compute_bsk;
     use tests::crypto::lwe::{encrypt, key_gen};
 
+    const N: usize = 8;
+
     fn check_rotation<F: RichField + Extendable<D>, const D: usize, const N: usize>(
         in_poly: &Poly<F, D, N>,
         out_poly: &Poly<F, D, N>,
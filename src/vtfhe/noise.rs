@@ -0,0 +1,203 @@
+use log::info;
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hash_types::RichField;
+use serde::Serialize;
+
+use super::crypto::glwe::Glwe;
+use super::crypto::lwe::mod_switch_ct;
+use super::crypto::poly::Poly;
+use super::ivc_based_vpbs::{PbsObserver, PbsStep};
+
+/// The noise of the accumulator after one step of a PBS.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StepNoise {
+    #[serde(flatten)]
+    pub step: PbsStep,
+    /// The canonical coefficients of the rotated test vector the accumulator should encrypt.
+    pub expected_testv: Vec<u64>,
+    /// `Glwe::get_avg_error` of the accumulator.
+    pub avg_error: f64,
+    /// `Glwe::get_max_error` of the accumulator.
+    pub max_error: f64,
+}
+
+/// The noise of every step of a PBS (body rotation, each CMUX, key switch), as recorded by a
+/// `NoiseObserver`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct NoiseReport {
+    pub steps: Vec<StepNoise>,
+}
+
+impl NoiseReport {
+    /// Writes the report as a JSON object `{"steps": [...]}` with one object per step. Errors
+    /// which are not finite are written as `null`.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+/// Logs and records in a `NoiseReport` the noise of the accumulator after every step of one PBS.
+/// It holds the secret keys, so it only runs where they are, e.g. on the client or in tests, and
+/// is never needed to prove.
+pub struct NoiseObserver<'a, F: RichField + Extendable<D>, const D: usize, const N: usize> {
+    glwe_key: &'a [Poly<F, D, N>],
+    lwe_key: &'a [F],
+    ksk_key: &'a [Poly<F, D, N>],
+    ct_switched: Vec<usize>,
+    testv: Poly<F, D, N>,
+    report: NoiseReport,
+}
+
+impl<'a, F: RichField + Extendable<D>, const D: usize, const N: usize> NoiseObserver<'a, F, D, N> {
+    /// Observes the PBS of `ct` with `testv`, where `glwe_key` is the key of the BSK, `lwe_key`
+    /// the key of `ct` and `ksk_key` the key of the KSK.
+    pub fn new(
+        ct: &[F],
+        testv: &Poly<F, D, N>,
+        glwe_key: &'a [Poly<F, D, N>],
+        lwe_key: &'a [F],
+        ksk_key: &'a [Poly<F, D, N>],
+    ) -> Self {
+        Self {
+            glwe_key,
+            lwe_key,
+            ksk_key,
+            ct_switched: mod_switch_ct(ct, N),
+            testv: testv.clone(),
+            report: NoiseReport::default(),
+        }
+    }
+
+    /// The noise of the steps observed so far.
+    pub fn report(&self) -> &NoiseReport {
+        &self.report
+    }
+
+    /// The message the accumulator encrypts after `step`: the test vector rotated by the body and
+    /// by the mask elements of the CMUXes up to this step. It only depends on the step, so the
+    /// steps of a resumed PBS can be observed without the ones before them.
+    fn expected_testv(&self, step: PbsStep) -> Poly<F, D, N> {
+        let num_cmuxes = match step {
+            PbsStep::BodyRotation => 0,
+            PbsStep::Cmux(i) => i + 1,
+            PbsStep::KeySwitch => self.lwe_key.len(),
+        };
+        // a mod switched element rounds up to 2N at most, which is no rotation
+        let body = self.ct_switched[self.ct_switched.len() - 1] % (2 * N);
+        (0..num_cmuxes).fold(self.testv.left_shift(body), |testv, i| {
+            let shift = self.ct_switched[i] * (self.lwe_key[i].to_canonical_u64() as usize);
+            testv.right_shift(shift % (2 * N))
+        })
+    }
+}
+
+impl<F: RichField + Extendable<D>, const D: usize, const N: usize, const K: usize>
+    PbsObserver<F, D, N, K> for NoiseObserver<'_, F, D, N>
+{
+    fn observe(&mut self, step: PbsStep, acc: &Glwe<F, D, N, K>) {
+        let key = match step {
+            PbsStep::BodyRotation | PbsStep::Cmux(_) => self.glwe_key,
+            PbsStep::KeySwitch => self.ksk_key,
+        };
+        let testv = self.expected_testv(step);
+        let avg_error = acc.get_avg_error(key, &testv);
+        let max_error = acc.get_max_error(key, &testv);
+        info!("Avg error: {avg_error}");
+        info!("Max error: {max_error}");
+        self.report.steps.push(StepNoise {
+            step,
+            expected_testv: testv.coeffs.iter().map(|c| c.to_canonical_u64()).collect(),
+            avg_error,
+            max_error,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vtfhe::crypto::{pbs_fixture, PbsFixture};
+    use crate::vtfhe::starky_ct::generate_build_circuit_input;
+    use crate::vtfhe::starky_ct::ggsw_ct::GgswCtNative;
+    use crate::vtfhe::starky_ct::glwe_ct::GlweCtNative;
+    use itertools::Itertools;
+    use plonky2::field::types::{Field, PrimeField64};
+    use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+
+    #[test]
+    fn test_noise_report() {
+        const LOGB: usize = 8;
+        const ELL: usize = 8;
+        const N: usize = 8;
+        const K: usize = 4;
+        const D: usize = 2;
+        const n: usize = 4;
+        type C = PoseidonGoldilocksConfig;
+        type F = <C as GenericConfig<D>>::F;
+
+        let PbsFixture {
+            s_to,
+            s_lwe,
+            s_glwe,
+            bsk,
+            ksk,
+            testv,
+            ct,
+            ..
+        } = pbs_fixture::<F, D, n, N, K, ELL, LOGB>();
+
+        // observe the native steps of the PBS
+        let mut observer = NoiseObserver::new(&ct, &testv, &s_glwe, &s_lwe, &s_to);
+        let coeffs = vec![F::ZERO; N * (K - 1)]
+            .into_iter()
+            .chain(testv.coeffs)
+            .collect_vec();
+        let mut acc = GlweCtNative::new_from_slice(&coeffs);
+        let mut accs = Vec::new();
+        let steps = [(PbsStep::BodyRotation, GgswCtNative::dummy_ct(), ct[n])]
+            .into_iter()
+            .chain((0..n).map(|i| (PbsStep::Cmux(i), GgswCtNative::from_ggsw(&bsk[i]), ct[i])))
+            .chain([(PbsStep::KeySwitch, GgswCtNative::from_ggsw(&ksk), F::ZERO)]);
+        for (counter, (step, ggsw_ct, mask_ele)) in steps.enumerate() {
+            (acc, _, _) = generate_build_circuit_input::<F, D, n, N, K, ELL, LOGB>(
+                &acc,
+                &ggsw_ct,
+                mask_ele,
+                F::from_canonical_usize(counter + 1),
+            );
+            let acc = Glwe::<F, D, N, K>::from_slice(&acc.flatten());
+            observer.observe(step, &acc);
+            accs.push((step, acc));
+        }
+
+        let report = observer.report();
+        assert_eq!(report.steps.len(), n + 2);
+        assert_eq!(report.steps[0].step, PbsStep::BodyRotation);
+        assert_eq!(report.steps[n + 1].step, PbsStep::KeySwitch);
+        let body = mod_switch_ct::<F, D>(&ct, N)[n] % (2 * N);
+        let rotated_testv = testv.left_shift(body);
+        assert_eq!(
+            report.steps[0].expected_testv,
+            rotated_testv
+                .coeffs
+                .iter()
+                .map(|c| c.to_canonical_u64())
+                .collect_vec()
+        );
+        // the body rotation of the trivial accumulator adds no noise
+        assert_eq!(report.steps[0].max_error, 0.0);
+
+        // a resumed PBS is only observed from the step it resumes at
+        let mut resumed_observer = NoiseObserver::new(&ct, &testv, &s_glwe, &s_lwe, &s_to);
+        for (step, acc) in &accs[2..] {
+            resumed_observer.observe(*step, acc);
+        }
+        assert_eq!(resumed_observer.report().steps, report.steps[2..]);
+
+        let json = report.to_json();
+        assert!(json.starts_with(r#"{"steps":[{"step":"body_rotation","expected_testv":["#));
+        assert!(json.contains(r#"{"step":"cmux","index":0,"#));
+        assert!(json.ends_with("}]}"));
+        assert_eq!(json.matches("avg_error").count(), n + 2);
+    }
+}
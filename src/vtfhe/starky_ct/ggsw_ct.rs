@@ -9,12 +9,16 @@ use plonky2::{
 };
 use starky::constraint_consumer::{ConstraintConsumer, RecursiveConstraintConsumer};
 
-use crate::vtfhe::{crypto::ggsw::Ggsw, NUM_BITS};
+use crate::{
+    ntt::{eval_ntt_forward, eval_ntt_forward_ext, ntt_forward_native},
+    vtfhe::{crypto::ggsw::Ggsw, NUM_BITS},
+};
 
 use super::{
     glev_ct::{GlevCtExp, GlevCtNative},
     glwe_ct::{GlweCtExp, GlweCtNative},
-    glwe_poly::{GlwePolyExp, GlwePolyNative},
+    glwe_poly::{decompose_native, num_limbs, GlwePolyExp, GlwePolyNative},
+    read_glwe_ct,
 };
 
 pub fn glwe_add_many_native<
@@ -68,6 +72,31 @@ pub fn eval_glwe_add_many_ext<
         .fold(init, |acc, t| acc.add_ext(builder, &t))
 }
 
+/// Committed gadget decomposition of the GLWE input of an external product.
+pub enum GlweDecomposition<'a, const N: usize, const K: usize, T> {
+    /// 64 bit decompositions of the coefficients and of their negations, the top ELL balanced
+    /// limbs they encode (polynomial major) and the output of the external product.
+    Bits(
+        [[[T; NUM_BITS]; N]; K],
+        [[[T; NUM_BITS]; N]; K],
+        &'a [T],
+        &'a [T],
+    ),
+    /// Balanced base 2^LOGB limbs of the coefficients (coefficient major), range checked
+    /// with a lookup by the caller, and the NTTs of the external product.
+    Limbs(&'a [T], ExternalProductNtts<'a, T>),
+}
+
+/// NTTs of an external product, committed in the trace and proven by the caller.
+pub struct ExternalProductNtts<'a, T> {
+    /// Forward NTTs of the top ELL limbs of the polynomials of the input (polynomial major).
+    pub limbs_hat: &'a [T],
+    /// Output in the NTT domain.
+    pub out_hat: &'a [T],
+    /// Backward NTTs of `out_hat`.
+    pub out: &'a [T],
+}
+
 #[derive(Debug)]
 pub struct GgswCtExp<const N: usize, const K: usize, const ELL: usize, T> {
     pub glev_cts: [GlevCtExp<N, K, ELL, T>; K],
@@ -93,25 +122,90 @@ impl<const N: usize, const K: usize, const ELL: usize, P: PackedField> GgswCtExp
         yield_constr: &mut ConstraintConsumer<P>,
         filter: P,
         glwe: &GlweCtExp<N, K, P>,
-        glwe_poly_coeffs_bit_dec: [[[P; NUM_BITS]; N]; K],
+        glwe_dec: GlweDecomposition<N, K, P>,
     ) -> GlweCtExp<N, K, P> {
+        let (bit_dec, neg_bit_dec, top_limbs, out) = match glwe_dec {
+            GlweDecomposition::Bits(bit_dec, neg_bit_dec, top_limbs, out) => {
+                (bit_dec, neg_bit_dec, top_limbs, out)
+            }
+            GlweDecomposition::Limbs(limbs, ntts) => {
+                return self.eval_external_product_hat::<LOGB>(
+                    yield_constr,
+                    filter,
+                    glwe,
+                    limbs,
+                    ntts,
+                )
+            }
+        };
+
+        let num_limbs = num_limbs::<LOGB>();
         let glev_muls: Vec<GlweCtExp<N, K, P>> = glwe
             .polys
             .iter()
             .zip(self.glev_cts.iter())
+            .zip(top_limbs.chunks(ELL * N))
             .enumerate()
-            .map(|(i, (glwe_poly, glev))| {
-                glev.eval_mul::<LOGB>(
+            .map(|(i, ((glwe_poly, glev), poly_top_limbs))| {
+                let limbs = glwe_poly.eval_decompose::<LOGB>(
                     yield_constr,
                     filter,
-                    &glwe_poly,
-                    &glwe_poly_coeffs_bit_dec[i],
-                )
+                    &bit_dec[i],
+                    &neg_bit_dec[i],
+                    num_limbs,
+                );
+                for (committed, limb) in poly_top_limbs.chunks(N).zip(&limbs[num_limbs - ELL..]) {
+                    for (c, l) in committed.iter().zip(limb) {
+                        yield_constr.constraint(*c - *l);
+                    }
+                }
+                let limbs_hat = poly_top_limbs
+                    .chunks(N)
+                    .map(|limb| eval_ntt_forward(&limb.to_vec()))
+                    .collect();
+                glev.eval_mul_hat(&limbs_hat)
             })
             .collect();
         let sum_polys = eval_glwe_add_many(&glev_muls[..K - 1]);
-        // sum_polys.sub(cb, &glev_muls[K - 1]).ntt_backward(cb)
-        glev_muls[K - 1].sub(&sum_polys).ntt_backward()
+        let expected = glev_muls[K - 1].sub(&sum_polys).ntt_backward();
+
+        for (committed, expected) in out.iter().zip_eq(expected.flatten()) {
+            yield_constr.constraint(*committed - expected);
+        }
+
+        read_glwe_ct(out, &mut 0)
+    }
+
+    /// External product with committed NTTs: only the recomposition of the limbs and the
+    /// product in the NTT domain are constrained here.
+    fn eval_external_product_hat<const LOGB: usize>(
+        &self,
+        yield_constr: &mut ConstraintConsumer<P>,
+        filter: P,
+        glwe: &GlweCtExp<N, K, P>,
+        limbs: &[P],
+        ntts: ExternalProductNtts<P>,
+    ) -> GlweCtExp<N, K, P> {
+        let glev_muls: Vec<GlweCtExp<N, K, P>> = glwe
+            .polys
+            .iter()
+            .zip(self.glev_cts.iter())
+            .zip(limbs.chunks(limbs.len() / K))
+            .zip(ntts.limbs_hat.chunks(ELL * N))
+            .map(|(((glwe_poly, glev), poly_limbs), poly_limbs_hat)| {
+                glwe_poly.eval_decompose_limbs::<LOGB>(yield_constr, filter, poly_limbs);
+                let limbs_hat = poly_limbs_hat.chunks(N).map(|l| l.to_vec()).collect();
+                glev.eval_mul_hat(&limbs_hat)
+            })
+            .collect();
+        let sum_polys = eval_glwe_add_many(&glev_muls[..K - 1]);
+        let out_hat = glev_muls[K - 1].sub(&sum_polys);
+
+        for (committed, expected) in ntts.out_hat.iter().zip_eq(out_hat.flatten()) {
+            yield_constr.constraint(filter * (*committed - expected));
+        }
+
+        read_glwe_ct(ntts.out, &mut 0)
     }
 }
 impl<const D: usize, const N: usize, const K: usize, const ELL: usize>
@@ -130,28 +224,102 @@ impl<const D: usize, const N: usize, const K: usize, const ELL: usize>
         yield_constr: &mut RecursiveConstraintConsumer<F, D>,
         filter: ExtensionTarget<D>,
         glwe: &GlweCtExp<N, K, ExtensionTarget<D>>,
-        glwe_poly_coeffs_bit_dec: [[[ExtensionTarget<D>; NUM_BITS]; N]; K],
+        glwe_dec: GlweDecomposition<N, K, ExtensionTarget<D>>,
     ) -> GlweCtExp<N, K, ExtensionTarget<D>> {
+        let (bit_dec, neg_bit_dec, top_limbs, out) = match glwe_dec {
+            GlweDecomposition::Bits(bit_dec, neg_bit_dec, top_limbs, out) => {
+                (bit_dec, neg_bit_dec, top_limbs, out)
+            }
+            GlweDecomposition::Limbs(limbs, ntts) => {
+                return self.eval_external_product_hat_ext::<F, LOGB>(
+                    builder,
+                    yield_constr,
+                    filter,
+                    glwe,
+                    limbs,
+                    ntts,
+                )
+            }
+        };
+
+        let num_limbs = num_limbs::<LOGB>();
         let glev_muls: Vec<GlweCtExp<N, K, ExtensionTarget<D>>> = glwe
             .polys
             .iter()
             .zip(self.glev_cts.iter())
+            .zip(top_limbs.chunks(ELL * N))
             .enumerate()
-            .map(|(i, (glwe_poly, glev))| {
-                glev.eval_mul_ext::<F, LOGB>(
+            .map(|(i, ((glwe_poly, glev), poly_top_limbs))| {
+                let limbs = glwe_poly.eval_decompose_ext::<F, LOGB>(
                     builder,
                     yield_constr,
                     filter,
-                    &glwe_poly,
-                    &glwe_poly_coeffs_bit_dec[i],
-                )
+                    &bit_dec[i],
+                    &neg_bit_dec[i],
+                    num_limbs,
+                );
+                for (committed, limb) in poly_top_limbs.chunks(N).zip(&limbs[num_limbs - ELL..]) {
+                    for (c, l) in committed.iter().zip(limb) {
+                        let constr = builder.sub_extension(*c, *l);
+                        yield_constr.constraint(builder, constr);
+                    }
+                }
+                let limbs_hat = poly_top_limbs
+                    .chunks(N)
+                    .map(|limb| eval_ntt_forward_ext(builder, &limb.to_vec()))
+                    .collect();
+                glev.eval_mul_hat_ext(builder, &limbs_hat)
             })
             .collect();
         let sum_polys = eval_glwe_add_many_ext(builder, &glev_muls[..K - 1]);
-        // sum_polys.sub(cb, &glev_muls[K - 1]).ntt_backward(cb)
-        glev_muls[K - 1]
+        let expected = glev_muls[K - 1]
             .sub_ext(builder, &sum_polys)
-            .ntt_backward_ext(builder)
+            .ntt_backward_ext(builder);
+
+        for (committed, expected) in out.iter().zip_eq(expected.flatten_ext()) {
+            let constr = builder.sub_extension(*committed, expected);
+            yield_constr.constraint(builder, constr);
+        }
+
+        read_glwe_ct(out, &mut 0)
+    }
+
+    fn eval_external_product_hat_ext<F: RichField + Extendable<D>, const LOGB: usize>(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        yield_constr: &mut RecursiveConstraintConsumer<F, D>,
+        filter: ExtensionTarget<D>,
+        glwe: &GlweCtExp<N, K, ExtensionTarget<D>>,
+        limbs: &[ExtensionTarget<D>],
+        ntts: ExternalProductNtts<ExtensionTarget<D>>,
+    ) -> GlweCtExp<N, K, ExtensionTarget<D>> {
+        let glev_muls: Vec<GlweCtExp<N, K, ExtensionTarget<D>>> = glwe
+            .polys
+            .iter()
+            .zip(self.glev_cts.iter())
+            .zip(limbs.chunks(limbs.len() / K))
+            .zip(ntts.limbs_hat.chunks(ELL * N))
+            .map(|(((glwe_poly, glev), poly_limbs), poly_limbs_hat)| {
+                glwe_poly.eval_decompose_limbs_ext::<F, LOGB>(
+                    builder,
+                    yield_constr,
+                    filter,
+                    poly_limbs,
+                );
+                let limbs_hat = poly_limbs_hat.chunks(N).map(|l| l.to_vec()).collect();
+                glev.eval_mul_hat_ext(builder, &limbs_hat)
+            })
+            .collect();
+        let sum_polys = eval_glwe_add_many_ext(builder, &glev_muls[..K - 1]);
+        let out_hat = glev_muls[K - 1].sub_ext(builder, &sum_polys);
+
+        for (committed, expected) in ntts.out_hat.iter().zip_eq(out_hat.flatten_ext()) {
+            let diff = builder.sub_extension(*committed, expected);
+            let constr = builder.mul_extension(filter, diff);
+            yield_constr.constraint(builder, constr);
+        }
+
+        read_glwe_ct(ntts.out, &mut 0)
     }
 }
 
@@ -203,6 +371,40 @@ impl<
         // sum_polys.sub(cb, &glev_muls[K - 1]).ntt_backward(cb)
         glev_muls[K - 1].sub(&sum_polys).ntt_backward()
     }
+
+    /// Forward NTTs of the top ELL limbs of the polynomials of the input of `external_product`,
+    /// and its output in the NTT domain.
+    pub fn external_product_hat<const LOGB: usize>(
+        &self,
+        glwe_poly_coeffs_bit_dec: [[[F; NUM_BITS]; N]; K],
+        neg_glwe_poly_coeffs_bit_dec: [[[F; NUM_BITS]; N]; K],
+    ) -> (Vec<Vec<F>>, GlweCtNative<F, D, N, K>) {
+        let mut limbs_hat = Vec::with_capacity(K * ELL);
+        let glev_muls = self
+            .glev_cts
+            .iter()
+            .zip(
+                glwe_poly_coeffs_bit_dec
+                    .iter()
+                    .zip(&neg_glwe_poly_coeffs_bit_dec),
+            )
+            .map(|(glev, (bit_dec, neg_bit_dec))| {
+                let coeffs_limbs = bit_dec
+                    .iter()
+                    .zip(neg_bit_dec)
+                    .map(|(bits, neg_bits)| decompose_native::<F, D, LOGB>(*bits, *neg_bits))
+                    .collect_vec();
+                let poly_limbs_hat = (num_limbs::<LOGB>() - ELL..num_limbs::<LOGB>())
+                    .map(|i| ntt_forward_native(&coeffs_limbs.iter().map(|l| l[i]).collect()))
+                    .collect_vec();
+                let glev_mul = glev.mul_hat(&poly_limbs_hat);
+                limbs_hat.extend(poly_limbs_hat);
+                glev_mul
+            })
+            .collect_vec();
+        let sum_polys = glwe_add_many_native(&glev_muls[..K - 1]);
+        (limbs_hat, glev_muls[K - 1].sub(&sum_polys))
+    }
     pub fn dummy_ct() -> Self {
         GgswCtNative {
             glev_cts: from_fn(|_| GlevCtNative::dummy_ct()),
@@ -1,5 +1,7 @@
 use std::array::from_fn;
 
+use itertools::Itertools;
+
 use plonky2::{
     field::{extension::Extendable, packed::PackedField},
     hash::hash_types::RichField,
@@ -53,20 +55,40 @@ impl<const N: usize, const K: usize, const ELL: usize, P: PackedField> GlevCtExp
             .collect()
     }
 
+    /// Product with `glwe_poly`, whose top ELL balanced limbs are committed in `top_limbs` (limb
+    /// major) to keep the degree of the product at 2. The limbs are bound to the decomposition on
+    /// every row.
     pub fn eval_mul<const LOGB: usize>(
         &self,
         yield_constr: &mut ConstraintConsumer<P>,
         filter: P,
         glwe_poly: &GlwePolyExp<N, P>,
         coeffs_bit_dec: &[[P; NUM_BITS]; N],
+        coeffs_neg_bit_dec: &[[P; NUM_BITS]; N],
+        top_limbs: &[P],
     ) -> GlweCtExp<N, K, P> {
         let num_limbs = ceil_div_usize(NUM_BITS, LOGB);
-        let limbs =
-            glwe_poly.eval_decompose::<LOGB>(yield_constr, filter, coeffs_bit_dec, num_limbs);
-        let limbs_hat = &limbs[num_limbs - ELL..]
-            .iter()
-            .map(|limb| eval_ntt_forward(&limb))
+        let limbs = glwe_poly.eval_decompose::<LOGB>(
+            yield_constr,
+            filter,
+            coeffs_bit_dec,
+            coeffs_neg_bit_dec,
+            num_limbs,
+        );
+        for (committed, limb) in top_limbs.chunks(N).zip_eq(&limbs[num_limbs - ELL..]) {
+            for (c, l) in committed.iter().zip(limb) {
+                yield_constr.constraint(*c - *l);
+            }
+        }
+        let limbs_hat = top_limbs
+            .chunks(N)
+            .map(|limb| eval_ntt_forward(&limb.to_vec()))
             .collect();
+        self.eval_mul_hat(&limbs_hat)
+    }
+
+    /// Product in the NTT domain, from the forward NTTs of the top ELL limbs.
+    pub fn eval_mul_hat(&self, limbs_hat: &Vec<Vec<P>>) -> GlweCtExp<N, K, P> {
         let range: [usize; K] = core::array::from_fn(|i| i);
         let polys = range.map(|index| GlwePolyExp {
             coeffs: eval_vec_inner(limbs_hat, &self.get_row(index))
@@ -87,6 +109,7 @@ impl<const D: usize, const N: usize, const K: usize, const ELL: usize>
             .collect()
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn eval_mul_ext<F: RichField + Extendable<D>, const LOGB: usize>(
         &self,
         builder: &mut CircuitBuilder<F, D>,
@@ -94,6 +117,8 @@ impl<const D: usize, const N: usize, const K: usize, const ELL: usize>
         filter: ExtensionTarget<D>,
         glwe_poly: &GlwePolyExp<N, ExtensionTarget<D>>,
         coeffs_bit_dec: &[[ExtensionTarget<D>; NUM_BITS]; N],
+        coeffs_neg_bit_dec: &[[ExtensionTarget<D>; NUM_BITS]; N],
+        top_limbs: &[ExtensionTarget<D>],
     ) -> GlweCtExp<N, K, ExtensionTarget<D>> {
         let num_limbs = ceil_div_usize(F::BITS, LOGB);
         let limbs = glwe_poly.eval_decompose_ext::<F, LOGB>(
@@ -101,12 +126,27 @@ impl<const D: usize, const N: usize, const K: usize, const ELL: usize>
             yield_constr,
             filter,
             coeffs_bit_dec,
+            coeffs_neg_bit_dec,
             num_limbs,
         );
-        let limbs_hat = &limbs[num_limbs - ELL..]
-            .iter()
-            .map(|limb| eval_ntt_forward_ext(builder, &limb))
+        for (committed, limb) in top_limbs.chunks(N).zip_eq(&limbs[num_limbs - ELL..]) {
+            for (c, l) in committed.iter().zip(limb) {
+                let constr = builder.sub_extension(*c, *l);
+                yield_constr.constraint(builder, constr);
+            }
+        }
+        let limbs_hat = top_limbs
+            .chunks(N)
+            .map(|limb| eval_ntt_forward_ext(builder, &limb.to_vec()))
             .collect();
+        self.eval_mul_hat_ext(builder, &limbs_hat)
+    }
+
+    pub fn eval_mul_hat_ext<F: RichField + Extendable<D>>(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        limbs_hat: &Vec<Vec<ExtensionTarget<D>>>,
+    ) -> GlweCtExp<N, K, ExtensionTarget<D>> {
         let range: [usize; K] = core::array::from_fn(|i| i);
         let polys = range.map(|index| GlwePolyExp {
             coeffs: eval_vec_inner_ext(builder, limbs_hat, &self.get_row(index))
@@ -165,10 +205,14 @@ impl<
             neg_coeffs_bit_dec.clone(),
             num_limbs,
         );
-        let limbs_hat = &limbs[num_limbs - ELL..]
+        let limbs_hat = limbs[num_limbs - ELL..]
             .iter()
             .map(|limb| ntt_forward_native(&limb))
             .collect();
+        self.mul_hat(&limbs_hat)
+    }
+
+    pub fn mul_hat(&self, limbs_hat: &Vec<Vec<F>>) -> GlweCtNative<F, D, N, K> {
         let range: [usize; K] = core::array::from_fn(|i| i);
         let polys = range.map(|index| GlwePolyNative {
             coeffs: vec_inner_native(limbs_hat, &self.get_row(index))
@@ -12,7 +12,7 @@ use crate::vtfhe::{crypto::glwe::Glwe, NUM_BITS};
 
 use super::glwe_poly::{GlwePolyExp, GlwePolyNative};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct GlweCtExp<const N: usize, const K: usize, T> {
     pub polys: [GlwePolyExp<N, T>; K],
 }
@@ -7,7 +7,6 @@ use plonky2::field::types::Field as PlonkyField;
 use plonky2::hash::hash_types::RichField;
 use plonky2::iop::ext_target::ExtensionTarget;
 use plonky2::plonk::circuit_builder::CircuitBuilder;
-use rand::random;
 use starky::constraint_consumer::{ConstraintConsumer, RecursiveConstraintConsumer};
 
 use crate::ntt::ntt_backward_native;
@@ -21,6 +20,64 @@ pub const MODULUS_U8: [u8; 64] = [
     1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
     1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
 ];
+
+// p = 2^64 - 2^32 + 1: the top half of MODULUS_U8 is all ones and the bottom half encodes 1,
+// so a 64 bit value is >= p exactly when its top half matches the modulus and its bottom half
+// is nonzero. The top half of the bits can only fall short of the modulus, so it matches when
+// the sum of the differences is zero.
+fn modulus_hi_diff<P: PackedField>(bits: &[P; NUM_BITS]) -> P {
+    bits[NUM_BITS / 2..]
+        .iter()
+        .zip(&MODULUS_U8[NUM_BITS / 2..])
+        .map(|(bit, p_bit)| P::from(P::Scalar::from_canonical_u8(*p_bit)) - *bit)
+        .sum()
+}
+
+/// Witness for `eval_canonical_bits`: the inverse of the difference between the top half of
+/// `bits` and the one of the modulus, or zero if they match.
+pub fn canonical_bits_inv_native<F: RichField>(bits: &[F; NUM_BITS]) -> F {
+    let hi_diff: F = bits[NUM_BITS / 2..]
+        .iter()
+        .zip(&MODULUS_U8[NUM_BITS / 2..])
+        .map(|(bit, p_bit)| F::from_canonical_u8(*p_bit) - *bit)
+        .sum();
+    hi_diff.try_inverse().unwrap_or(F::ZERO)
+}
+
+/// Constrains the (bit checked) `bits` to encode a value < p, so that the decomposition of a
+/// field element is unique. Zero bits satisfy it, so padding rows need no filter.
+pub fn eval_canonical_bits<P: PackedField>(
+    yield_constr: &mut ConstraintConsumer<P>,
+    bits: &[P; NUM_BITS],
+    hi_diff_inv: P,
+) {
+    let hi_diff = modulus_hi_diff(bits);
+    let lo_sum: P = bits[..NUM_BITS / 2].iter().copied().sum();
+    // if the top half matches the modulus, the bottom half has to be zero
+    yield_constr.constraint(lo_sum * (P::ONES - hi_diff * hi_diff_inv));
+}
+
+pub fn eval_canonical_bits_ext<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    yield_constr: &mut RecursiveConstraintConsumer<F, D>,
+    bits: &[ExtensionTarget<D>; NUM_BITS],
+    hi_diff_inv: ExtensionTarget<D>,
+) {
+    let mut hi_diff = builder.zero_extension();
+    for (bit, p_bit) in bits[NUM_BITS / 2..].iter().zip(&MODULUS_U8[NUM_BITS / 2..]) {
+        let p_bit = builder.constant_extension(F::Extension::from_canonical_u8(*p_bit));
+        let diff = builder.sub_extension(p_bit, *bit);
+        hi_diff = builder.add_extension(hi_diff, diff);
+    }
+    let lo_sum = builder.add_many_extension(&bits[..NUM_BITS / 2]);
+
+    let one = builder.one_extension();
+    let not_matching = builder.mul_extension(hi_diff, hi_diff_inv);
+    let matching = builder.sub_extension(one, not_matching);
+    let constr = builder.mul_extension(lo_sum, matching);
+    yield_constr.constraint(builder, constr);
+}
+
 pub fn eval_plus_or_minus<P: PackedField>(
     yield_constr: &mut ConstraintConsumer<P>,
     b: P,
@@ -47,84 +104,6 @@ pub fn eval_plus_or_minus_ext<F: RichField + Extendable<D>, const D: usize>(
     builder.mul_add_extension(b, diff, x)
 }
 
-pub fn eval_two_s_comp<P: PackedField>(bits: Vec<P>) -> Vec<P> {
-    let one = P::ONES;
-
-    let one_s = bits.into_iter().map(|bit| one - bit).collect_vec();
-
-    let mut carry = one;
-    let mut out = Vec::new();
-
-    for bit in one_s.into_iter() {
-        let (sum, c_out) = eval_half_adder(bit, carry);
-        carry = c_out;
-        out.push(sum);
-    }
-    out
-}
-pub fn eval_two_s_comp_ext<F: RichField + Extendable<D>, const D: usize>(
-    builder: &mut CircuitBuilder<F, D>,
-    bits: Vec<ExtensionTarget<D>>,
-) -> Vec<ExtensionTarget<D>> {
-    let one = builder.one_extension();
-    let one_s = bits
-        .into_iter()
-        .map(|bit| builder.sub_extension(one, bit))
-        .collect_vec();
-
-    let mut carry = one;
-    let mut out = Vec::new();
-
-    for bit in one_s.into_iter() {
-        let (sum, c_out) = eval_half_adder_ext(builder, bit, carry);
-        carry = c_out;
-        out.push(sum);
-    }
-
-    out
-}
-
-//Returns -int mod p = (p - (int mod p))
-pub fn eval_neg_ele<P: PackedField>(int: Vec<P>) -> Vec<P> {
-    let neg_int = eval_two_s_comp(int);
-    let modulus = MODULUS_U8.map(|bit| P::from(P::Scalar::from_canonical_u8(bit)));
-
-    assert_eq!(neg_int.len(), modulus.len());
-
-    let mut c_in = P::ZEROS;
-
-    let mut neg_int_mod = Vec::new();
-
-    for (i, bit) in neg_int.into_iter().enumerate() {
-        let (sum, c_out) = eval_full_adder(bit, modulus[i], c_in);
-        neg_int_mod.push(sum);
-        c_in = c_out;
-    }
-    neg_int_mod
-}
-
-pub fn eval_neg_ele_ext<F: RichField + Extendable<D>, const D: usize>(
-    builder: &mut CircuitBuilder<F, D>,
-    int: Vec<ExtensionTarget<D>>,
-) -> Vec<ExtensionTarget<D>> {
-    let neg_int = eval_two_s_comp_ext(builder, int);
-    let modulus =
-        MODULUS_U8.map(|bit| builder.constant_extension(F::Extension::from_canonical_u8(bit)));
-
-    assert_eq!(neg_int.len(), modulus.len());
-
-    let mut c_in = builder.zero_extension();
-
-    let mut neg_int_mod = Vec::new();
-
-    for (i, bit) in neg_int.into_iter().enumerate() {
-        let (sum, c_out) = eval_full_adder_ext(builder, bit, modulus[i], c_in);
-        neg_int_mod.push(sum);
-        c_in = c_out;
-    }
-    neg_int_mod
-}
-
 //select flag = 1 -> vec_a
 // s * (a - b) + b
 pub fn eval_select_vec<P: PackedField>(select_flag: P, vec_a: Vec<P>, vec_b: Vec<P>) -> Vec<P> {
@@ -156,13 +135,19 @@ pub fn eval_decompose_coeff<P: PackedField, const LOGB: usize>(
     filter: P,
     x: P,
     x_bit_dec: &[P; NUM_BITS],
+    neg_x_bit_dec: &[P; NUM_BITS],
     num_limbs: usize,
 ) -> Vec<P> {
     // assert_eq!(x_bit_dec.len(), num_limbs * LOGB);
     let cal_x = eval_le_sum(yield_constr, x_bit_dec.to_vec());
     yield_constr.constraint(filter * (x - cal_x));
 
-    let neg_x_bit_dec = eval_neg_ele(x_bit_dec.to_vec());
+    // the bits of -x are committed as well, deriving them from the bits of x
+    // with a chain of adders makes the degree of the limbs grow with NUM_BITS
+    let cal_neg_x = eval_le_sum(yield_constr, neg_x_bit_dec.to_vec());
+    yield_constr.constraint(filter * (x + cal_neg_x));
+
+    let neg_x_bit_dec = neg_x_bit_dec.to_vec();
     let sgn = &x_bit_dec.last().unwrap();
 
     let bits_centered = eval_select_vec(**sgn, neg_x_bit_dec, x_bit_dec.to_vec());
@@ -187,6 +172,7 @@ pub fn eval_decompose_coeff_ext<F: RichField + Extendable<D>, const D: usize, co
     filter: ExtensionTarget<D>,
     x: ExtensionTarget<D>,
     x_bit_dec: &[ExtensionTarget<D>; NUM_BITS],
+    neg_x_bit_dec: &[ExtensionTarget<D>; NUM_BITS],
     num_limbs: usize,
 ) -> Vec<ExtensionTarget<D>> {
     // assert_eq!(x_bit_dec.len(), num_limbs * LOGB);
@@ -195,7 +181,12 @@ pub fn eval_decompose_coeff_ext<F: RichField + Extendable<D>, const D: usize, co
     let constr = builder.mul_extension(filter, diff);
     yield_constr.constraint(builder, constr);
 
-    let neg_x_bit_dec = eval_neg_ele_ext(builder, x_bit_dec.to_vec());
+    let cal_neg_x = eval_le_sum_ext(builder, yield_constr, neg_x_bit_dec.to_vec());
+    let sum = builder.add_extension(x, cal_neg_x);
+    let constr = builder.mul_extension(filter, sum);
+    yield_constr.constraint(builder, constr);
+
+    let neg_x_bit_dec = neg_x_bit_dec.to_vec();
     let sgn = &x_bit_dec.last().unwrap();
 
     let bits_centered = eval_select_vec_ext(builder, **sgn, neg_x_bit_dec, x_bit_dec.to_vec());
@@ -220,59 +211,37 @@ pub fn eval_decompose_coeff_ext<F: RichField + Extendable<D>, const D: usize, co
         .collect()
 }
 
-//TODO: Add constrain a , b, c_in are bits
-fn eval_half_adder<P: PackedField>(a: P, b: P) -> (P, P) {
-    let two = P::from(P::Scalar::from_canonical_usize(2));
-    let c_out = a * b;
-    let sum = (a + b) - (two * c_out);
-
-    (sum, c_out)
-}
-fn eval_half_adder_ext<F: RichField + Extendable<D>, const D: usize>(
-    builder: &mut CircuitBuilder<F, D>,
-    a: ExtensionTarget<D>,
-    b: ExtensionTarget<D>,
-) -> (ExtensionTarget<D>, ExtensionTarget<D>) {
-    let two = builder.constant_extension(F::Extension::from_canonical_u8(2));
-    let c_out = builder.mul_extension(a, b);
-    let a_or_b = builder.add_extension(a, b);
-    let two_and_c_out = builder.mul_extension(two, c_out);
-    let sum = builder.sub_extension(a_or_b, two_and_c_out);
-    (sum, c_out)
-}
-//TODO: Add constrain a , b, c_in are bits
-fn eval_full_adder<P: PackedField>(a: P, b: P, c_in: P) -> (P, P) {
-    let two = P::Scalar::from_canonical_u8(2);
-    let a_and_b = a * b;
-    let a_xor_b = a + b - two * a_and_b;
-
-    let sum = a_xor_b + c_in - two * a_xor_b * c_in;
-    let c_out = (a_xor_b * c_in) + a_and_b;
-
-    (sum, c_out)
+/// Constrains the balanced base 2^LOGB `limbs` (little endian) to recompose to `x`.
+/// The limbs are range checked by the caller, with a lookup.
+pub fn eval_recompose_coeff<P: PackedField, const LOGB: usize>(
+    yield_constr: &mut ConstraintConsumer<P>,
+    filter: P,
+    x: P,
+    limbs: &[P],
+) {
+    let base = P::Scalar::from_canonical_u64(1u64 << LOGB);
+    let cal_x = limbs
+        .iter()
+        .rev()
+        .fold(P::ZEROS, |acc, limb| acc * base + *limb);
+    yield_constr.constraint(filter * (x - cal_x));
 }
 
-fn eval_full_adder_ext<F: RichField + Extendable<D>, const D: usize>(
+pub fn eval_recompose_coeff_ext<F: RichField + Extendable<D>, const D: usize, const LOGB: usize>(
     builder: &mut CircuitBuilder<F, D>,
-    a: ExtensionTarget<D>,
-    b: ExtensionTarget<D>,
-    c_in: ExtensionTarget<D>,
-) -> (ExtensionTarget<D>, ExtensionTarget<D>) {
-    let two = builder.constant_extension(F::Extension::from_canonical_u8(2));
-
-    let a_or_b = builder.add_extension(a, b);
-    let a_and_b = builder.mul_extension(a, b);
-    let two_and_a_b = builder.mul_extension(two, a_and_b);
-    let a_xor_b = builder.sub_extension(a_or_b, two_and_a_b);
-
-    let a_xor_b_or_cin = builder.add_extension(a_xor_b, c_in);
-    let a_xor_b_or_cin_and_c_in = builder.mul_extension(a_xor_b, c_in);
-    let two_or_a_xor_b_and_cin = builder.mul_extension(two, a_xor_b_or_cin_and_c_in);
-    let sum = builder.sub_extension(a_xor_b_or_cin, two_or_a_xor_b_and_cin);
-
-    let c_out = builder.mul_add_extension(a_xor_b, c_in, a_and_b);
-
-    (sum, c_out)
+    yield_constr: &mut RecursiveConstraintConsumer<F, D>,
+    filter: ExtensionTarget<D>,
+    x: ExtensionTarget<D>,
+    limbs: &[ExtensionTarget<D>],
+) {
+    let base = builder.constant_extension(F::Extension::from_canonical_u64(1u64 << LOGB));
+    let mut cal_x = builder.zero_extension();
+    for limb in limbs.iter().rev() {
+        cal_x = builder.mul_add_extension(base, cal_x, *limb);
+    }
+    let diff = builder.sub_extension(x, cal_x);
+    let constr = builder.mul_extension(filter, diff);
+    yield_constr.constraint(builder, constr);
 }
 
 #[derive(Debug, Clone)]
@@ -328,6 +297,7 @@ impl<const N: usize, P: PackedField> GlwePolyExp<N, P> {
         yield_constr: &mut ConstraintConsumer<P>,
         filter: P,
         coeffs_bit_dec: &[[P; NUM_BITS]; N],
+        coeffs_neg_bit_dec: &[[P; NUM_BITS]; N],
         num_limbs: usize,
     ) -> Vec<Vec<P>> {
         let decomps = self.coeffs.iter().enumerate().map(|(i, xi)| {
@@ -336,6 +306,7 @@ impl<const N: usize, P: PackedField> GlwePolyExp<N, P> {
                 filter,
                 *xi,
                 &coeffs_bit_dec[i],
+                &coeffs_neg_bit_dec[i],
                 num_limbs,
             )
         });
@@ -347,6 +318,24 @@ impl<const N: usize, P: PackedField> GlwePolyExp<N, P> {
         }
         acc
     }
+
+    /// Same as `eval_decompose`, from the committed limbs of the coefficients (coefficient major).
+    pub fn eval_decompose_limbs<const LOGB: usize>(
+        &self,
+        yield_constr: &mut ConstraintConsumer<P>,
+        filter: P,
+        coeffs_limbs: &[P],
+    ) -> Vec<Vec<P>> {
+        let num_limbs = coeffs_limbs.len() / N;
+        let mut acc = vec![Vec::new(); num_limbs];
+        for (xi, limbs) in self.coeffs.iter().zip_eq(coeffs_limbs.chunks(num_limbs)) {
+            eval_recompose_coeff::<P, LOGB>(yield_constr, filter, *xi, limbs);
+            for i in 0..num_limbs {
+                acc[i].push(limbs[i])
+            }
+        }
+        acc
+    }
 }
 impl<const D: usize, const N: usize> GlwePolyExp<N, ExtensionTarget<D>> {
     pub fn flatten_ext(&self) -> Vec<ExtensionTarget<D>> {
@@ -409,6 +398,7 @@ impl<const D: usize, const N: usize> GlwePolyExp<N, ExtensionTarget<D>> {
         yield_constr: &mut RecursiveConstraintConsumer<F, D>,
         filter: ExtensionTarget<D>,
         coeffs_bit_dec: &[[ExtensionTarget<D>; NUM_BITS]; N],
+        coeffs_neg_bit_dec: &[[ExtensionTarget<D>; NUM_BITS]; N],
         num_limbs: usize,
     ) -> Vec<Vec<ExtensionTarget<D>>> {
         let decomps = self.coeffs.iter().enumerate().map(|(i, xi)| {
@@ -418,6 +408,7 @@ impl<const D: usize, const N: usize> GlwePolyExp<N, ExtensionTarget<D>> {
                 filter,
                 *xi,
                 &coeffs_bit_dec[i],
+                &coeffs_neg_bit_dec[i],
                 num_limbs,
             )
         });
@@ -429,6 +420,24 @@ impl<const D: usize, const N: usize> GlwePolyExp<N, ExtensionTarget<D>> {
         }
         acc
     }
+
+    pub fn eval_decompose_limbs_ext<F: RichField + Extendable<D>, const LOGB: usize>(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        yield_constr: &mut RecursiveConstraintConsumer<F, D>,
+        filter: ExtensionTarget<D>,
+        coeffs_limbs: &[ExtensionTarget<D>],
+    ) -> Vec<Vec<ExtensionTarget<D>>> {
+        let num_limbs = coeffs_limbs.len() / N;
+        let mut acc = vec![Vec::new(); num_limbs];
+        for (xi, limbs) in self.coeffs.iter().zip_eq(coeffs_limbs.chunks(num_limbs)) {
+            eval_recompose_coeff_ext::<F, D, LOGB>(builder, yield_constr, filter, *xi, limbs);
+            for i in 0..num_limbs {
+                acc[i].push(limbs[i])
+            }
+        }
+        acc
+    }
 }
 pub fn le_sum_native<F: RichField + Extendable<D>, const D: usize>(bits: Vec<F>) -> F {
     let mut rev_bits = bits.into_iter().rev();
@@ -442,6 +451,11 @@ pub fn le_sum_native<F: RichField + Extendable<D>, const D: usize>(bits: Vec<F>)
     sum
 }
 
+/// Number of base 2^LOGB limbs of a 64 bit decomposition.
+pub const fn num_limbs<const LOGB: usize>() -> usize {
+    NUM_BITS.div_ceil(LOGB)
+}
+
 pub fn decompose_native<F: RichField + Extendable<D>, const D: usize, const LOGB: usize>(
     bits: [F; NUM_BITS],
     neg_bits: [F; NUM_BITS],
@@ -553,3 +567,40 @@ impl<F: RichField + Extendable<D>, const D: usize, const N: usize> GlwePolyNativ
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::field::types::{Field64, PrimeField64, Sample};
+    use rand::random;
+
+    use super::*;
+    use crate::vtfhe::starky_ct::glwe_ct::decimal_to_binary;
+
+    #[test]
+    fn test_canonical_bits() {
+        type F = GoldilocksField;
+        const D: usize = 2;
+
+        let check = |value: u64| {
+            let bits = decimal_to_binary::<F, D>(value);
+            let mut yield_constr = ConstraintConsumer::new(vec![F::rand()], F::ONE, F::ONE, F::ONE);
+            eval_canonical_bits(&mut yield_constr, &bits, canonical_bits_inv_native(&bits));
+            yield_constr.accumulators()[0] == F::ZERO
+        };
+
+        for value in [
+            0,
+            1,
+            F::ORDER - 1,
+            F::ORDER - 2,
+            F::rand().to_canonical_u64(),
+        ] {
+            assert!(check(value));
+        }
+        // the same field elements, encoded with values >= p
+        for value in [0, 1, random::<u64>() % ((1 << 32) - 1)] {
+            assert!(!check(value + F::ORDER));
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use core::fmt;
 
-use ggsw_ct::{GgswCtExp, GgswCtNative};
+use ggsw_ct::{GgswCtExp, GgswCtNative, GlweDecomposition};
 use glev_ct::{GlevCtExp, GlevCtNative};
 use glwe_ct::{decimal_to_binary, GlweCtExp, GlweCtNative};
 use glwe_poly::{GlwePolyExp, GlwePolyNative};
@@ -14,15 +14,19 @@ use plonky2::{
 use starky::constraint_consumer::{ConstraintConsumer, RecursiveConstraintConsumer};
 
 use super::{
-    eval_glwe_select, eval_glwe_select_ext, eval_rotate_glwe, eval_rotate_glwe_ext,
-    rotate_glwe_native, NUM_BITS,
+    eval_glwe_select, eval_glwe_select_ext, eval_rotate_glwe_stages, eval_rotate_glwe_stages_ext,
+    num_rotation_stages, rotate_glwe_native, NUM_BITS,
 };
 
 pub mod ggsw_ct;
 pub mod glev_ct;
 pub mod glwe_ct;
 pub mod glwe_poly;
+pub mod ntt;
+pub mod poseidon;
+pub mod range_check;
 pub mod vpbs;
+pub mod vpbs_multi_row;
 
 pub fn generate_build_circuit_input<
     F: RichField + Extendable<D>,
@@ -37,7 +41,11 @@ pub fn generate_build_circuit_input<
     ggsw_ct: &GgswCtNative<F, D, N, K, ELL>,
     mask_ele: F,
     counter: F,
-) -> (GlweCtNative<F, D, N, K>, [[[F; 64]; N]; K]) {
+) -> (
+    GlweCtNative<F, D, N, K>,
+    [[[F; NUM_BITS]; N]; K],
+    [[[F; NUM_BITS]; N]; K],
+) {
     let first_neg_mask = if counter == F::ONE {
         -mask_ele
     } else {
@@ -76,7 +84,32 @@ pub fn generate_build_circuit_input<
         cmux_or_exprod
     };
 
-    (current_acc_out, xprod_in_pos_bit_dec)
+    (current_acc_out, xprod_in_pos_bit_dec, xprod_in_neg_bit_dec)
+}
+
+/// Intermediate GLWEs of a step, committed to keep the degree of its constraints at 3.
+pub struct StepColumns<const N: usize, const K: usize, T> {
+    /// Accumulator after each selection of the rotation.
+    pub rotations: Vec<GlweCtExp<N, K, T>>,
+    /// Input of the external product.
+    pub xprod_in: GlweCtExp<N, K, T>,
+    /// Output accumulator.
+    pub acc_out: GlweCtExp<N, K, T>,
+}
+
+impl<const N: usize, const K: usize, T: Clone + fmt::Debug> StepColumns<N, K, T> {
+    pub fn read(lv: &[T], cur_col: &mut usize) -> Self {
+        let rotations = (0..num_rotation_stages::<N>())
+            .map(|_| read_glwe_ct(lv, cur_col))
+            .collect();
+        let xprod_in = read_glwe_ct(lv, cur_col);
+        let acc_out = read_glwe_ct(lv, cur_col);
+        Self {
+            rotations,
+            xprod_in,
+            acc_out,
+        }
+    }
 }
 
 pub fn eval_step_circuit<
@@ -91,7 +124,8 @@ pub fn eval_step_circuit<
     ggsw_ct: GgswCtExp<N, K, ELL, P>,
     mask_element: P,
     mask_ele_bit_dec: [P; NUM_BITS],
-    xprod_in_bit_dec: [[[P; NUM_BITS]; N]; K],
+    xprod_in_dec: GlweDecomposition<N, K, P>,
+    step_columns: StepColumns<N, K, P>,
     non_pad_flag: P,
     is_first_row: P,
     is_last_non_pad_row: P,
@@ -100,21 +134,24 @@ pub fn eval_step_circuit<
 
     let first_negated_mask = is_first_row * (neg_mask - mask_element) + mask_element;
 
-    let shifted_glwe = eval_rotate_glwe(
+    let shifted_glwe = eval_rotate_glwe_stages(
         yield_constr,
         non_pad_flag,
         &current_acc_in,
         first_negated_mask,
         mask_ele_bit_dec,
+        &step_columns.rotations,
     );
 
     let diff_glwe = shifted_glwe.sub(&current_acc_in);
     let xprod_in = eval_glwe_select(is_last_non_pad_row, &current_acc_in, &diff_glwe);
+    eval_glwe_eq(yield_constr, &step_columns.xprod_in, &xprod_in);
+
     let xprod_out = ggsw_ct.eval_external_product::<LOGB>(
         yield_constr,
         non_pad_flag,
-        &xprod_in,
-        xprod_in_bit_dec,
+        &step_columns.xprod_in,
+        xprod_in_dec,
     );
     let cmux_out = xprod_out.add(&current_acc_in);
 
@@ -123,8 +160,9 @@ pub fn eval_step_circuit<
 
     // in the first step (body) we don't apply the full CMUX, just the rotation
     let current_acc_out = eval_glwe_select(is_first_row, &shifted_glwe, &cmux_or_exprod);
+    eval_glwe_eq(yield_constr, &step_columns.acc_out, &current_acc_out);
 
-    current_acc_out
+    step_columns.acc_out
 }
 
 pub fn eval_step_circuit_ext<
@@ -141,7 +179,8 @@ pub fn eval_step_circuit_ext<
     ggsw_ct: GgswCtExp<N, K, ELL, ExtensionTarget<D>>,
     mask_element: ExtensionTarget<D>,
     mask_ele_bit_dec: [ExtensionTarget<D>; NUM_BITS],
-    xprod_in_bit_dec: [[[ExtensionTarget<D>; NUM_BITS]; N]; K],
+    xprod_in_dec: GlweDecomposition<N, K, ExtensionTarget<D>>,
+    step_columns: StepColumns<N, K, ExtensionTarget<D>>,
     non_pad_flag: ExtensionTarget<D>,
     is_first_row: ExtensionTarget<D>,
     is_last_non_pad_row: ExtensionTarget<D>,
@@ -151,23 +190,26 @@ pub fn eval_step_circuit_ext<
     let diff = builder.sub_extension(neg_mask, mask_element);
     let first_negated_mask = builder.mul_add_extension(is_first_row, diff, mask_element);
 
-    let shifted_glwe = eval_rotate_glwe_ext(
+    let shifted_glwe = eval_rotate_glwe_stages_ext(
         builder,
         yield_constr,
         non_pad_flag,
         &current_acc_in,
         first_negated_mask,
         mask_ele_bit_dec,
+        &step_columns.rotations,
     );
 
     let diff_glwe = shifted_glwe.sub_ext(builder, &current_acc_in);
     let xprod_in = eval_glwe_select_ext(builder, is_last_non_pad_row, &current_acc_in, &diff_glwe);
+    eval_glwe_eq_ext(builder, yield_constr, &step_columns.xprod_in, &xprod_in);
+
     let xprod_out = ggsw_ct.eval_external_product_ext::<F, LOGB>(
         builder,
         yield_constr,
         non_pad_flag,
-        &xprod_in,
-        xprod_in_bit_dec,
+        &step_columns.xprod_in,
+        xprod_in_dec,
     );
     let cmux_out = xprod_out.add_ext(builder, &current_acc_in);
 
@@ -177,8 +219,46 @@ pub fn eval_step_circuit_ext<
     // in the first step (body) we don't apply the full CMUX, just the rotation
     let current_acc_out =
         eval_glwe_select_ext(builder, is_first_row, &shifted_glwe, &cmux_or_exprod);
+    eval_glwe_eq_ext(
+        builder,
+        yield_constr,
+        &step_columns.acc_out,
+        &current_acc_out,
+    );
+
+    step_columns.acc_out
+}
 
-    current_acc_out
+/// Constrains the committed GLWE `committed` to be `expected` on every row.
+fn eval_glwe_eq<P: PackedField, const N: usize, const K: usize>(
+    yield_constr: &mut ConstraintConsumer<P>,
+    committed: &GlweCtExp<N, K, P>,
+    expected: &GlweCtExp<N, K, P>,
+) {
+    for (c, e) in committed.flatten().into_iter().zip(expected.flatten()) {
+        yield_constr.constraint(c - e);
+    }
+}
+
+fn eval_glwe_eq_ext<
+    F: RichField + Extendable<D>,
+    const D: usize,
+    const N: usize,
+    const K: usize,
+>(
+    builder: &mut CircuitBuilder<F, D>,
+    yield_constr: &mut RecursiveConstraintConsumer<F, D>,
+    committed: &GlweCtExp<N, K, ExtensionTarget<D>>,
+    expected: &GlweCtExp<N, K, ExtensionTarget<D>>,
+) {
+    for (c, e) in committed
+        .flatten_ext()
+        .into_iter()
+        .zip(expected.flatten_ext())
+    {
+        let constr = builder.sub_extension(c, e);
+        yield_constr.constraint(builder, constr);
+    }
 }
 
 pub fn write_array<F: RichField + Extendable<D>, const D: usize, const N: usize>(
@@ -0,0 +1,361 @@
+//| input | values | is_forward |  layers  |     slots     |
+//|   N   |   N    |      1     | LOGN + 1 | (ELL + 1) * K |
+//
+// Forward and backward NTTs of size N, one butterfly layer per row. An NTT takes LOGN + 1 rows:
+// the row with layers[0] set holds the input in values, and the row with layers[i] set holds the
+// values after i butterfly layers. The input and the direction are kept over the rows of an NTT,
+// so the row with layers[LOGN] set holds the input and the output, which are looked up by
+// `VpbsStark`. The scaling by N^-1 of the backward NTT is done in its last layer.
+//
+// Every NTT is looked up from one of the slots of an external product with GLWE dimension K and
+// ELL levels: the forward NTTs of the top ELL limbs of every polynomial of the input, then the
+// backward NTTs of the output. There is one cross-table lookup per slot, and slots[s] is set on
+// the output row of the NTTs of slot s. A single lookup with several looking columns
+// per row would need helper columns, which starky only supports for constraints of degree 3.
+use std::marker::PhantomData;
+
+use itertools::Itertools;
+use plonky2::{
+    field::{
+        extension::{Extendable, FieldExtension},
+        packed::PackedField,
+        polynomial::PolynomialValues,
+        types::Field,
+    },
+    hash::hash_types::RichField,
+    iop::ext_target::ExtensionTarget,
+    plonk::circuit_builder::CircuitBuilder,
+    util::transpose,
+};
+use starky::{
+    constraint_consumer::{ConstraintConsumer, RecursiveConstraintConsumer},
+    evaluation_frame::{StarkEvaluationFrame, StarkFrame},
+    lookup::{Column, Filter},
+    stark::Stark,
+};
+
+use crate::ntt::{
+    eval_ntt_bw_update, eval_ntt_bw_update_ext, eval_ntt_fw_update, eval_ntt_fw_update_ext,
+    get_params, ntt_bw_update_native, ntt_fw_update_native,
+};
+
+/// Number of NTTs of an external product.
+pub const fn xprod_ntt_slots<const K: usize, const ELL: usize>() -> usize {
+    (ELL + 1) * K
+}
+
+pub const fn ntt_columns<const N: usize, const K: usize, const ELL: usize>() -> usize {
+    2 * N + 2 + N.ilog2() as usize + xprod_ntt_slots::<K, ELL>()
+}
+
+/// STARK of the NTTs of external products of ring dimension `N`, GLWE dimension `K` and `ELL`
+/// levels. `COLUMNS` is the width of its trace, `ntt_columns::<N, K, ELL>()`.
+#[derive(Clone, Copy)]
+pub struct NttStark<
+    F: RichField + Extendable<D>,
+    const D: usize,
+    const N: usize,
+    const K: usize,
+    const ELL: usize,
+    const COLUMNS: usize,
+> {
+    _phantom: PhantomData<F>,
+}
+
+impl<
+        F: RichField + Extendable<D>,
+        const D: usize,
+        const N: usize,
+        const K: usize,
+        const ELL: usize,
+        const COLUMNS: usize,
+    > NttStark<F, D, N, K, ELL, COLUMNS>
+{
+    const LOGN: usize = N.ilog2() as usize;
+
+    const VALUES_COL: usize = N;
+    const IS_FORWARD_COL: usize = 2 * N;
+    const LAYERS_COL: usize = 2 * N + 1;
+    const SLOTS_COL: usize = Self::LAYERS_COL + Self::LOGN + 1;
+
+    pub fn new() -> Self {
+        assert_eq!(COLUMNS, ntt_columns::<N, K, ELL>());
+        Self {
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Direction, input and output of the NTTs, in this order.
+    pub fn looked_columns() -> Vec<Column<F>> {
+        Column::singles([Self::IS_FORWARD_COL].into_iter().chain(0..2 * N)).collect()
+    }
+
+    /// Selects the rows holding the output of an NTT of `slot`.
+    pub fn looked_filter(slot: usize) -> Filter<F> {
+        Filter::new_simple(Column::single(Self::SLOTS_COL + slot))
+    }
+
+    /// Computes the trace of the NTTs of `ntts`, given by their slot, direction and input.
+    pub fn generate_trace(&self, ntts: &[(usize, bool, Vec<F>)]) -> Vec<PolynomialValues<F>> {
+        let params = get_params(N);
+        let n_inv = F::from_canonical_u64(params.ninv);
+        let num_rows = (ntts.len() * (Self::LOGN + 1)).next_power_of_two().max(8);
+
+        let mut trace_rows = Vec::with_capacity(num_rows);
+        for (slot, is_forward, input) in ntts {
+            assert!(
+                *slot < xprod_ntt_slots::<K, ELL>(),
+                "NTT slot out of range."
+            );
+            assert_eq!(input.len(), N, "NTT input does not match N={N}.");
+            let mut values = input.clone();
+            for layer in 0..=Self::LOGN {
+                if layer > 0 {
+                    values = if *is_forward {
+                        ntt_fw_update_native(&values, params, 1 << (layer - 1))
+                    } else {
+                        ntt_bw_update_native(&values, params, 1 << (Self::LOGN - layer))
+                    };
+                    if !is_forward && layer == Self::LOGN {
+                        values = values.into_iter().map(|v| v * n_inv).collect();
+                    }
+                }
+
+                let mut row = vec![F::ZERO; COLUMNS];
+                row[..N].copy_from_slice(input);
+                row[Self::VALUES_COL..Self::IS_FORWARD_COL].copy_from_slice(&values);
+                row[Self::IS_FORWARD_COL] = F::from_bool(*is_forward);
+                row[Self::LAYERS_COL + layer] = F::ONE;
+                if layer == Self::LOGN {
+                    row[Self::SLOTS_COL + slot] = F::ONE;
+                }
+                trace_rows.push(row);
+            }
+        }
+
+        // the padding rows keep the input and the direction of the last NTT
+        let mut padding_row = trace_rows.last().cloned().unwrap_or(vec![F::ZERO; COLUMNS]);
+        padding_row[Self::LAYERS_COL..].fill(F::ZERO);
+        trace_rows.resize(num_rows, padding_row);
+
+        transpose(&trace_rows)
+            .into_iter()
+            .map(PolynomialValues::new)
+            .collect()
+    }
+}
+
+impl<
+        F: RichField + Extendable<D>,
+        const D: usize,
+        const N: usize,
+        const K: usize,
+        const ELL: usize,
+        const COLUMNS: usize,
+    > Stark<F, D> for NttStark<F, D, N, K, ELL, COLUMNS>
+{
+    type EvaluationFrame<FE, P, const D2: usize>
+        = StarkFrame<P, P::Scalar, COLUMNS, 0>
+    where
+        FE: FieldExtension<D2, BaseField = F>,
+        P: PackedField<Scalar = FE>;
+
+    type EvaluationFrameTarget = StarkFrame<ExtensionTarget<D>, ExtensionTarget<D>, COLUMNS, 0>;
+
+    fn eval_packed_generic<FE, P, const D2: usize>(
+        &self,
+        vars: &Self::EvaluationFrame<FE, P, D2>,
+        yield_constr: &mut ConstraintConsumer<P>,
+    ) where
+        FE: FieldExtension<D2, BaseField = F>,
+        P: PackedField<Scalar = FE>,
+    {
+        let lv = vars.get_local_values();
+        let nv = vars.get_next_values();
+        let params = get_params(N);
+        let n_inv = P::Scalar::from_canonical_u64(params.ninv);
+        let one = P::ONES;
+
+        let input = &lv[..N];
+        let values = &lv[Self::VALUES_COL..Self::IS_FORWARD_COL];
+        let is_forward = lv[Self::IS_FORWARD_COL];
+        let layers = &lv[Self::LAYERS_COL..Self::SLOTS_COL];
+        let slots = &lv[Self::SLOTS_COL..];
+
+        // a row is in at most one layer, and padding rows are in none
+        let num_layers: P = layers.iter().copied().sum();
+        for flag in layers.iter().chain([&is_forward, &num_layers]) {
+            yield_constr.constraint(*flag * *flag - *flag);
+        }
+
+        // only output rows are looked up
+        let output = layers[Self::LOGN];
+        for slot in slots {
+            yield_constr.constraint(*slot * *slot - *slot);
+            yield_constr.constraint(*slot * (one - output));
+        }
+
+        // an NTT starts from its input and goes through all the layers
+        for (value, x) in values.iter().zip(input) {
+            yield_constr.constraint(layers[0] * (*value - *x));
+        }
+        for layer in 1..=Self::LOGN {
+            yield_constr.constraint_first_row(layers[layer]);
+            yield_constr.constraint_transition(nv[Self::LAYERS_COL + layer] - layers[layer - 1]);
+        }
+        for layer in &layers[..Self::LOGN] {
+            yield_constr.constraint_last_row(*layer);
+        }
+
+        // the input and the direction are kept until the next NTT
+        let same_ntt = one - nv[Self::LAYERS_COL];
+        yield_constr.constraint_transition(same_ntt * (nv[Self::IS_FORWARD_COL] - is_forward));
+        for (i, x) in input.iter().enumerate() {
+            yield_constr.constraint_transition(same_ntt * (nv[i] - *x));
+        }
+
+        let values = values.to_vec();
+        for layer in 1..=Self::LOGN {
+            let fw = eval_ntt_fw_update(&values, params, 1 << (layer - 1));
+            let mut bw = eval_ntt_bw_update(&values, params, 1 << (Self::LOGN - layer));
+            if layer == Self::LOGN {
+                bw = bw.into_iter().map(|v| v * n_inv).collect();
+            }
+
+            let next_layer = nv[Self::LAYERS_COL + layer];
+            for (i, (fw, bw)) in fw.into_iter().zip_eq(bw).enumerate() {
+                let expected = is_forward * (fw - bw) + bw;
+                yield_constr
+                    .constraint_transition(next_layer * (nv[Self::VALUES_COL + i] - expected));
+            }
+        }
+    }
+
+    fn eval_ext_circuit(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        vars: &Self::EvaluationFrameTarget,
+        yield_constr: &mut RecursiveConstraintConsumer<F, D>,
+    ) {
+        let lv = vars.get_local_values();
+        let nv = vars.get_next_values();
+        let params = get_params(N);
+        let n_inv = F::Extension::from_canonical_u64(params.ninv);
+        let one = builder.one_extension();
+
+        let input = &lv[..N];
+        let values = &lv[Self::VALUES_COL..Self::IS_FORWARD_COL];
+        let is_forward = lv[Self::IS_FORWARD_COL];
+        let layers = &lv[Self::LAYERS_COL..Self::SLOTS_COL];
+        let slots = &lv[Self::SLOTS_COL..];
+
+        // a row is in at most one layer, and padding rows are in none
+        let num_layers = builder.add_many_extension(layers);
+        for flag in layers.iter().chain([&is_forward, &num_layers]) {
+            let constr = builder.mul_sub_extension(*flag, *flag, *flag);
+            yield_constr.constraint(builder, constr);
+        }
+
+        // only output rows are looked up
+        let not_output = builder.sub_extension(one, layers[Self::LOGN]);
+        for slot in slots {
+            let constr = builder.mul_sub_extension(*slot, *slot, *slot);
+            yield_constr.constraint(builder, constr);
+            let constr = builder.mul_extension(*slot, not_output);
+            yield_constr.constraint(builder, constr);
+        }
+
+        // an NTT starts from its input and goes through all the layers
+        for (value, x) in values.iter().zip(input) {
+            let diff = builder.sub_extension(*value, *x);
+            let constr = builder.mul_extension(layers[0], diff);
+            yield_constr.constraint(builder, constr);
+        }
+        for layer in 1..=Self::LOGN {
+            yield_constr.constraint_first_row(builder, layers[layer]);
+            let constr = builder.sub_extension(nv[Self::LAYERS_COL + layer], layers[layer - 1]);
+            yield_constr.constraint_transition(builder, constr);
+        }
+        for layer in &layers[..Self::LOGN] {
+            yield_constr.constraint_last_row(builder, *layer);
+        }
+
+        // the input and the direction are kept until the next NTT
+        let same_ntt = builder.sub_extension(one, nv[Self::LAYERS_COL]);
+        let diff = builder.sub_extension(nv[Self::IS_FORWARD_COL], is_forward);
+        let constr = builder.mul_extension(same_ntt, diff);
+        yield_constr.constraint_transition(builder, constr);
+        for (i, x) in input.iter().enumerate() {
+            let diff = builder.sub_extension(nv[i], *x);
+            let constr = builder.mul_extension(same_ntt, diff);
+            yield_constr.constraint_transition(builder, constr);
+        }
+
+        let values = values.to_vec();
+        for layer in 1..=Self::LOGN {
+            let fw = eval_ntt_fw_update_ext(builder, &values, params, 1 << (layer - 1));
+            let mut bw =
+                eval_ntt_bw_update_ext(builder, &values, params, 1 << (Self::LOGN - layer));
+            if layer == Self::LOGN {
+                let n_inv = builder.constant_extension(n_inv);
+                bw = bw
+                    .into_iter()
+                    .map(|v| builder.mul_extension(v, n_inv))
+                    .collect();
+            }
+
+            let next_layer = nv[Self::LAYERS_COL + layer];
+            for (i, (fw, bw)) in fw.into_iter().zip_eq(bw).enumerate() {
+                let diff = builder.sub_extension(fw, bw);
+                let expected = builder.mul_add_extension(is_forward, diff, bw);
+                let diff = builder.sub_extension(nv[Self::VALUES_COL + i], expected);
+                let constr = builder.mul_extension(next_layer, diff);
+                yield_constr.constraint_transition(builder, constr);
+            }
+        }
+    }
+
+    fn constraint_degree(&self) -> usize {
+        3
+    }
+
+    fn requires_ctls(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ntt::{ntt_backward_native, ntt_forward_native};
+    use plonky2::field::types::Sample;
+    use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+    use starky::stark_testing::{test_stark_circuit_constraints, test_stark_low_degree};
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    #[test]
+    fn test_ntt_stark() {
+        const N: usize = 8;
+        const K: usize = 1;
+        const ELL: usize = 1;
+        let stark = NttStark::<F, D, N, K, ELL, { ntt_columns::<N, K, ELL>() }>::new();
+
+        test_stark_low_degree(stark).unwrap();
+        test_stark_circuit_constraints::<F, C, _, D>(stark).unwrap();
+
+        let ntts = [(0, true, F::rand_vec(N)), (1, false, F::rand_vec(N))];
+        let trace = stark.generate_trace(&ntts);
+        let output_row = |ntt: usize| {
+            let row = ntt * (N.ilog2() as usize + 1) + N.ilog2() as usize;
+            trace[N..2 * N]
+                .iter()
+                .map(|col| col.values[row])
+                .collect_vec()
+        };
+        assert_eq!(output_row(0), ntt_forward_native::<F, D>(&ntts[0].2));
+        assert_eq!(output_row(1), ntt_backward_native::<F, D>(&ntts[1].2));
+    }
+}
@@ -0,0 +1,227 @@
+/*
+    Poseidon permutation over trace columns, used to chain the same
+    `hash_n_to_hash_no_pad` commitments as the IVC backend.
+    Every S-box input is committed together with its cube, and so is the output of the hash, so
+    all constraints have degree 3. They are not filtered: every row, padding rows included, has to
+    hold the permutations of its own inputs.
+*/
+use itertools::Itertools;
+use plonky2::{
+    field::{
+        extension::{Extendable, FieldExtension},
+        packed::PackedField,
+    },
+    hash::{
+        hash_types::{HashOut, RichField, NUM_HASH_OUT_ELTS},
+        poseidon::{HALF_N_FULL_ROUNDS, N_PARTIAL_ROUNDS, SPONGE_RATE, SPONGE_WIDTH},
+    },
+    iop::ext_target::ExtensionTarget,
+    plonk::circuit_builder::CircuitBuilder,
+};
+use starky::constraint_consumer::{ConstraintConsumer, RecursiveConstraintConsumer};
+
+const N_ROUNDS: usize = 2 * HALF_N_FULL_ROUNDS + N_PARTIAL_ROUNDS;
+
+/// S-box inputs of one permutation and their cubes: all lanes in the full rounds, the first lane
+/// in the partial rounds.
+pub const POSEIDON_COLUMNS: usize = 2 * (2 * HALF_N_FULL_ROUNDS * SPONGE_WIDTH + N_PARTIAL_ROUNDS);
+
+/// Number of permutations needed to hash `num_inputs` elements without padding.
+pub const fn num_hash_perms(num_inputs: usize) -> usize {
+    num_inputs.div_ceil(SPONGE_RATE)
+}
+
+/// Number of columns of a hash of `num_inputs` elements without padding: the permutations and
+/// the hash.
+pub const fn hash_no_pad_columns(num_inputs: usize) -> usize {
+    num_hash_perms(num_inputs) * POSEIDON_COLUMNS + NUM_HASH_OUT_ELTS
+}
+
+fn is_full_round(round: usize) -> bool {
+    !(HALF_N_FULL_ROUNDS..HALF_N_FULL_ROUNDS + N_PARTIAL_ROUNDS).contains(&round)
+}
+
+fn num_sbox_lanes(round: usize) -> usize {
+    if is_full_round(round) {
+        SPONGE_WIDTH
+    } else {
+        1
+    }
+}
+
+pub fn poseidon_native<F: RichField>(
+    input: [F; SPONGE_WIDTH],
+) -> ([F; SPONGE_WIDTH], [F; POSEIDON_COLUMNS]) {
+    let mut state = input;
+    let mut sbox_in = Vec::with_capacity(POSEIDON_COLUMNS);
+
+    for round in 0..N_ROUNDS {
+        F::constant_layer(&mut state, round);
+        for x in state[..num_sbox_lanes(round)].iter_mut() {
+            sbox_in.push(*x);
+            sbox_in.push(x.cube());
+            *x = F::sbox_monomial(*x);
+        }
+        state = F::mds_layer(&state);
+    }
+
+    (state, sbox_in.try_into().unwrap())
+}
+
+/// Same as `PoseidonHash::hash_no_pad`, also returning the columns of the hash.
+pub fn hash_no_pad_native<F: RichField>(inputs: &[F]) -> (HashOut<F>, Vec<F>) {
+    let mut state = [F::ZERO; SPONGE_WIDTH];
+    let mut columns = Vec::with_capacity(hash_no_pad_columns(inputs.len()));
+
+    for chunk in inputs.chunks(SPONGE_RATE) {
+        state[..chunk.len()].copy_from_slice(chunk);
+        let sbox_in;
+        (state, sbox_in) = poseidon_native(state);
+        columns.extend(sbox_in);
+    }
+
+    let elements: [F; NUM_HASH_OUT_ELTS] = state[..NUM_HASH_OUT_ELTS].try_into().unwrap();
+    columns.extend(elements);
+    (HashOut { elements }, columns)
+}
+
+pub fn eval_poseidon<
+    F: RichField + Extendable<D>,
+    const D: usize,
+    FE: FieldExtension<D2, BaseField = F>,
+    P: PackedField<Scalar = FE>,
+    const D2: usize,
+>(
+    yield_constr: &mut ConstraintConsumer<P>,
+    input: [P; SPONGE_WIDTH],
+    sbox_in: &[P],
+) -> [P; SPONGE_WIDTH] {
+    assert_eq!(sbox_in.len(), POSEIDON_COLUMNS);
+    let mut sbox_in = sbox_in.chunks(2);
+    let mut state = input;
+
+    for round in 0..N_ROUNDS {
+        F::constant_layer_packed_field(&mut state, round);
+        for x in state[..num_sbox_lanes(round)].iter_mut() {
+            let (committed, cube) = sbox_in.next().unwrap().iter().collect_tuple().unwrap();
+            yield_constr.constraint(*x - *committed);
+            yield_constr.constraint(*cube - committed.square() * *committed);
+            *x = cube.square() * *committed;
+        }
+        state = F::mds_layer_packed_field(&state);
+    }
+
+    state
+}
+
+pub fn eval_poseidon_ext<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    yield_constr: &mut RecursiveConstraintConsumer<F, D>,
+    input: [ExtensionTarget<D>; SPONGE_WIDTH],
+    sbox_in: &[ExtensionTarget<D>],
+) -> [ExtensionTarget<D>; SPONGE_WIDTH] {
+    assert_eq!(sbox_in.len(), POSEIDON_COLUMNS);
+    let mut sbox_in = sbox_in.chunks(2);
+    let mut state = input;
+
+    for round in 0..N_ROUNDS {
+        F::constant_layer_circuit(builder, &mut state, round);
+        for x in state[..num_sbox_lanes(round)].iter_mut() {
+            let (committed, cube) = sbox_in.next().unwrap().iter().collect_tuple().unwrap();
+            let constr = builder.sub_extension(*x, *committed);
+            yield_constr.constraint(builder, constr);
+            let committed_cube = builder.cube_extension(*committed);
+            let constr = builder.sub_extension(*cube, committed_cube);
+            yield_constr.constraint(builder, constr);
+            *x = builder.mul_many_extension([*cube, *cube, *committed]);
+        }
+        state = F::mds_layer_circuit(builder, &state);
+    }
+
+    state
+}
+
+/// Constrains `columns` to be the permutations and the output of `hash_no_pad(inputs)` and
+/// returns the committed hash.
+pub fn eval_hash_no_pad<
+    F: RichField + Extendable<D>,
+    const D: usize,
+    FE: FieldExtension<D2, BaseField = F>,
+    P: PackedField<Scalar = FE>,
+    const D2: usize,
+>(
+    yield_constr: &mut ConstraintConsumer<P>,
+    inputs: &[P],
+    columns: &[P],
+) -> [P; NUM_HASH_OUT_ELTS] {
+    assert_eq!(columns.len(), hash_no_pad_columns(inputs.len()));
+    let (perms, hash) = columns.split_at(columns.len() - NUM_HASH_OUT_ELTS);
+    let mut state = [P::ZEROS; SPONGE_WIDTH];
+
+    for (chunk, sbox_in) in inputs
+        .chunks(SPONGE_RATE)
+        .zip_eq(perms.chunks(POSEIDON_COLUMNS))
+    {
+        state[..chunk.len()].copy_from_slice(chunk);
+        state = eval_poseidon::<F, D, FE, P, D2>(yield_constr, state, sbox_in);
+    }
+
+    for (committed, out) in hash.iter().zip(state) {
+        yield_constr.constraint(*committed - out);
+    }
+
+    hash.try_into().unwrap()
+}
+
+pub fn eval_hash_no_pad_ext<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    yield_constr: &mut RecursiveConstraintConsumer<F, D>,
+    inputs: &[ExtensionTarget<D>],
+    columns: &[ExtensionTarget<D>],
+) -> [ExtensionTarget<D>; NUM_HASH_OUT_ELTS] {
+    assert_eq!(columns.len(), hash_no_pad_columns(inputs.len()));
+    let (perms, hash) = columns.split_at(columns.len() - NUM_HASH_OUT_ELTS);
+    let mut state = [builder.zero_extension(); SPONGE_WIDTH];
+
+    for (chunk, sbox_in) in inputs
+        .chunks(SPONGE_RATE)
+        .zip_eq(perms.chunks(POSEIDON_COLUMNS))
+    {
+        state[..chunk.len()].copy_from_slice(chunk);
+        state = eval_poseidon_ext(builder, yield_constr, state, sbox_in);
+    }
+
+    for (committed, out) in hash.iter().zip(state) {
+        let constr = builder.sub_extension(*committed, out);
+        yield_constr.constraint(builder, constr);
+    }
+
+    hash.try_into().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::field::types::{Field, Sample};
+    use plonky2::hash::poseidon::PoseidonHash;
+    use plonky2::plonk::config::Hasher;
+
+    #[test]
+    fn test_hash_no_pad() {
+        const D: usize = 2;
+        type F = GoldilocksField;
+
+        for num_inputs in [1, 5, 8, 132] {
+            let inputs = F::rand_vec(num_inputs);
+            let (hash, columns) = hash_no_pad_native(&inputs);
+            assert_eq!(hash, PoseidonHash::hash_no_pad(&inputs));
+
+            let mut yield_constr =
+                ConstraintConsumer::new(vec![F::rand()], F::rand(), F::rand(), F::rand());
+            let eval_hash = eval_hash_no_pad::<F, D, F, F, 1>(&mut yield_constr, &inputs, &columns);
+            assert_eq!(eval_hash, hash.elements);
+            assert_eq!(yield_constr.accumulators(), vec![F::ZERO]);
+        }
+    }
+}
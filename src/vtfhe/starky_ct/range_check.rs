@@ -0,0 +1,240 @@
+//|       limbs       | filter | table | frequencies |
+//| NUM_LIMBS * N * K |    1   |   1   |      1      |
+//
+// Range checks the balanced base 2^LOGB limbs of the external product inputs of `VpbsStark`.
+// The rows with the filter set are looked up from the non padding rows of `VpbsStark`, and every
+// limb shifted by 2^(LOGB-1) is looked up in the table, which counts from 0 to 2^LOGB and then
+// repeats its last value. The logUp argument of starky only supports constraints of degree at
+// most 3, which is why the limbs are not checked in `VpbsStark` itself.
+use std::marker::PhantomData;
+
+use itertools::Itertools;
+use plonky2::{
+    field::{
+        extension::{Extendable, FieldExtension},
+        packed::PackedField,
+        polynomial::PolynomialValues,
+        types::Field,
+    },
+    hash::hash_types::RichField,
+    iop::ext_target::ExtensionTarget,
+    plonk::circuit_builder::CircuitBuilder,
+    util::transpose,
+};
+use starky::{
+    constraint_consumer::{ConstraintConsumer, RecursiveConstraintConsumer},
+    evaluation_frame::{StarkEvaluationFrame, StarkFrame},
+    lookup::{Column, Lookup},
+    stark::Stark,
+};
+
+use super::glwe_poly::num_limbs;
+
+pub const fn range_check_columns<const N: usize, const K: usize, const LOGB: usize>() -> usize {
+    num_limbs::<LOGB>() * N * K + 3
+}
+
+/// STARK range checking the limbs of base 2^LOGB of N * K coefficients per row. `COLUMNS` is the
+/// width of its trace, `range_check_columns::<N, K, LOGB>()`.
+#[derive(Clone, Copy)]
+pub struct RangeCheckStark<
+    F: RichField + Extendable<D>,
+    const D: usize,
+    const N: usize,
+    const K: usize,
+    const LOGB: usize,
+    const COLUMNS: usize,
+> {
+    _phantom: PhantomData<F>,
+}
+
+impl<
+        F: RichField + Extendable<D>,
+        const D: usize,
+        const N: usize,
+        const K: usize,
+        const LOGB: usize,
+        const COLUMNS: usize,
+    > RangeCheckStark<F, D, N, K, LOGB, COLUMNS>
+{
+    const NUM_LIMBS: usize = num_limbs::<LOGB>() * N * K;
+
+    pub const FILTER_COL: usize = Self::NUM_LIMBS;
+    const TABLE_COL: usize = Self::FILTER_COL + 1;
+    const FREQUENCIES_COL: usize = Self::FILTER_COL + 2;
+
+    pub fn new() -> Self {
+        assert_eq!(COLUMNS, range_check_columns::<N, K, LOGB>());
+        Self {
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Columns of the limbs, in the order in which they are looked up.
+    pub fn limb_columns() -> Vec<Column<F>> {
+        Column::singles(0..Self::NUM_LIMBS).collect()
+    }
+
+    /// Computes the trace from the limbs of the looked up rows, one vector of limbs per row.
+    pub fn generate_trace(&self, limb_rows: &[Vec<F>]) -> Vec<PolynomialValues<F>> {
+        let table_size = (1 << LOGB) + 1;
+        let num_rows = limb_rows.len().max(table_size).next_power_of_two();
+        let half_base = F::from_canonical_usize(1 << (LOGB - 1));
+
+        let mut trace_rows = vec![vec![F::ZERO; COLUMNS]; num_rows];
+        for (row, limbs) in trace_rows.iter_mut().zip(limb_rows) {
+            row[..Self::NUM_LIMBS].copy_from_slice(limbs);
+            row[Self::FILTER_COL] = F::ONE;
+        }
+
+        // the padding rows are made of zero limbs, which are in the table as well
+        let mut frequencies = vec![0; table_size];
+        for row in trace_rows.iter() {
+            for limb in &row[..Self::NUM_LIMBS] {
+                let value = (*limb + half_base).to_canonical_u64() as usize;
+                assert!(value < table_size, "Limb out of range.");
+                frequencies[value] += 1;
+            }
+        }
+
+        for (i, row) in trace_rows.iter_mut().enumerate() {
+            row[Self::TABLE_COL] = F::from_canonical_usize(i.min(table_size - 1));
+            row[Self::FREQUENCIES_COL] =
+                F::from_canonical_usize(frequencies.get(i).copied().unwrap_or(0));
+        }
+
+        transpose(&trace_rows)
+            .into_iter()
+            .map(PolynomialValues::new)
+            .collect()
+    }
+}
+
+impl<
+        F: RichField + Extendable<D>,
+        const D: usize,
+        const N: usize,
+        const K: usize,
+        const LOGB: usize,
+        const COLUMNS: usize,
+    > Stark<F, D> for RangeCheckStark<F, D, N, K, LOGB, COLUMNS>
+{
+    type EvaluationFrame<FE, P, const D2: usize>
+        = StarkFrame<P, P::Scalar, COLUMNS, 0>
+    where
+        FE: FieldExtension<D2, BaseField = F>,
+        P: PackedField<Scalar = FE>;
+
+    type EvaluationFrameTarget = StarkFrame<ExtensionTarget<D>, ExtensionTarget<D>, COLUMNS, 0>;
+
+    fn eval_packed_generic<FE, P, const D2: usize>(
+        &self,
+        vars: &Self::EvaluationFrame<FE, P, D2>,
+        yield_constr: &mut ConstraintConsumer<P>,
+    ) where
+        FE: FieldExtension<D2, BaseField = F>,
+        P: PackedField<Scalar = FE>,
+    {
+        let lv = vars.get_local_values();
+        let nv = vars.get_next_values();
+
+        let filter = lv[Self::FILTER_COL];
+        yield_constr.constraint(filter * filter - filter);
+
+        // the table counts from 0 to 2^LOGB
+        let table = lv[Self::TABLE_COL];
+        let step = nv[Self::TABLE_COL] - table;
+        yield_constr.constraint_first_row(table);
+        yield_constr.constraint_transition(step * step - step);
+        yield_constr.constraint_last_row(table - P::Scalar::from_canonical_usize(1 << LOGB));
+    }
+
+    fn eval_ext_circuit(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        vars: &Self::EvaluationFrameTarget,
+        yield_constr: &mut RecursiveConstraintConsumer<F, D>,
+    ) {
+        let lv = vars.get_local_values();
+        let nv = vars.get_next_values();
+
+        let filter = lv[Self::FILTER_COL];
+        let constr = builder.mul_sub_extension(filter, filter, filter);
+        yield_constr.constraint(builder, constr);
+
+        // the table counts from 0 to 2^LOGB
+        let table = lv[Self::TABLE_COL];
+        let step = builder.sub_extension(nv[Self::TABLE_COL], table);
+        yield_constr.constraint_first_row(builder, table);
+        let constr = builder.mul_sub_extension(step, step, step);
+        yield_constr.constraint_transition(builder, constr);
+        let table_size = builder.constant_extension(F::Extension::from_canonical_usize(1 << LOGB));
+        let constr = builder.sub_extension(table, table_size);
+        yield_constr.constraint_last_row(builder, constr);
+    }
+
+    fn constraint_degree(&self) -> usize {
+        3
+    }
+
+    fn lookups(&self) -> Vec<Lookup<F>> {
+        // the balanced limbs are shifted into [0, 2^LOGB]
+        let half_base = F::from_canonical_usize(1 << (LOGB - 1));
+        let columns = (0..Self::NUM_LIMBS)
+            .map(|col| Column::linear_combination_with_constant([(col, F::ONE)], half_base))
+            .collect_vec();
+
+        vec![Lookup {
+            filter_columns: vec![None; columns.len()],
+            columns,
+            table_column: Column::single(Self::TABLE_COL),
+            frequencies_column: Column::single(Self::FREQUENCIES_COL),
+        }]
+    }
+
+    fn requires_ctls(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+    use rand::random;
+    use starky::stark_testing::{test_stark_circuit_constraints, test_stark_low_degree};
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    #[test]
+    fn test_range_check_stark() {
+        const N: usize = 8;
+        const K: usize = 2;
+        const LOGB: usize = 5;
+        type S = RangeCheckStark<F, D, N, K, LOGB, { range_check_columns::<N, K, LOGB>() }>;
+        let stark = S::new();
+
+        test_stark_low_degree(stark).unwrap();
+        test_stark_circuit_constraints::<F, C, _, D>(stark).unwrap();
+
+        // the limbs may take both ends of the range
+        let limb_rows = (0..6)
+            .map(|_| {
+                (0..num_limbs::<LOGB>() * N * K)
+                    .map(|_| {
+                        F::from_canonical_usize(random::<usize>() % ((1 << LOGB) + 1))
+                            - F::from_canonical_usize(1 << (LOGB - 1))
+                    })
+                    .collect_vec()
+            })
+            .collect_vec();
+        let trace = stark.generate_trace(&limb_rows);
+        assert_eq!(trace[0].len(), 64);
+        assert_eq!(
+            trace[S::FILTER_COL].values[..7],
+            [F::ONE, F::ONE, F::ONE, F::ONE, F::ONE, F::ONE, F::ZERO]
+        );
+    }
+}
@@ -1,93 +1,311 @@
-//| cur_acc_in |      ggsw_ct     | mask_ele | mask_ele_bit_dec |   xprod_in_bit_dec  | non_pad_flag | is_first_row | is_last_non_pad_row |
-//|    N * K   |  K * K * N * ELL |     1    |     NUM_BITS     |   NUM_BITS * N * K  |       1      |       1      |          1          |
-//|    GLWE    |       GGSW       |
-use crate::{
-    ntt::params::N,
-    vtfhe::{
-        crypto::{compute_bsk, get_testv, ggsw::Ggsw, glwe::Glwe, lwe::encrypt, poly::Poly},
-        starky_ct::{
-            generate_build_circuit_input, ggsw_ct::GgswCtNative, glwe_ct::GlweCtNative,
-            glwe_poly::le_sum_native,
+//| cur_acc_in |      ggsw_ct     | mask_ele | mask_ele_bit_dec | acc_rotations | xprod_in | cur_acc_out |   xprod_in_bit_dec  | xprod_in_neg_bit_dec | xprod_in_top_limbs | xprod_out | bit_dec_hi_diff_inv | bsk_hash_in |      bsk_hash      | lwe_hash_in |      lwe_hash      | counter | non_pad_flag | is_first_row | is_last_non_pad_row |
+//|    N * K   |  K * K * N * ELL |     1    |     NUM_BITS     |  ROT * N * K  |   N * K  |    N * K    |   NUM_BITS * N * K  |   NUM_BITS * N * K   |     ELL * N * K    |   N * K   |    1 + 2 * N * K    |      4      |  BSK_HASH_COLUMNS  |      4      |  LWE_HASH_COLUMNS  |    1    |       1      |       1      |          1          |
+//|    GLWE    |       GGSW       |                                     |   ROT GLWEs   |   GLWE   |     GLWE    |                                             |                    |    GLWE   |
+//
+// Public inputs:
+//| acc_init | acc_out | bsk_hash | lwe_hash |
+//|   N * K  |  N * K  |     4    |     4    |
+//|   GLWE   |   GLWE  |
+//
+// bsk_hash and lwe_hash are chained over the rows from the zero hash, like in the IVC backend:
+// the GGSWs are the dummy GGSW, the BSK and the KSK, the mask elements are ct[n], ct[0..n] and 0.
+// The bsk_hash and lwe_hash columns hold the permutations and the output of the hashes, see
+// `hash_no_pad_columns`, and the padding rows hold the hashes of their zero inputs.
+//
+// acc_rotations holds the accumulator after each of the ROT = log2(N) + 2 selections of the
+// rotation, xprod_in the input of the external product, xprod_in_top_limbs the top ELL balanced
+// limbs of its polynomials (polynomial major) and xprod_out its output. These intermediate values
+// are committed so that no constraint has a degree above 3, and the STARK runs with the standard
+// rate. The constraints binding them are not filtered, they also hold on the padding rows.
+//
+// bit_dec_hi_diff_inv holds the witnesses of the canonical range checks of mask_ele_bit_dec,
+// xprod_in_bit_dec and xprod_in_neg_bit_dec, in this order.
+//
+// With LOGUP, the bit decompositions of the external product input are replaced by its balanced
+// base 2^LOGB limbs, and the NTTs of the external product are committed:
+//| ... | cur_acc_out |   xprod_in_limbs  | xprod_limbs_hat | xprod_out_hat | xprod_out | bit_dec_hi_diff_inv | bsk_hash_in | ...
+//| ... |    N * K    | NUM_LIMBS * N * K |   ELL * N * K   |     N * K     |   N * K   |          1          |      4      | ...
+// where NUM_LIMBS = ceil(NUM_BITS / LOGB). The limbs of the non padding rows are looked up in a
+// `RangeCheckStark`, which range checks them in [-2^(LOGB-1), 2^(LOGB-1)] with a logUp lookup.
+// Any limbs in range which recompose to a coefficient keep the error of the external product
+// bounded, so they do not need to be unique.
+// xprod_limbs_hat holds the forward NTTs of the top ELL limbs of every polynomial of the input
+// (polynomial major), xprod_out_hat the output in the NTT domain and xprod_out its backward NTT.
+// These NTTs are looked up in an `NttStark`, so only their inputs and outputs are in this table.
+use crate::vtfhe::{
+    crypto::{ggsw::Ggsw, glwe::Glwe, poly::Poly},
+    num_rotation_stages, rotate_glwe_stages_native,
+    starky_ct::{
+        generate_build_circuit_input,
+        ggsw_ct::{ExternalProductNtts, GgswCtNative, GlweDecomposition},
+        glwe_ct::GlweCtNative,
+        glwe_poly::{
+            canonical_bits_inv_native, decompose_native, eval_canonical_bits,
+            eval_canonical_bits_ext, num_limbs,
         },
-        NUM_BITS,
+        ntt::{ntt_columns, NttStark},
+        poseidon::{
+            eval_hash_no_pad, eval_hash_no_pad_ext, hash_no_pad_columns, hash_no_pad_native,
+        },
+        range_check::{range_check_columns, RangeCheckStark},
     },
+    NUM_BITS,
 };
-use std::{array::from_fn, marker::PhantomData, time::Instant};
+use std::{array::from_fn, marker::PhantomData};
 
+use anyhow::{ensure, Result};
 use itertools::Itertools;
-use plonky2::{
-    field::types::{Field, PrimeField64},
-    iop::witness::PartialWitness,
-    plonk::circuit_data::CircuitConfig,
-};
+use plonky2::field::types::Field;
 use plonky2::{
     field::{
         extension::{Extendable, FieldExtension},
         packed::PackedField,
         polynomial::PolynomialValues,
     },
-    hash::hash_types::RichField,
-    iop::ext_target::ExtensionTarget,
+    fri::oracle::PolynomialBatch,
+    hash::hash_types::{HashOut, RichField, NUM_HASH_OUT_ELTS},
+    iop::{
+        challenger::{Challenger, RecursiveChallenger},
+        ext_target::ExtensionTarget,
+        target::Target,
+        witness::Witness,
+    },
     plonk::{
         circuit_builder::CircuitBuilder,
-        config::{GenericConfig, PoseidonGoldilocksConfig},
+        config::{AlgebraicHasher, GenericConfig},
     },
-    util::{log2_ceil, timing::TimingTree, transpose},
+    util::{timing::TimingTree, transpose},
 };
 
-use rand::random;
 use starky::{
     config::StarkConfig,
     constraint_consumer::{ConstraintConsumer, RecursiveConstraintConsumer},
+    cross_table_lookup::{
+        get_ctl_data, get_ctl_vars_from_proofs, verify_cross_table_lookups,
+        verify_cross_table_lookups_circuit, CrossTableLookup, CtlCheckVarsTarget, CtlData,
+        TableIdx, TableWithColumns,
+    },
     evaluation_frame::{StarkEvaluationFrame, StarkFrame},
-    prover::prove,
+    lookup::{
+        get_grand_product_challenge_set, get_grand_product_challenge_set_target, Column, Filter,
+        GrandProductChallengeSet,
+    },
+    proof::{MultiProof, StarkProofTarget, StarkProofWithMetadata},
+    prover::prove_with_commitment,
     recursive_verifier::{
-        add_virtual_stark_proof_with_pis, set_stark_proof_with_pis_target,
-        verify_stark_proof_circuit,
+        add_virtual_stark_proof, set_stark_proof_target, verify_stark_proof_with_challenges_circuit,
     },
     stark::Stark,
-    stark_testing::test_stark_circuit_constraints,
-    verifier::verify_stark_proof,
+    verifier::verify_stark_proof_with_challenges,
 };
 
 use super::{
     eval_step_circuit, eval_step_circuit_ext, glwe_ct::decimal_to_binary, read_array, read_ggsw_ct,
-    read_glwe_ct, write_array, write_ggsw_ct, write_glwe_ct,
+    read_glwe_ct, write_array, write_ggsw_ct, write_glwe_ct, StepColumns,
 };
 
-const LOGB: usize = 5;
-const ELL: usize = 4;
-const D: usize = 2;
+const LWE_HASH_COLUMNS: usize = hash_no_pad_columns(NUM_HASH_OUT_ELTS + 1);
+
+const fn bsk_hash_columns<const N: usize, const K: usize, const ELL: usize>() -> usize {
+    hash_no_pad_columns(NUM_HASH_OUT_ELTS + K * K * N * ELL)
+}
+
+const fn step_columns<const N: usize, const K: usize>() -> usize {
+    (num_rotation_stages::<N>() + 2) * N * K
+}
 
-const K: usize = 2; // GLWE dimension (K = k + 1)
-const n: usize = 728; // LWE dimension
-const p: usize = 2; // plaintext modulus
+const fn xprod_in_dec_columns<
+    const N: usize,
+    const K: usize,
+    const LOGB: usize,
+    const LOGUP: bool,
+>() -> usize {
+    if LOGUP {
+        num_limbs::<LOGB>() * N * K
+    } else {
+        2 * NUM_BITS * N * K
+    }
+}
 
-const VPBS_COLUMNS: usize = N * K + K * K * N * ELL + 1 + NUM_BITS + NUM_BITS * N * K + 3 * 1;
-const VPBS_PUBLIC_INPUT: usize = 0;
+const fn xprod_product_columns<
+    const N: usize,
+    const K: usize,
+    const ELL: usize,
+    const LOGUP: bool,
+>() -> usize {
+    if LOGUP {
+        (ELL + 2) * N * K
+    } else {
+        (ELL + 1) * N * K
+    }
+}
 
+const fn hi_diff_inv_columns<const N: usize, const K: usize, const LOGUP: bool>() -> usize {
+    if LOGUP {
+        1
+    } else {
+        1 + 2 * N * K
+    }
+}
+
+/// Width of the trace of `VpbsStark` for ring dimension `N`, GLWE dimension `K`, `ELL` levels
+/// of base 2^LOGB, with or without the limb and NTT lookups.
+pub const fn vpbs_columns<
+    const N: usize,
+    const K: usize,
+    const ELL: usize,
+    const LOGB: usize,
+    const LOGUP: bool,
+>() -> usize {
+    N * K
+        + K * K * N * ELL
+        + 1
+        + NUM_BITS
+        + step_columns::<N, K>()
+        + xprod_in_dec_columns::<N, K, LOGB, LOGUP>()
+        + xprod_product_columns::<N, K, ELL, LOGUP>()
+        + hi_diff_inv_columns::<N, K, LOGUP>()
+        + NUM_HASH_OUT_ELTS
+        + bsk_hash_columns::<N, K, ELL>()
+        + NUM_HASH_OUT_ELTS
+        + LWE_HASH_COLUMNS
+        + 4
+}
+
+pub const fn vpbs_public_inputs<const N: usize, const K: usize>() -> usize {
+    2 * N * K + 2 * NUM_HASH_OUT_ELTS
+}
+
+/// The `VpbsStark` of the given `F, D, n, N, K, ELL, LOGB, LOGUP`, with the widths of its trace and
+/// of its lookup tables and its number of public inputs computed from them. The parameters must
+/// be constants.
+// only the tests name a `VpbsStark` in this binary
+#[allow(unused_macros)]
+macro_rules! vpbs_stark {
+    ($F:ty, $D:tt, $n:tt, $N:tt, $K:tt, $ELL:tt, $LOGB:tt, $LOGUP:tt) => {
+        $crate::vtfhe::starky_ct::vpbs::VpbsStark<
+            $F,
+            $D,
+            $n,
+            $N,
+            $K,
+            $ELL,
+            $LOGB,
+            $LOGUP,
+            { $crate::vtfhe::starky_ct::vpbs::vpbs_columns::<$N, $K, $ELL, $LOGB, $LOGUP>() },
+            { $crate::vtfhe::starky_ct::vpbs::vpbs_public_inputs::<$N, $K>() },
+            { $crate::vtfhe::starky_ct::range_check::range_check_columns::<$N, $K, $LOGB>() },
+            { $crate::vtfhe::starky_ct::ntt::ntt_columns::<$N, $K, $ELL>() },
+        >
+    };
+}
+#[allow(unused_imports)]
+pub(crate) use vpbs_stark;
+
+/// STARK of a PBS with the same parameters as `verified_pbs`. With `LOGUP`, the external product
+/// inputs are decomposed into limbs range checked by a `RangeCheckStark` instead of bits, which
+/// makes the trace roughly LOGB times narrower, and the NTTs of the external product are proven
+/// by an `NttStark`. Such proofs are made by `prove_with_ctls`.
+/// `COLUMNS` and `PUBLIC_INPUTS` are the width of the trace, `vpbs_columns`, and the number of
+/// public inputs, `vpbs_public_inputs`, and `RANGE_CHECK_COLUMNS` and `NTT_COLUMNS` the widths of
+/// the `RangeCheckStark` and the `NttStark`, `range_check_columns` and `ntt_columns`. They are
+/// derived from the other parameters, so name the type with `vpbs_stark!` instead.
 #[derive(Clone, Copy)]
-pub struct VpbsStark<F: RichField + Extendable<D>, const D: usize> {
+pub struct VpbsStark<
+    F: RichField + Extendable<D>,
+    const D: usize,
+    const n: usize,
+    const N: usize,
+    const K: usize,
+    const ELL: usize,
+    const LOGB: usize,
+    const LOGUP: bool,
+    const COLUMNS: usize,
+    const PUBLIC_INPUTS: usize,
+    const RANGE_CHECK_COLUMNS: usize,
+    const NTT_COLUMNS: usize,
+> {
     _phantom: PhantomData<F>,
 }
 
-impl<F: RichField + Extendable<D>, const D: usize> VpbsStark<F, D> {
+impl<
+        F: RichField + Extendable<D>,
+        const D: usize,
+        const n: usize,
+        const N: usize,
+        const K: usize,
+        const ELL: usize,
+        const LOGB: usize,
+        const LOGUP: bool,
+        const COLUMNS: usize,
+        const PUBLIC_INPUTS: usize,
+        const RANGE_CHECK_COLUMNS: usize,
+        const NTT_COLUMNS: usize,
+    >
+    VpbsStark<
+        F,
+        D,
+        n,
+        N,
+        K,
+        ELL,
+        LOGB,
+        LOGUP,
+        COLUMNS,
+        PUBLIC_INPUTS,
+        RANGE_CHECK_COLUMNS,
+        NTT_COLUMNS,
+    >
+{
+    const BSK_HASH_COLUMNS: usize = bsk_hash_columns::<N, K, ELL>();
+
+    const GGSW_COL: usize = N * K;
+    const MASK_ELE_COL: usize = Self::GGSW_COL + K * K * N * ELL;
+    const STEP_COL: usize = Self::MASK_ELE_COL + 1 + NUM_BITS;
+    const XPROD_IN_DEC_COL: usize = Self::STEP_COL + step_columns::<N, K>();
+    const XPROD_PRODUCT_COL: usize =
+        Self::XPROD_IN_DEC_COL + xprod_in_dec_columns::<N, K, LOGB, LOGUP>();
+    const XPROD_OUT_HAT_COL: usize = Self::XPROD_PRODUCT_COL + ELL * N * K;
+    const XPROD_OUT_COL: usize = Self::HI_DIFF_INV_COL - N * K;
+    const HI_DIFF_INV_COL: usize =
+        Self::XPROD_PRODUCT_COL + xprod_product_columns::<N, K, ELL, LOGUP>();
+    const BSK_HASH_IN_COL: usize = Self::HI_DIFF_INV_COL + hi_diff_inv_columns::<N, K, LOGUP>();
+    const LWE_HASH_IN_COL: usize =
+        Self::BSK_HASH_IN_COL + NUM_HASH_OUT_ELTS + Self::BSK_HASH_COLUMNS;
+    const COUNTER_COL: usize = Self::LWE_HASH_IN_COL + NUM_HASH_OUT_ELTS + LWE_HASH_COLUMNS;
+    const NON_PAD_FLAG_COL: usize = Self::COUNTER_COL + 1;
+    const IS_FIRST_ROW_COL: usize = Self::COUNTER_COL + 2;
+
+    pub fn new() -> Self {
+        assert_eq!(COLUMNS, vpbs_columns::<N, K, ELL, LOGB, LOGUP>());
+        assert_eq!(PUBLIC_INPUTS, vpbs_public_inputs::<N, K>());
+        assert_eq!(RANGE_CHECK_COLUMNS, range_check_columns::<N, K, LOGB>());
+        assert_eq!(NTT_COLUMNS, ntt_columns::<N, K, ELL>());
+        Self {
+            _phantom: PhantomData,
+        }
+    }
+
     pub fn fill_row(
         &self,
         lv: &mut [F],
         cur_col: &mut usize,
         cur_acc_in: &GlweCtNative<F, D, N, K>,
+        cur_acc_out: &GlweCtNative<F, D, N, K>,
         ggsw_ct: &GgswCtNative<F, D, N, K, ELL>,
         mask_ele: F,
         xprod_in_bit_dec: &[[[F; NUM_BITS]; N]; K],
+        xprod_in_neg_bit_dec: &[[[F; NUM_BITS]; N]; K],
+        bsk_hash: &mut HashOut<F>,
+        lwe_hash: &mut HashOut<F>,
         counter: F,
     ) {
         write_glwe_ct(lv, cur_acc_in, cur_col);
-        assert_eq!(*cur_col, N * K);
+        assert_eq!(*cur_col, Self::GGSW_COL);
 
         write_ggsw_ct(lv, ggsw_ct, cur_col);
 
-        assert_eq!(*cur_col, N * K + K * K * N * ELL);
+        assert_eq!(*cur_col, Self::MASK_ELE_COL);
 
         lv[*cur_col] = mask_ele;
         *cur_col += 1;
@@ -102,42 +320,146 @@ impl<F: RichField + Extendable<D>, const D: usize> VpbsStark<F, D> {
 
         write_array(lv, cur_col, &mask_bit_dec);
 
-        assert_eq!(*cur_col, N * K + K * K * N * ELL + 1 + NUM_BITS);
+        assert_eq!(*cur_col, Self::STEP_COL);
+
+        let rotations = rotate_glwe_stages_native(cur_acc_in, mask_bit_dec);
+        let xprod_in = if counter == F::from_canonical_usize(n + 2) {
+            cur_acc_in.clone()
+        } else {
+            rotations.last().unwrap().sub(cur_acc_in)
+        };
+        for glwe in rotations.iter().chain([&xprod_in, cur_acc_out]) {
+            write_glwe_ct(lv, glwe, cur_col);
+        }
+
+        assert_eq!(*cur_col, Self::XPROD_IN_DEC_COL);
+
+        if LOGUP {
+            for (coeff_bit, coeff_neg_bit) in xprod_in_bit_dec
+                .iter()
+                .flatten()
+                .zip(xprod_in_neg_bit_dec.iter().flatten())
+            {
+                let limbs = decompose_native::<F, D, LOGB>(*coeff_bit, *coeff_neg_bit);
+                lv[*cur_col..*cur_col + limbs.len()].copy_from_slice(&limbs);
+                *cur_col += limbs.len();
+            }
+
+            assert_eq!(*cur_col, Self::XPROD_PRODUCT_COL);
+
+            let (limbs_hat, out_hat) =
+                ggsw_ct.external_product_hat::<LOGB>(*xprod_in_bit_dec, *xprod_in_neg_bit_dec);
+            for limb_hat in limbs_hat {
+                lv[*cur_col..*cur_col + N].copy_from_slice(&limb_hat);
+                *cur_col += N;
+            }
+            write_glwe_ct(lv, &out_hat, cur_col);
+            write_glwe_ct(lv, &out_hat.ntt_backward(), cur_col);
+        } else {
+            for poly in xprod_in_bit_dec {
+                for coeff_bit in poly {
+                    write_array(lv, cur_col, coeff_bit);
+                }
+            }
+
+            for poly in xprod_in_neg_bit_dec {
+                for coeff_bit in poly {
+                    write_array(lv, cur_col, coeff_bit);
+                }
+            }
 
-        for poly in xprod_in_bit_dec {
-            for coeff_bit in poly {
-                write_array(lv, cur_col, coeff_bit);
+            assert_eq!(*cur_col, Self::XPROD_PRODUCT_COL);
+
+            let num_limbs = num_limbs::<LOGB>();
+            for (poly_bit_dec, poly_neg_bit_dec) in
+                xprod_in_bit_dec.iter().zip(xprod_in_neg_bit_dec)
+            {
+                let limbs = poly_bit_dec
+                    .iter()
+                    .zip(poly_neg_bit_dec)
+                    .map(|(bit_dec, neg_bit_dec)| {
+                        decompose_native::<F, D, LOGB>(*bit_dec, *neg_bit_dec)
+                    })
+                    .collect_vec();
+                for l in num_limbs - ELL..num_limbs {
+                    for coeff_limbs in &limbs {
+                        lv[*cur_col] = coeff_limbs[l];
+                        *cur_col += 1;
+                    }
+                }
             }
+            let xprod_out = ggsw_ct.external_product::<LOGB>(
+                &xprod_in,
+                *xprod_in_bit_dec,
+                *xprod_in_neg_bit_dec,
+            );
+            write_glwe_ct(lv, &xprod_out, cur_col);
         }
-    }
 
-    fn generate_trace(&self) -> Vec<PolynomialValues<F>> {
-        let mut lv = vec![F::ZERO; VPBS_COLUMNS];
+        assert_eq!(*cur_col, Self::HI_DIFF_INV_COL);
 
-        let mut num_rows = 1 << (64 - ((n + 2) - 1).leading_zeros());
-        if num_rows < 8 {
-            num_rows = 8;
+        lv[*cur_col] = canonical_bits_inv_native(&mask_bit_dec);
+        *cur_col += 1;
+
+        if !LOGUP {
+            for bit_dec in xprod_in_bit_dec
+                .iter()
+                .flatten()
+                .chain(xprod_in_neg_bit_dec.iter().flatten())
+            {
+                lv[*cur_col] = canonical_bits_inv_native(bit_dec);
+                *cur_col += 1;
+            }
         }
 
-        let mut trace_rows = Vec::<Vec<F>>::new();
+        assert_eq!(*cur_col, Self::BSK_HASH_IN_COL);
 
-        // partial GLWE key corresponding to LWE key
-        let s_to = Glwe::<F, D, N, K>::partial_key(n);
-        let s_lwe = Glwe::<F, D, N, K>::flatten_partial_key(&s_to, n);
+        let bsk_hash_inputs = [
+            &bsk_hash.elements[..],
+            &lv[Self::GGSW_COL..Self::MASK_ELE_COL],
+        ]
+        .concat();
+        let (bsk_hash_out, bsk_hash_columns) = hash_no_pad_native(&bsk_hash_inputs);
+        write_array(lv, cur_col, &bsk_hash.elements);
+        lv[*cur_col..*cur_col + bsk_hash_columns.len()].copy_from_slice(&bsk_hash_columns);
+        *cur_col += bsk_hash_columns.len();
+        *bsk_hash = bsk_hash_out;
 
-        let s_glwe = Glwe::<F, D, N, K>::key_gen();
-        let bsk = compute_bsk::<F, D, N, K, ELL, LOGB>(&s_lwe, &s_glwe, 0f64);
+        assert_eq!(*cur_col, Self::LWE_HASH_IN_COL);
 
-        let ksk = Ggsw::<F, D, N, K, ELL>::compute_ksk::<LOGB>(&s_to, &s_glwe, 0f64);
+        let (lwe_hash_out, lwe_hash_columns) =
+            hash_no_pad_native(&[&lwe_hash.elements[..], &[mask_ele]].concat());
+        write_array(lv, cur_col, &lwe_hash.elements);
+        lv[*cur_col..*cur_col + lwe_hash_columns.len()].copy_from_slice(&lwe_hash_columns);
+        *cur_col += lwe_hash_columns.len();
+        *lwe_hash = lwe_hash_out;
 
-        let delta = F::from_noncanonical_biguint(F::order() >> log2_ceil(2 * p));
+        assert_eq!(*cur_col, Self::COUNTER_COL);
 
-        let testv = get_testv::<F, D, N>(p, delta);
-        println!("testv: {:?}", testv);
+        lv[*cur_col] = counter;
+        *cur_col += 1;
+    }
 
-        let m = F::from_canonical_usize(random::<usize>() % p);
-        println!("message: {delta} * {m} = {}", delta * m);
-        let ct = encrypt::<F, D, n>(&s_lwe, &(delta * m), 0f64);
+    /// Computes the trace of a PBS of `ct` with `testv`, `bsk` and `ksk`, the same inputs as
+    /// `verified_pbs`, together with the public inputs and the output GLWE ciphertext.
+    pub fn generate_trace(
+        &self,
+        ct: &[F],
+        testv: &Poly<F, D, N>,
+        bsk: &[Ggsw<F, D, N, K, ELL>],
+        ksk: &Ggsw<F, D, N, K, ELL>,
+    ) -> (Vec<PolynomialValues<F>>, Vec<F>, Glwe<F, D, N, K>) {
+        assert_eq!(ct.len(), n + 1, "LWE ciphertext does not match n={n}.");
+        assert_eq!(bsk.len(), n, "BSK does not match n={n}.");
+
+        let mut lv = vec![F::ZERO; COLUMNS];
+
+        let mut num_rows = 1 << (64 - ((n + 2) - 1).leading_zeros());
+        if num_rows < 8 {
+            num_rows = 8;
+        }
+
+        let mut trace_rows = Vec::<Vec<F>>::new();
 
         let mut cur_col = 0;
 
@@ -145,27 +467,33 @@ impl<F: RichField + Extendable<D>, const D: usize> VpbsStark<F, D> {
             .into_iter()
             .chain(testv.coeffs.into_iter())
             .collect_vec();
-        let mut current_acc_in = GlweCtNative::new_from_slice(&coeffs);
+        let acc_init = GlweCtNative::new_from_slice(&coeffs);
 
-        let mut prev_acc_in = current_acc_in;
+        let mut prev_acc_in = acc_init.clone();
         let dummy_ggsw_ct = GgswCtNative::<F, D, N, K, ELL>::dummy_ct();
-        let mut xprod_in_bit_dec = [[[F::ZERO; 64]; N]; K];
+        let mut bsk_hash = HashOut::ZERO;
+        let mut lwe_hash = HashOut::ZERO;
 
-        (current_acc_in, xprod_in_bit_dec) = generate_build_circuit_input::<F, D, n, N, K, ELL, LOGB>(
-            &prev_acc_in,
-            &dummy_ggsw_ct,
-            ct[n],
-            F::ONE,
-        );
+        let (mut current_acc_in, mut xprod_in_bit_dec, mut xprod_in_neg_bit_dec) =
+            generate_build_circuit_input::<F, D, n, N, K, ELL, LOGB>(
+                &prev_acc_in,
+                &dummy_ggsw_ct,
+                ct[n],
+                F::ONE,
+            );
 
         //Fill 1st row
         self.fill_row(
             &mut lv,
             &mut cur_col,
             &prev_acc_in,
+            &current_acc_in,
             &dummy_ggsw_ct,
             ct[n],
             &xprod_in_bit_dec,
+            &xprod_in_neg_bit_dec,
+            &mut bsk_hash,
+            &mut lwe_hash,
             F::ONE,
         );
 
@@ -178,20 +506,20 @@ impl<F: RichField + Extendable<D>, const D: usize> VpbsStark<F, D> {
         //is_last_non_pad_row
         lv[cur_col] = F::ZERO;
         cur_col += 1;
-        assert_eq!(cur_col, VPBS_COLUMNS);
+        assert_eq!(cur_col, COLUMNS);
 
         trace_rows.push(lv);
 
         prev_acc_in = current_acc_in.clone();
 
         for x in 0..n {
-            let mut lv = vec![F::ZERO; VPBS_COLUMNS];
+            let mut lv = vec![F::ZERO; COLUMNS];
             cur_col = 0;
 
             let counter = F::from_canonical_usize(x + 2);
 
             let ggsw_ct = GgswCtNative::from_ggsw(&bsk[x]);
-            (current_acc_in, xprod_in_bit_dec) =
+            (current_acc_in, xprod_in_bit_dec, xprod_in_neg_bit_dec) =
                 generate_build_circuit_input::<F, D, n, N, K, ELL, LOGB>(
                     &prev_acc_in,
                     &ggsw_ct,
@@ -203,9 +531,13 @@ impl<F: RichField + Extendable<D>, const D: usize> VpbsStark<F, D> {
                 &mut lv,
                 &mut cur_col,
                 &prev_acc_in,
+                &current_acc_in,
                 &ggsw_ct,
                 ct[x],
                 &xprod_in_bit_dec,
+                &xprod_in_neg_bit_dec,
+                &mut bsk_hash,
+                &mut lwe_hash,
                 counter,
             );
 
@@ -218,33 +550,38 @@ impl<F: RichField + Extendable<D>, const D: usize> VpbsStark<F, D> {
             //is_last_non_pad_row
             lv[cur_col] = F::ZERO;
             cur_col += 1;
-            assert_eq!(cur_col, VPBS_COLUMNS);
+            assert_eq!(cur_col, COLUMNS);
 
             trace_rows.push(lv);
 
             prev_acc_in = current_acc_in.clone();
         }
 
-        let ksk_native = GgswCtNative::from_ggsw(&ksk);
+        let ksk_native = GgswCtNative::from_ggsw(ksk);
 
         let counter = F::from_canonical_usize(n + 2);
-        (current_acc_in, xprod_in_bit_dec) = generate_build_circuit_input::<F, D, n, N, K, ELL, LOGB>(
-            &prev_acc_in,
-            &ksk_native,
-            F::ZERO,
-            counter,
-        );
+        (current_acc_in, xprod_in_bit_dec, xprod_in_neg_bit_dec) =
+            generate_build_circuit_input::<F, D, n, N, K, ELL, LOGB>(
+                &prev_acc_in,
+                &ksk_native,
+                F::ZERO,
+                counter,
+            );
 
-        let mut lv = vec![F::ZERO; VPBS_COLUMNS];
+        let mut lv = vec![F::ZERO; COLUMNS];
         cur_col = 0;
 
         self.fill_row(
             &mut lv,
             &mut cur_col,
             &prev_acc_in,
+            &current_acc_in,
             &ksk_native,
             F::ZERO,
             &xprod_in_bit_dec,
+            &xprod_in_neg_bit_dec,
+            &mut bsk_hash,
+            &mut lwe_hash,
             counter,
         );
 
@@ -258,85 +595,97 @@ impl<F: RichField + Extendable<D>, const D: usize> VpbsStark<F, D> {
         lv[cur_col] = F::ONE;
         cur_col += 1;
 
-        assert_eq!(cur_col, VPBS_COLUMNS);
+        assert_eq!(cur_col, COLUMNS);
 
         trace_rows.push(lv);
 
         assert_eq!(trace_rows.len(), n + 2);
 
-        for _ in trace_rows.len()..num_rows {
-            let lv = vec![F::ZERO; VPBS_COLUMNS];
+        // the counter keeps running through the padding rows, which hold the hashes of their
+        // zero inputs
+        let mut padding_row = vec![F::ZERO; COLUMNS];
+        let bsk_hash_columns =
+            hash_no_pad_native(&vec![F::ZERO; NUM_HASH_OUT_ELTS + K * K * N * ELL]).1;
+        let lwe_hash_columns = hash_no_pad_native(&[F::ZERO; NUM_HASH_OUT_ELTS + 1]).1;
+        let bsk_hash_col = Self::BSK_HASH_IN_COL + NUM_HASH_OUT_ELTS;
+        let lwe_hash_col = Self::LWE_HASH_IN_COL + NUM_HASH_OUT_ELTS;
+        padding_row[bsk_hash_col..Self::LWE_HASH_IN_COL].copy_from_slice(&bsk_hash_columns);
+        padding_row[lwe_hash_col..Self::COUNTER_COL].copy_from_slice(&lwe_hash_columns);
+        for i in trace_rows.len()..num_rows {
+            let mut lv = padding_row.clone();
+            lv[Self::COUNTER_COL] = F::from_canonical_usize(i + 1);
             trace_rows.push(lv);
         }
 
-        for i in 0..num_rows {
-            let mut col = 0;
-
-            let lv = &trace_rows[i];
+        let trace_cols = transpose(&trace_rows);
 
-            let current_acc_in = read_glwe_ct::<F, N, K>(lv, &mut col);
-            let ggsw_ct = read_ggsw_ct::<F, N, K, ELL>(lv, &mut col);
+        let public_inputs =
+            self.generate_public_inputs(&acc_init, &current_acc_in, bsk_hash, lwe_hash);
 
-            let mask_element = lv[col];
-            col += 1;
-
-            let mask_ele_bit_dec = read_array::<F, NUM_BITS>(lv, &mut col);
-
-            let clone_mask_ele = if i == 0 {
-                -mask_element.clone()
-            } else {
-                mask_element
-            };
-
-            let trace_xprod_in_bit_dec: [[[F; NUM_BITS]; N]; K] =
-                from_fn(|_| from_fn(|_| read_array::<F, NUM_BITS>(lv, &mut col)));
-
-            for i in 0..K {
-                for j in 0..N {
-                    trace_xprod_in_bit_dec[i][j].into_iter().for_each(|bit| {
-                        assert_eq!(bit * bit - bit, F::ZERO);
-                    });
-                }
-            }
-
-            let check_mask = le_sum_native(mask_ele_bit_dec.to_vec());
-
-            let non_pad_flag = lv[col];
-            col += 1;
-
-            let is_first_row = lv[col];
-            col += 1;
-
-            let is_last_non_pad_row = lv[col];
-
-            let constr = non_pad_flag * (check_mask - clone_mask_ele);
-            assert!(constr == F::ZERO, "fail at row {} ", i);
-        }
-
-        let trace_cols = transpose(&trace_rows.iter().map(|v| v.to_vec()).collect_vec());
-
-        println!("non_pad_flag {:?}", trace_cols[VPBS_COLUMNS - 3]);
-        println!("is_first_row {:?}", trace_cols[VPBS_COLUMNS - 2]);
-        println!("is_last_non_pad_row {:?}", trace_cols[VPBS_COLUMNS - 1]);
+        (
+            trace_cols
+                .into_iter()
+                .map(|column| PolynomialValues::new(column))
+                .collect(),
+            public_inputs,
+            Glwe::from_slice(&current_acc_in.flatten()),
+        )
+    }
 
-        trace_cols
+    fn generate_public_inputs(
+        &self,
+        acc_init: &GlweCtNative<F, D, N, K>,
+        acc_out: &GlweCtNative<F, D, N, K>,
+        bsk_hash: HashOut<F>,
+        lwe_hash: HashOut<F>,
+    ) -> Vec<F> {
+        acc_init
+            .flatten()
             .into_iter()
-            .map(|column| PolynomialValues::new(column))
+            .chain(acc_out.flatten())
+            .chain(bsk_hash.elements)
+            .chain(lwe_hash.elements)
             .collect()
     }
-
-    fn generate_public_inputs(&self) {}
 }
 
-impl<F: RichField + Extendable<D>, const D: usize> Stark<F, D> for VpbsStark<F, D> {
+impl<
+        F: RichField + Extendable<D>,
+        const D: usize,
+        const n: usize,
+        const N: usize,
+        const K: usize,
+        const ELL: usize,
+        const LOGB: usize,
+        const LOGUP: bool,
+        const COLUMNS: usize,
+        const PUBLIC_INPUTS: usize,
+        const RANGE_CHECK_COLUMNS: usize,
+        const NTT_COLUMNS: usize,
+    > Stark<F, D>
+    for VpbsStark<
+        F,
+        D,
+        n,
+        N,
+        K,
+        ELL,
+        LOGB,
+        LOGUP,
+        COLUMNS,
+        PUBLIC_INPUTS,
+        RANGE_CHECK_COLUMNS,
+        NTT_COLUMNS,
+    >
+{
     type EvaluationFrame<FE, P, const D2: usize>
-        = StarkFrame<P, P::Scalar, VPBS_COLUMNS, VPBS_PUBLIC_INPUT>
+        = StarkFrame<P, P::Scalar, COLUMNS, PUBLIC_INPUTS>
     where
         FE: FieldExtension<D2, BaseField = F>,
         P: PackedField<Scalar = FE>;
 
     type EvaluationFrameTarget =
-        StarkFrame<ExtensionTarget<D>, ExtensionTarget<D>, VPBS_COLUMNS, VPBS_PUBLIC_INPUT>;
+        StarkFrame<ExtensionTarget<D>, ExtensionTarget<D>, COLUMNS, PUBLIC_INPUTS>;
 
     fn eval_packed_generic<FE, P, const D2: usize>(
         &self,
@@ -347,6 +696,8 @@ impl<F: RichField + Extendable<D>, const D: usize> Stark<F, D> for VpbsStark<F,
         P: PackedField<Scalar = FE>,
     {
         let lv = vars.get_local_values();
+        let nv = vars.get_next_values();
+        let public_inputs = vars.get_public_inputs();
         let mut cur_col = 0;
 
         let current_acc_in = read_glwe_ct(lv, &mut cur_col);
@@ -355,9 +706,44 @@ impl<F: RichField + Extendable<D>, const D: usize> Stark<F, D> for VpbsStark<F,
         cur_col += 1;
 
         let mask_ele_bit_dec = read_array(lv, &mut cur_col);
+        let step_columns = StepColumns::read(lv, &mut cur_col);
+
+        let xprod_in_dec = if LOGUP {
+            let limbs = &lv[cur_col..cur_col + xprod_in_dec_columns::<N, K, LOGB, LOGUP>()];
+            cur_col += limbs.len();
+            let ntts = ExternalProductNtts {
+                limbs_hat: &lv[cur_col..cur_col + ELL * N * K],
+                out_hat: &lv[cur_col + ELL * N * K..cur_col + (ELL + 1) * N * K],
+                out: &lv[cur_col + (ELL + 1) * N * K..cur_col + (ELL + 2) * N * K],
+            };
+            cur_col += xprod_product_columns::<N, K, ELL, LOGUP>();
+            GlweDecomposition::Limbs(limbs, ntts)
+        } else {
+            let xprod_in_bit_dec: [[[P; NUM_BITS]; N]; K] =
+                from_fn(|_| from_fn(|_| read_array::<P, NUM_BITS>(lv, &mut cur_col)));
+
+            let xprod_in_neg_bit_dec: [[[P; NUM_BITS]; N]; K] =
+                from_fn(|_| from_fn(|_| read_array::<P, NUM_BITS>(lv, &mut cur_col)));
+
+            let top_limbs = &lv[cur_col..cur_col + ELL * N * K];
+            let out = &lv[cur_col + ELL * N * K..cur_col + (ELL + 1) * N * K];
+            cur_col += xprod_product_columns::<N, K, ELL, LOGUP>();
+            GlweDecomposition::Bits(xprod_in_bit_dec, xprod_in_neg_bit_dec, top_limbs, out)
+        };
+
+        let hi_diff_invs = &lv[cur_col..cur_col + hi_diff_inv_columns::<N, K, LOGUP>()];
+        cur_col += hi_diff_invs.len();
+
+        let bsk_hash_in: [P; NUM_HASH_OUT_ELTS] = read_array(lv, &mut cur_col);
+        let bsk_hash_columns = &lv[cur_col..cur_col + Self::BSK_HASH_COLUMNS];
+        cur_col += Self::BSK_HASH_COLUMNS;
+
+        let lwe_hash_in: [P; NUM_HASH_OUT_ELTS] = read_array(lv, &mut cur_col);
+        let lwe_hash_columns = &lv[cur_col..cur_col + LWE_HASH_COLUMNS];
+        cur_col += LWE_HASH_COLUMNS;
 
-        let xprod_in_bit_dec: [[[P; NUM_BITS]; N]; K] =
-            from_fn(|_| from_fn(|_| read_array::<P, NUM_BITS>(lv, &mut cur_col)));
+        let counter = lv[cur_col];
+        cur_col += 1;
 
         let non_pad_flag = lv[cur_col];
         cur_col += 1;
@@ -367,19 +753,104 @@ impl<F: RichField + Extendable<D>, const D: usize> Stark<F, D> for VpbsStark<F,
 
         let is_last_non_pad_row = lv[cur_col];
 
-        assert_eq!(cur_col + 1, VPBS_COLUMNS);
+        assert_eq!(cur_col + 1, COLUMNS);
+
+        let one = P::ONES;
+        let num_steps = P::Scalar::from_canonical_usize(n + 2);
+
+        for flag in [non_pad_flag, is_first_row, is_last_non_pad_row] {
+            yield_constr.constraint(flag * flag - flag);
+        }
+
+        // only the first row does the body rotation
+        yield_constr.constraint_first_row(is_first_row - one);
+        yield_constr.constraint_transition(nv[Self::IS_FIRST_ROW_COL]);
+
+        yield_constr.constraint_first_row(counter - one);
+        yield_constr.constraint_transition(nv[Self::COUNTER_COL] - counter - one);
+
+        // the padding rows come after all the non padding rows
+        yield_constr.constraint_first_row(non_pad_flag - one);
+        yield_constr.constraint_transition(nv[Self::NON_PAD_FLAG_COL] * (one - non_pad_flag));
+
+        // the last non padding row is the one followed by padding, or the last row of the trace
+        yield_constr.constraint_transition(
+            is_last_non_pad_row - non_pad_flag * (one - nv[Self::NON_PAD_FLAG_COL]),
+        );
+        yield_constr.constraint_last_row(is_last_non_pad_row - non_pad_flag);
 
-        let exp_cipher = eval_step_circuit::<P, N, K, ELL, LOGB>(
+        // body rotation, n CMUX steps and the key switch
+        yield_constr.constraint(is_last_non_pad_row * (counter - num_steps));
+
+        // a 64 bit decomposition of a field element is only unique if it encodes a value < p
+        let mut bit_decs = vec![&mask_ele_bit_dec];
+        if let GlweDecomposition::Bits(bit_dec, neg_bit_dec, _, _) = &xprod_in_dec {
+            bit_decs.extend(bit_dec.iter().flatten().chain(neg_bit_dec.iter().flatten()));
+        }
+        for (bit_dec, hi_diff_inv) in bit_decs.into_iter().zip_eq(hi_diff_invs) {
+            eval_canonical_bits(yield_constr, bit_dec, *hi_diff_inv);
+        }
+
+        let acc_in = current_acc_in.flatten();
+        let current_acc_out = eval_step_circuit::<P, N, K, ELL, LOGB>(
             yield_constr,
             current_acc_in,
             ggsw_ct,
             mask_element,
             mask_ele_bit_dec,
-            xprod_in_bit_dec,
+            xprod_in_dec,
+            step_columns,
             non_pad_flag,
             is_first_row,
             is_last_non_pad_row,
         );
+
+        let next_acc_in = read_glwe_ct::<P, N, K>(nv, &mut 0);
+        let (acc_init, rest) = public_inputs.split_at(N * K);
+        let (acc_out, rest) = rest.split_at(N * K);
+        let (expected_bsk_hash, expected_lwe_hash) = rest.split_at(NUM_HASH_OUT_ELTS);
+
+        // the accumulator starts from the test vector
+        for (coeff, init) in acc_in.into_iter().zip(acc_init) {
+            yield_constr.constraint_first_row(coeff - *init);
+        }
+
+        let bsk_hash_inputs = [&bsk_hash_in[..], &lv[Self::GGSW_COL..Self::MASK_ELE_COL]].concat();
+        let bsk_hash =
+            eval_hash_no_pad::<F, D, FE, P, D2>(yield_constr, &bsk_hash_inputs, bsk_hash_columns);
+        let lwe_hash = eval_hash_no_pad::<F, D, FE, P, D2>(
+            yield_constr,
+            &[&lwe_hash_in[..], &[mask_element]].concat(),
+            lwe_hash_columns,
+        );
+
+        // the hashes are chained from the zero hash and the last non padding row holds the result
+        for (hash_in_col, hash, expected) in [
+            (Self::BSK_HASH_IN_COL, bsk_hash, expected_bsk_hash),
+            (Self::LWE_HASH_IN_COL, lwe_hash, expected_lwe_hash),
+        ] {
+            for i in 0..NUM_HASH_OUT_ELTS {
+                yield_constr.constraint_first_row(lv[hash_in_col + i]);
+                yield_constr.constraint_transition(
+                    nv[Self::NON_PAD_FLAG_COL] * (nv[hash_in_col + i] - hash[i]),
+                );
+                yield_constr.constraint(is_last_non_pad_row * (hash[i] - expected[i]));
+            }
+        }
+
+        // the accumulator computed in this row is the input of the next one
+        for (next_in, out) in next_acc_in
+            .flatten()
+            .into_iter()
+            .zip(current_acc_out.flatten())
+        {
+            yield_constr.constraint_transition(nv[Self::NON_PAD_FLAG_COL] * (next_in - out));
+        }
+
+        // the last non padding row holds the result of the PBS
+        for (out, expected) in current_acc_out.flatten().into_iter().zip(acc_out) {
+            yield_constr.constraint(is_last_non_pad_row * (out - *expected));
+        }
     }
 
     fn eval_ext_circuit(
@@ -389,6 +860,8 @@ impl<F: RichField + Extendable<D>, const D: usize> Stark<F, D> for VpbsStark<F,
         yield_constr: &mut RecursiveConstraintConsumer<F, D>,
     ) {
         let lv = vars.get_local_values();
+        let nv = vars.get_next_values();
+        let public_inputs = vars.get_public_inputs();
         let mut cur_col = 0;
 
         let current_acc_in = read_glwe_ct(lv, &mut cur_col);
@@ -397,9 +870,46 @@ impl<F: RichField + Extendable<D>, const D: usize> Stark<F, D> for VpbsStark<F,
         cur_col += 1;
 
         let mask_ele_bit_dec = read_array(lv, &mut cur_col);
+        let step_columns = StepColumns::read(lv, &mut cur_col);
+
+        let xprod_in_dec = if LOGUP {
+            let limbs = &lv[cur_col..cur_col + xprod_in_dec_columns::<N, K, LOGB, LOGUP>()];
+            cur_col += limbs.len();
+            let ntts = ExternalProductNtts {
+                limbs_hat: &lv[cur_col..cur_col + ELL * N * K],
+                out_hat: &lv[cur_col + ELL * N * K..cur_col + (ELL + 1) * N * K],
+                out: &lv[cur_col + (ELL + 1) * N * K..cur_col + (ELL + 2) * N * K],
+            };
+            cur_col += xprod_product_columns::<N, K, ELL, LOGUP>();
+            GlweDecomposition::Limbs(limbs, ntts)
+        } else {
+            let xprod_in_bit_dec: [[[ExtensionTarget<D>; NUM_BITS]; N]; K] = from_fn(|_| {
+                from_fn(|_| read_array::<ExtensionTarget<D>, NUM_BITS>(lv, &mut cur_col))
+            });
+
+            let xprod_in_neg_bit_dec: [[[ExtensionTarget<D>; NUM_BITS]; N]; K] = from_fn(|_| {
+                from_fn(|_| read_array::<ExtensionTarget<D>, NUM_BITS>(lv, &mut cur_col))
+            });
+
+            let top_limbs = &lv[cur_col..cur_col + ELL * N * K];
+            let out = &lv[cur_col + ELL * N * K..cur_col + (ELL + 1) * N * K];
+            cur_col += xprod_product_columns::<N, K, ELL, LOGUP>();
+            GlweDecomposition::Bits(xprod_in_bit_dec, xprod_in_neg_bit_dec, top_limbs, out)
+        };
 
-        let xprod_in_bit_dec: [[[ExtensionTarget<D>; NUM_BITS]; N]; K] =
-            from_fn(|_| from_fn(|_| read_array::<ExtensionTarget<D>, NUM_BITS>(lv, &mut cur_col)));
+        let hi_diff_invs = &lv[cur_col..cur_col + hi_diff_inv_columns::<N, K, LOGUP>()];
+        cur_col += hi_diff_invs.len();
+
+        let bsk_hash_in: [ExtensionTarget<D>; NUM_HASH_OUT_ELTS] = read_array(lv, &mut cur_col);
+        let bsk_hash_columns = &lv[cur_col..cur_col + Self::BSK_HASH_COLUMNS];
+        cur_col += Self::BSK_HASH_COLUMNS;
+
+        let lwe_hash_in: [ExtensionTarget<D>; NUM_HASH_OUT_ELTS] = read_array(lv, &mut cur_col);
+        let lwe_hash_columns = &lv[cur_col..cur_col + LWE_HASH_COLUMNS];
+        cur_col += LWE_HASH_COLUMNS;
+
+        let counter = lv[cur_col];
+        cur_col += 1;
 
         let non_pad_flag = lv[cur_col];
         cur_col += 1;
@@ -409,61 +919,817 @@ impl<F: RichField + Extendable<D>, const D: usize> Stark<F, D> for VpbsStark<F,
 
         let is_last_non_pad_row = lv[cur_col];
 
-        assert_eq!(cur_col + 1, VPBS_COLUMNS);
+        assert_eq!(cur_col + 1, COLUMNS);
 
-        eval_step_circuit_ext::<F, D, N, K, ELL, LOGB>(
+        let one = builder.one_extension();
+        let num_steps = builder.constant_extension(F::Extension::from_canonical_usize(n + 2));
+
+        for flag in [non_pad_flag, is_first_row, is_last_non_pad_row] {
+            let constr = builder.mul_sub_extension(flag, flag, flag);
+            yield_constr.constraint(builder, constr);
+        }
+
+        // only the first row does the body rotation
+        let constr = builder.sub_extension(is_first_row, one);
+        yield_constr.constraint_first_row(builder, constr);
+        yield_constr.constraint_transition(builder, nv[Self::IS_FIRST_ROW_COL]);
+
+        let constr = builder.sub_extension(counter, one);
+        yield_constr.constraint_first_row(builder, constr);
+        let diff = builder.sub_extension(nv[Self::COUNTER_COL], counter);
+        let constr = builder.sub_extension(diff, one);
+        yield_constr.constraint_transition(builder, constr);
+
+        // the padding rows come after all the non padding rows
+        let constr = builder.sub_extension(non_pad_flag, one);
+        yield_constr.constraint_first_row(builder, constr);
+        let is_pad = builder.sub_extension(one, non_pad_flag);
+        let constr = builder.mul_extension(nv[Self::NON_PAD_FLAG_COL], is_pad);
+        yield_constr.constraint_transition(builder, constr);
+
+        // the last non padding row is the one followed by padding, or the last row of the trace
+        let next_is_pad = builder.sub_extension(one, nv[Self::NON_PAD_FLAG_COL]);
+        let last = builder.mul_extension(non_pad_flag, next_is_pad);
+        let constr = builder.sub_extension(is_last_non_pad_row, last);
+        yield_constr.constraint_transition(builder, constr);
+        let constr = builder.sub_extension(is_last_non_pad_row, non_pad_flag);
+        yield_constr.constraint_last_row(builder, constr);
+
+        // body rotation, n CMUX steps and the key switch
+        let diff = builder.sub_extension(counter, num_steps);
+        let constr = builder.mul_extension(is_last_non_pad_row, diff);
+        yield_constr.constraint(builder, constr);
+
+        // a 64 bit decomposition of a field element is only unique if it encodes a value < p
+        let mut bit_decs = vec![&mask_ele_bit_dec];
+        if let GlweDecomposition::Bits(bit_dec, neg_bit_dec, _, _) = &xprod_in_dec {
+            bit_decs.extend(bit_dec.iter().flatten().chain(neg_bit_dec.iter().flatten()));
+        }
+        for (bit_dec, hi_diff_inv) in bit_decs.into_iter().zip_eq(hi_diff_invs) {
+            eval_canonical_bits_ext(builder, yield_constr, bit_dec, *hi_diff_inv);
+        }
+
+        let acc_in = current_acc_in.flatten_ext();
+        let current_acc_out = eval_step_circuit_ext::<F, D, N, K, ELL, LOGB>(
             builder,
             yield_constr,
             current_acc_in,
             ggsw_ct,
             mask_element,
             mask_ele_bit_dec,
-            xprod_in_bit_dec,
+            xprod_in_dec,
+            step_columns,
             non_pad_flag,
             is_first_row,
             is_last_non_pad_row,
         );
+
+        let next_acc_in = read_glwe_ct::<ExtensionTarget<D>, N, K>(nv, &mut 0);
+        let (acc_init, rest) = public_inputs.split_at(N * K);
+        let (acc_out, rest) = rest.split_at(N * K);
+        let (expected_bsk_hash, expected_lwe_hash) = rest.split_at(NUM_HASH_OUT_ELTS);
+
+        // the accumulator starts from the test vector
+        for (coeff, init) in acc_in.into_iter().zip(acc_init) {
+            let constr = builder.sub_extension(coeff, *init);
+            yield_constr.constraint_first_row(builder, constr);
+        }
+
+        let bsk_hash_inputs = [&bsk_hash_in[..], &lv[Self::GGSW_COL..Self::MASK_ELE_COL]].concat();
+        let bsk_hash =
+            eval_hash_no_pad_ext(builder, yield_constr, &bsk_hash_inputs, bsk_hash_columns);
+        let lwe_hash = eval_hash_no_pad_ext(
+            builder,
+            yield_constr,
+            &[&lwe_hash_in[..], &[mask_element]].concat(),
+            lwe_hash_columns,
+        );
+
+        // the hashes are chained from the zero hash and the last non padding row holds the result
+        for (hash_in_col, hash, expected) in [
+            (Self::BSK_HASH_IN_COL, bsk_hash, expected_bsk_hash),
+            (Self::LWE_HASH_IN_COL, lwe_hash, expected_lwe_hash),
+        ] {
+            for i in 0..NUM_HASH_OUT_ELTS {
+                yield_constr.constraint_first_row(builder, lv[hash_in_col + i]);
+                let diff = builder.sub_extension(nv[hash_in_col + i], hash[i]);
+                let constr = builder.mul_extension(nv[Self::NON_PAD_FLAG_COL], diff);
+                yield_constr.constraint_transition(builder, constr);
+                let diff = builder.sub_extension(hash[i], expected[i]);
+                let constr = builder.mul_extension(is_last_non_pad_row, diff);
+                yield_constr.constraint(builder, constr);
+            }
+        }
+
+        // the accumulator computed in this row is the input of the next one
+        for (next_in, out) in next_acc_in
+            .flatten_ext()
+            .into_iter()
+            .zip(current_acc_out.flatten_ext())
+        {
+            let diff = builder.sub_extension(next_in, out);
+            let constr = builder.mul_extension(nv[Self::NON_PAD_FLAG_COL], diff);
+            yield_constr.constraint_transition(builder, constr);
+        }
+
+        // the last non padding row holds the result of the PBS
+        for (out, expected) in current_acc_out.flatten_ext().into_iter().zip(acc_out) {
+            let diff = builder.sub_extension(out, *expected);
+            let constr = builder.mul_extension(is_last_non_pad_row, diff);
+            yield_constr.constraint(builder, constr);
+        }
     }
 
     fn constraint_degree(&self) -> usize {
-        8
+        3
+    }
+
+    fn requires_ctls(&self) -> bool {
+        LOGUP
+    }
+}
+
+/// Tables of the proof of `VpbsStark` with `LOGUP`.
+const VPBS_TABLE: TableIdx = 0;
+const RANGE_CHECK_TABLE: TableIdx = 1;
+const NTT_TABLE: TableIdx = 2;
+const NUM_TABLES: usize = 3;
+
+/// Targets of a proof of `VpbsStark` with `LOGUP`, together with its public inputs.
+pub struct VpbsProofTarget<const D: usize> {
+    pub stark_proofs: [StarkProofTarget<D>; NUM_TABLES],
+    pub public_inputs: Vec<Target>,
+}
+
+/// Number of helper and Z polynomials of the cross-table lookups of `table`, and the number of
+/// Z polynomials alone.
+fn num_ctl_polys<F: Field>(
+    ctls: &[CrossTableLookup<F>],
+    table: TableIdx,
+    config: &StarkConfig,
+) -> (usize, usize) {
+    let (num_helpers, num_zs, _) =
+        CrossTableLookup::num_ctl_helpers_zs_all(ctls, table, config.num_challenges, 3);
+    (num_helpers + num_zs, num_zs)
+}
+
+impl<
+        F: RichField + Extendable<D>,
+        const D: usize,
+        const n: usize,
+        const N: usize,
+        const K: usize,
+        const ELL: usize,
+        const LOGB: usize,
+        const LOGUP: bool,
+        const COLUMNS: usize,
+        const PUBLIC_INPUTS: usize,
+        const RANGE_CHECK_COLUMNS: usize,
+        const NTT_COLUMNS: usize,
+    >
+    VpbsStark<
+        F,
+        D,
+        n,
+        N,
+        K,
+        ELL,
+        LOGB,
+        LOGUP,
+        COLUMNS,
+        PUBLIC_INPUTS,
+        RANGE_CHECK_COLUMNS,
+        NTT_COLUMNS,
+    >
+{
+    /// Direction, input columns and output columns of the NTTs of the external product of a row,
+    /// by slot of the `NttStark`.
+    fn xprod_ntts() -> Vec<(bool, Vec<usize>, Vec<usize>)> {
+        let num_limbs = num_limbs::<LOGB>();
+        let forward = (0..K).cartesian_product(0..ELL).map(|(k, l)| {
+            let limb = num_limbs - ELL + l;
+            let input = (0..N)
+                .map(|c| Self::XPROD_IN_DEC_COL + (k * N + c) * num_limbs + limb)
+                .collect();
+            let output = (0..N)
+                .map(|c| Self::XPROD_PRODUCT_COL + (k * ELL + l) * N + c)
+                .collect();
+            (true, input, output)
+        });
+        let backward = (0..K).map(|k| {
+            let input = (0..N)
+                .map(|c| Self::XPROD_OUT_HAT_COL + k * N + c)
+                .collect();
+            let output = (0..N).map(|c| Self::XPROD_OUT_COL + k * N + c).collect();
+            (false, input, output)
+        });
+        forward.chain(backward).collect()
+    }
+
+    /// The limbs of the non padding rows are the rows of the `RangeCheckStark`, and their NTTs
+    /// are the NTTs of the `NttStark`.
+    pub fn cross_table_lookups(&self) -> Vec<CrossTableLookup<F>> {
+        assert!(
+            LOGUP,
+            "Only the limbs and the NTTs are proven in other tables."
+        );
+        let non_pad_filter = || Some(Filter::new_simple(Column::single(Self::NON_PAD_FLAG_COL)));
+
+        let range_check_ctl = CrossTableLookup::new(
+            vec![TableWithColumns::new(
+                VPBS_TABLE,
+                Column::singles(Self::XPROD_IN_DEC_COL..Self::XPROD_PRODUCT_COL).collect(),
+                non_pad_filter(),
+            )],
+            TableWithColumns::new(
+                RANGE_CHECK_TABLE,
+                RangeCheckStark::<F, D, N, K, LOGB, RANGE_CHECK_COLUMNS>::limb_columns(),
+                Some(Filter::new_simple(Column::single(
+                    RangeCheckStark::<F, D, N, K, LOGB, RANGE_CHECK_COLUMNS>::FILTER_COL,
+                ))),
+            ),
+        );
+
+        let ntt_ctls = Self::xprod_ntts().into_iter().enumerate().map(
+            |(slot, (is_forward, input, output))| {
+                let columns = [Column::constant(F::from_bool(is_forward))]
+                    .into_iter()
+                    .chain(Column::singles(input.into_iter().chain(output)))
+                    .collect();
+                CrossTableLookup::new(
+                    vec![TableWithColumns::new(VPBS_TABLE, columns, non_pad_filter())],
+                    TableWithColumns::new(
+                        NTT_TABLE,
+                        NttStark::<F, D, N, K, ELL, NTT_COLUMNS>::looked_columns(),
+                        Some(NttStark::<F, D, N, K, ELL, NTT_COLUMNS>::looked_filter(
+                            slot,
+                        )),
+                    ),
+                )
+            },
+        );
+
+        [range_check_ctl].into_iter().chain(ntt_ctls).collect()
+    }
+
+    /// Proves a trace of `generate_trace` together with the range checks of its limbs and its NTTs.
+    pub fn prove_with_ctls<C: GenericConfig<D, F = F>>(
+        &self,
+        config: &StarkConfig,
+        trace: Vec<PolynomialValues<F>>,
+        public_inputs: &[F],
+        timing: &mut TimingTree,
+    ) -> Result<MultiProof<F, C, D, NUM_TABLES>> {
+        let range_check = RangeCheckStark::<F, D, N, K, LOGB, RANGE_CHECK_COLUMNS>::new();
+        let ntt = NttStark::<F, D, N, K, ELL, NTT_COLUMNS>::new();
+        let ctls = self.cross_table_lookups();
+
+        let non_pad_rows = (0..trace[0].len())
+            .filter(|&row| trace[Self::NON_PAD_FLAG_COL].values[row] == F::ONE)
+            .collect_vec();
+        let read_row = |columns: &[usize], row: usize| {
+            columns
+                .iter()
+                .map(|&column| trace[column].values[row])
+                .collect_vec()
+        };
+        let limb_columns = (Self::XPROD_IN_DEC_COL..Self::XPROD_PRODUCT_COL).collect_vec();
+        let limb_rows = non_pad_rows
+            .iter()
+            .map(|&row| read_row(&limb_columns, row))
+            .collect_vec();
+        let xprod_ntts = Self::xprod_ntts();
+        let ntt_inputs = non_pad_rows
+            .iter()
+            .flat_map(|&row| {
+                xprod_ntts
+                    .iter()
+                    .enumerate()
+                    .map(move |(slot, (is_forward, input, _))| {
+                        (slot, *is_forward, read_row(input, row))
+                    })
+            })
+            .collect_vec();
+        let range_check_trace = range_check.generate_trace(&limb_rows);
+        let ntt_trace = ntt.generate_trace(&ntt_inputs);
+        let traces = [trace, range_check_trace, ntt_trace];
+
+        let rate_bits = config.fri_config.rate_bits;
+        let cap_height = config.fri_config.cap_height;
+        let commitments = traces.each_ref().map(|trace| {
+            PolynomialBatch::<F, C, D>::from_values(
+                trace.clone(),
+                rate_bits,
+                false,
+                cap_height,
+                timing,
+                None,
+            )
+        });
+
+        let mut challenger = Challenger::<F, C::Hasher>::new();
+        for commitment in &commitments {
+            challenger.observe_cap(&commitment.merkle_tree.cap);
+        }
+
+        let (ctl_challenges, ctl_data) = get_ctl_data::<F, C, D, NUM_TABLES>(
+            config,
+            &traces,
+            &ctls,
+            &mut challenger,
+            range_check.constraint_degree(),
+        );
+
+        let [vpbs_proof, range_check_proof, ntt_proof] = [
+            prove_table(
+                self,
+                config,
+                &traces[VPBS_TABLE],
+                &commitments[VPBS_TABLE],
+                &ctl_data[VPBS_TABLE],
+                &ctl_challenges,
+                &mut challenger,
+                public_inputs,
+                timing,
+            )?,
+            prove_table(
+                &range_check,
+                config,
+                &traces[RANGE_CHECK_TABLE],
+                &commitments[RANGE_CHECK_TABLE],
+                &ctl_data[RANGE_CHECK_TABLE],
+                &ctl_challenges,
+                &mut challenger,
+                &[],
+                timing,
+            )?,
+            prove_table(
+                &ntt,
+                config,
+                &traces[NTT_TABLE],
+                &commitments[NTT_TABLE],
+                &ctl_data[NTT_TABLE],
+                &ctl_challenges,
+                &mut challenger,
+                &[],
+                timing,
+            )?,
+        ];
+
+        Ok(MultiProof {
+            stark_proofs: [vpbs_proof, range_check_proof, ntt_proof],
+            ctl_challenges,
+        })
+    }
+
+    pub fn verify_with_ctls<C: GenericConfig<D, F = F>>(
+        &self,
+        proof: &MultiProof<F, C, D, NUM_TABLES>,
+        public_inputs: &[F],
+        config: &StarkConfig,
+    ) -> Result<()> {
+        ensure!(public_inputs.len() == Self::PUBLIC_INPUTS);
+        let range_check = RangeCheckStark::<F, D, N, K, LOGB, RANGE_CHECK_COLUMNS>::new();
+        let ntt = NttStark::<F, D, N, K, ELL, NTT_COLUMNS>::new();
+        let ctls = self.cross_table_lookups();
+        let [vpbs_proof, range_check_proof, ntt_proof] = &proof.stark_proofs;
+
+        let mut challenger = Challenger::<F, C::Hasher>::new();
+        for stark_proof in &proof.stark_proofs {
+            challenger.observe_cap(&stark_proof.proof.trace_cap);
+        }
+
+        let ctl_challenges =
+            get_grand_product_challenge_set(&mut challenger, config.num_challenges);
+        let [vpbs_challenges, range_check_challenges, ntt_challenges] =
+            proof.stark_proofs.each_ref().map(|stark_proof| {
+                stark_proof.proof.get_challenges(
+                    &mut challenger,
+                    Some(&ctl_challenges),
+                    true,
+                    config,
+                )
+            });
+
+        let num_lookup_columns = [
+            self.num_lookup_helper_columns(config),
+            range_check.num_lookup_helper_columns(config),
+            ntt.num_lookup_helper_columns(config),
+        ];
+        let [vpbs_ctl_vars, range_check_ctl_vars, ntt_ctl_vars] = get_ctl_vars_from_proofs(
+            proof,
+            &ctls,
+            &ctl_challenges,
+            &num_lookup_columns,
+            range_check.constraint_degree(),
+        );
+
+        verify_stark_proof_with_challenges(
+            self,
+            &vpbs_proof.proof,
+            &vpbs_challenges,
+            Some(&vpbs_ctl_vars),
+            public_inputs,
+            config,
+        )?;
+        verify_stark_proof_with_challenges(
+            &range_check,
+            &range_check_proof.proof,
+            &range_check_challenges,
+            Some(&range_check_ctl_vars),
+            &[],
+            config,
+        )?;
+        verify_stark_proof_with_challenges(
+            &ntt,
+            &ntt_proof.proof,
+            &ntt_challenges,
+            Some(&ntt_ctl_vars),
+            &[],
+            config,
+        )?;
+
+        // all the proofs have been checked to open the CTL polynomials
+        let ctl_zs_first = proof
+            .stark_proofs
+            .each_ref()
+            .map(|stark_proof| stark_proof.proof.openings.ctl_zs_first.clone().unwrap());
+        let extra_looking_sums = vec![vec![F::ZERO; config.num_challenges]; NUM_TABLES];
+        verify_cross_table_lookups::<F, D, NUM_TABLES>(
+            &ctls,
+            ctl_zs_first,
+            Some(&extra_looking_sums),
+            config,
+        )
+    }
+
+    pub fn add_virtual_proof_with_ctls(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        config: &StarkConfig,
+        degree_bits: [usize; NUM_TABLES],
+    ) -> VpbsProofTarget<D> {
+        let ctls = self.cross_table_lookups();
+
+        let (num_helpers_zs, num_zs) = num_ctl_polys(&ctls, VPBS_TABLE, config);
+        let vpbs_proof = add_virtual_stark_proof(
+            builder,
+            self,
+            config,
+            degree_bits[VPBS_TABLE],
+            num_helpers_zs,
+            num_zs,
+        );
+        let (num_helpers_zs, num_zs) = num_ctl_polys(&ctls, RANGE_CHECK_TABLE, config);
+        let range_check_proof = add_virtual_stark_proof(
+            builder,
+            &RangeCheckStark::<F, D, N, K, LOGB, RANGE_CHECK_COLUMNS>::new(),
+            config,
+            degree_bits[RANGE_CHECK_TABLE],
+            num_helpers_zs,
+            num_zs,
+        );
+        let (num_helpers_zs, num_zs) = num_ctl_polys(&ctls, NTT_TABLE, config);
+        let ntt_proof = add_virtual_stark_proof(
+            builder,
+            &NttStark::<F, D, N, K, ELL, NTT_COLUMNS>::new(),
+            config,
+            degree_bits[NTT_TABLE],
+            num_helpers_zs,
+            num_zs,
+        );
+
+        VpbsProofTarget {
+            stark_proofs: [vpbs_proof, range_check_proof, ntt_proof],
+            public_inputs: builder.add_virtual_targets(Self::PUBLIC_INPUTS),
+        }
+    }
+
+    pub fn verify_with_ctls_circuit<C: GenericConfig<D, F = F>>(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        proof: &VpbsProofTarget<D>,
+        config: &StarkConfig,
+    ) where
+        C::Hasher: AlgebraicHasher<F>,
+    {
+        let range_check = RangeCheckStark::<F, D, N, K, LOGB, RANGE_CHECK_COLUMNS>::new();
+        let ntt = NttStark::<F, D, N, K, ELL, NTT_COLUMNS>::new();
+        let ctls = self.cross_table_lookups();
+        let [vpbs_proof, range_check_proof, ntt_proof] = &proof.stark_proofs;
+
+        let mut challenger = RecursiveChallenger::<F, C::Hasher, D>::new(builder);
+        for stark_proof in &proof.stark_proofs {
+            challenger.observe_cap(&stark_proof.trace_cap);
+        }
+
+        let ctl_challenges =
+            get_grand_product_challenge_set_target(builder, &mut challenger, config.num_challenges);
+        let [vpbs_challenges, range_check_challenges, ntt_challenges] =
+            proof.stark_proofs.each_ref().map(|stark_proof| {
+                stark_proof.get_challenges::<F, C>(
+                    builder,
+                    &mut challenger,
+                    Some(&ctl_challenges),
+                    true,
+                    config,
+                )
+            });
+
+        let ctl_vars = |table, stark_proof, num_lookup_columns| {
+            let (num_helpers, _, num_helpers_by_ctl) = CrossTableLookup::num_ctl_helpers_zs_all(
+                &ctls,
+                table,
+                config.num_challenges,
+                range_check.constraint_degree(),
+            );
+            CtlCheckVarsTarget::from_proof(
+                table,
+                stark_proof,
+                &ctls,
+                &ctl_challenges,
+                num_lookup_columns,
+                num_helpers,
+                &num_helpers_by_ctl,
+            )
+        };
+        let vpbs_ctl_vars = ctl_vars(
+            VPBS_TABLE,
+            vpbs_proof,
+            self.num_lookup_helper_columns(config),
+        );
+        let range_check_ctl_vars = ctl_vars(
+            RANGE_CHECK_TABLE,
+            range_check_proof,
+            range_check.num_lookup_helper_columns(config),
+        );
+        let ntt_ctl_vars = ctl_vars(NTT_TABLE, ntt_proof, ntt.num_lookup_helper_columns(config));
+
+        verify_stark_proof_with_challenges_circuit::<F, C, _, D>(
+            builder,
+            self,
+            vpbs_proof,
+            &proof.public_inputs,
+            vpbs_challenges,
+            Some(&vpbs_ctl_vars),
+            config,
+        );
+        verify_stark_proof_with_challenges_circuit::<F, C, _, D>(
+            builder,
+            &range_check,
+            range_check_proof,
+            &[],
+            range_check_challenges,
+            Some(&range_check_ctl_vars),
+            config,
+        );
+        verify_stark_proof_with_challenges_circuit::<F, C, _, D>(
+            builder,
+            &ntt,
+            ntt_proof,
+            &[],
+            ntt_challenges,
+            Some(&ntt_ctl_vars),
+            config,
+        );
+
+        let ctl_zs_first = proof
+            .stark_proofs
+            .each_ref()
+            .map(|stark_proof| stark_proof.openings.ctl_zs_first.clone().unwrap());
+        let zero = builder.zero();
+        let extra_looking_sums = vec![vec![zero; config.num_challenges]; NUM_TABLES];
+        verify_cross_table_lookups_circuit::<F, D, NUM_TABLES>(
+            builder,
+            ctls,
+            ctl_zs_first,
+            Some(&extra_looking_sums),
+            config,
+        );
+    }
+}
+
+/// Proves one table of a multi-table proof, recording the challenger state it starts from.
+fn prove_table<F, C, S, const D: usize>(
+    stark: &S,
+    config: &StarkConfig,
+    trace: &[PolynomialValues<F>],
+    commitment: &PolynomialBatch<F, C, D>,
+    ctl_data: &CtlData<F>,
+    ctl_challenges: &GrandProductChallengeSet<F>,
+    challenger: &mut Challenger<F, C::Hasher>,
+    public_inputs: &[F],
+    timing: &mut TimingTree,
+) -> Result<StarkProofWithMetadata<F, C, D>>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    S: Stark<F, D>,
+{
+    let init_challenger_state = challenger.clone().compact();
+    let proof = prove_with_commitment(
+        stark,
+        config,
+        trace,
+        commitment,
+        Some(ctl_data),
+        Some(ctl_challenges),
+        challenger,
+        public_inputs,
+        timing,
+    )?;
+
+    Ok(StarkProofWithMetadata {
+        init_challenger_state,
+        proof: proof.proof,
+    })
+}
+
+pub fn set_proof_with_ctls_target<F, C: GenericConfig<D, F = F>, W, const D: usize>(
+    witness: &mut W,
+    proof_target: &VpbsProofTarget<D>,
+    proof: &MultiProof<F, C, D, NUM_TABLES>,
+    public_inputs: &[F],
+    zero: Target,
+) where
+    F: RichField + Extendable<D>,
+    C::Hasher: AlgebraicHasher<F>,
+    W: Witness<F>,
+{
+    for (stark_proof_target, stark_proof) in
+        proof_target.stark_proofs.iter().zip(&proof.stark_proofs)
+    {
+        set_stark_proof_target(witness, stark_proof_target, &stark_proof.proof, zero);
     }
+    witness.set_target_arr(&proof_target.public_inputs, public_inputs);
 }
 
-#[test]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vtfhe::crypto::{compute_bsk, get_testv, lwe::encrypt};
+    use crate::vtfhe::ivc_based_vpbs::{PbsObserver, PbsStep};
+    use crate::vtfhe::noise::NoiseObserver;
+    use plonky2::{
+        iop::witness::PartialWitness,
+        plonk::{circuit_data::CircuitConfig, config::PoseidonGoldilocksConfig},
+        util::log2_ceil,
+    };
+    use rand::random;
+    use starky::{
+        prover::prove,
+        recursive_verifier::{
+            add_virtual_stark_proof_with_pis, set_stark_proof_with_pis_target,
+            verify_stark_proof_circuit,
+        },
+        stark_testing::test_stark_low_degree,
+        verifier::verify_stark_proof,
+    };
 
-fn test_vpbs() {
     const D: usize = 2;
     type C = PoseidonGoldilocksConfig;
     type F = <C as GenericConfig<D>>::F;
-    type S = VpbsStark<F, D>;
-    let stark = VpbsStark::<F, D> {
-        _phantom: PhantomData,
-    };
 
-    println!("reached till here");
-    let mut config = StarkConfig::standard_fast_config();
-    config.fri_config.rate_bits = 4;
-    println!("start stark proof generation");
-    let now = Instant::now();
-    let trace = stark.generate_trace();
-    let inner_proof =
-        prove::<F, C, S, D>(stark, &config, trace, &[], &mut TimingTree::default()).unwrap();
-    // verify_stark_proof(stark, inner_proof.clone(), &config).unwrap();
-    println!("end stark proof generation: {:?}", now.elapsed());
-
-    let circuit_config = CircuitConfig::standard_recursion_config();
-    let mut builder = CircuitBuilder::<F, D>::new(circuit_config);
-    let mut pw = PartialWitness::new();
-    let degree_bits = inner_proof.proof.recover_degree_bits(&config);
-    let pt = add_virtual_stark_proof_with_pis(&mut builder, &stark, &config, degree_bits, 0, 0);
-    set_stark_proof_with_pis_target(&mut pw, &pt, &inner_proof, builder.zero());
-    verify_stark_proof_circuit::<F, C, S, D>(&mut builder, stark, pt, &config);
-    let data = builder.build::<C>();
-
-    dbg!(degree_bits);
-    println!("start plonky2 proof generation");
-    let now = Instant::now();
-    let _proof = data.prove(pw).unwrap();
-    println!("end plonky2 proof generation: {:?}", now.elapsed());
+    // the STARKs of the tests, with n = 4, N = 8, K = 2, ELL = 4 and LOGB = 5
+    type TestVpbsStark = vpbs_stark!(F, D, 4, 8, 2, 4, 5, false);
+    type TestVpbsStarkLogup = vpbs_stark!(F, D, 4, 8, 2, 4, 5, true);
+
+    #[test]
+    fn test_vpbs() {
+        prove_and_verify_pbs(TestVpbsStark::new(), 2);
+    }
+
+    #[test]
+    fn test_vpbs_logup() {
+        prove_and_verify_pbs(TestVpbsStarkLogup::new(), 2);
+    }
+
+    #[test]
+    fn test_vpbs_low_degree() {
+        test_stark_low_degree(TestVpbsStark::new()).unwrap();
+        test_stark_low_degree(TestVpbsStarkLogup::new()).unwrap();
+    }
+
+    fn prove_and_verify_pbs<
+        const n: usize,
+        const N: usize,
+        const K: usize,
+        const ELL: usize,
+        const LOGB: usize,
+        const LOGUP: bool,
+        const COLUMNS: usize,
+        const PUBLIC_INPUTS: usize,
+        const RANGE_CHECK_COLUMNS: usize,
+        const NTT_COLUMNS: usize,
+    >(
+        stark: VpbsStark<
+            F,
+            D,
+            n,
+            N,
+            K,
+            ELL,
+            LOGB,
+            LOGUP,
+            COLUMNS,
+            PUBLIC_INPUTS,
+            RANGE_CHECK_COLUMNS,
+            NTT_COLUMNS,
+        >,
+        p: usize,
+    ) {
+        // partial GLWE key corresponding to LWE key
+        let s_to = Glwe::<F, D, N, K>::partial_key(n);
+        let s_lwe = Glwe::<F, D, N, K>::flatten_partial_key(&s_to, n);
+
+        let s_glwe = Glwe::<F, D, N, K>::key_gen();
+        let bsk = compute_bsk::<F, D, N, K, ELL, LOGB>(&s_lwe, &s_glwe, 0f64);
+
+        let ksk = Ggsw::<F, D, N, K, ELL>::compute_ksk::<LOGB>(&s_to, &s_glwe, 0f64);
+
+        let delta = F::from_noncanonical_biguint(F::order() >> log2_ceil(2 * p));
+
+        let testv = get_testv::<F, D, N>(p, delta);
+        let m = F::from_canonical_usize(random::<usize>() % p);
+        let ct = encrypt::<F, D, n>(&s_lwe, &(delta * m), 0f64);
+
+        let config = StarkConfig::standard_fast_config();
+        let (trace, public_inputs, out_ct) = stark.generate_trace(&ct, &testv, &bsk, &ksk);
+        let num_rows = (n + 2).next_power_of_two().max(8);
+        assert_eq!(trace[0].len(), num_rows);
+
+        // the output encrypts the test vector rotated by the mod switched ct, up to the error of
+        // the decomposition, which is far below delta / 2
+        let mut noise_observer = NoiseObserver::new(&ct, &testv, &s_glwe, &s_lwe, &s_to);
+        noise_observer.observe(PbsStep::KeySwitch, &out_ct);
+        assert!(noise_observer.report().steps[0].max_error < 1.0 / (4 * p) as f64);
+
+        // the public inputs are the initial and output accumulators and the hashes chained over
+        // the GGSWs and the mask elements of the steps
+        let acc_init = Glwe::<F, D, N, K>::trivial_ct(testv.clone());
+        let bsk_hash = [GgswCtNative::dummy_ct()]
+            .into_iter()
+            .chain(bsk.iter().map(GgswCtNative::from_ggsw))
+            .chain([GgswCtNative::from_ggsw(&ksk)])
+            .fold(HashOut::ZERO, |hash, ggsw_ct| {
+                hash_no_pad_native(&[&hash.elements[..], &ggsw_ct.flatten()].concat()).0
+            });
+        let lwe_hash = [ct[n]]
+            .into_iter()
+            .chain(ct[..n].iter().copied())
+            .chain([F::ZERO])
+            .fold(HashOut::ZERO, |hash, mask_ele| {
+                hash_no_pad_native(&[&hash.elements[..], &[mask_ele]].concat()).0
+            });
+        assert_eq!(
+            public_inputs,
+            [
+                acc_init.flatten(),
+                out_ct.flatten(),
+                bsk_hash.elements.to_vec(),
+                lwe_hash.elements.to_vec()
+            ]
+            .concat()
+        );
+
+        let circuit_config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(circuit_config);
+        let mut pw = PartialWitness::new();
+
+        let degree_bits = if LOGUP {
+            let inner_proof = stark
+                .prove_with_ctls::<C>(&config, trace, &public_inputs, &mut TimingTree::default())
+                .unwrap();
+            stark
+                .verify_with_ctls(&inner_proof, &public_inputs, &config)
+                .unwrap();
+
+            let degree_bits = inner_proof.recover_degree_bits(&config);
+            let pt = stark.add_virtual_proof_with_ctls(&mut builder, &config, degree_bits);
+            set_proof_with_ctls_target(&mut pw, &pt, &inner_proof, &public_inputs, builder.zero());
+            stark.verify_with_ctls_circuit::<C>(&mut builder, &pt, &config);
+            degree_bits[VPBS_TABLE]
+        } else {
+            let inner_proof = prove::<F, C, _, D>(
+                stark,
+                &config,
+                trace,
+                &public_inputs,
+                &mut TimingTree::default(),
+            )
+            .unwrap();
+            verify_stark_proof(stark, inner_proof.clone(), &config).unwrap();
+
+            let degree_bits = inner_proof.proof.recover_degree_bits(&config);
+            let pt =
+                add_virtual_stark_proof_with_pis(&mut builder, &stark, &config, degree_bits, 0, 0);
+            set_stark_proof_with_pis_target(&mut pw, &pt, &inner_proof, builder.zero());
+            verify_stark_proof_circuit::<F, C, _, D>(&mut builder, stark, pt, &config);
+            degree_bits
+        };
+        assert_eq!(degree_bits, log2_ceil(num_rows));
+        let data = builder.build::<C>();
+        let proof = data.prove(pw).unwrap();
+        data.verify(proof).unwrap();
+    }
 }
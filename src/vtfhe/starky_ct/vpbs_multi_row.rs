@@ -0,0 +1,1092 @@
+//| cur_acc_in |     glev_ct     | mask_ele | mask_ele_bit_dec | acc_rotations | xprod_in_poly | xprod_in_bit_dec | xprod_in_neg_bit_dec | xprod_in_top_limbs | bit_dec_hi_diff_inv | xprod_sum_in | xprod_sum_out | cur_acc_out | bsk_hash_in |             glev_hash_columns          | lwe_hash_in |             lwe_hash_columns          | counter | glev_flags | is_first_step | is_last_step | is_last_non_pad_row |
+//|    N * K   |   K * N * ELL   |     1    |     NUM_BITS     |  ROT * N * K  |       N       |   NUM_BITS * N   |     NUM_BITS * N     |       ELL * N      |      1 + 2 * N      |    N * K     |     N * K     |    N * K    |      4      |           GLEV_HASH_COLUMNS            |      4      |           LWE_HASH_COLUMNS            |    1    |      K     |       1       |       1      |          1          |
+//|    GLWE    |      GLEV       |                                     |   ROT GLWEs   |                    of one polynomial of xprod_in                     |                     |     GLWE     |      GLWE     |     GLWE    |
+//
+// Public inputs:
+//| acc_init | acc_out | bsk_hash | lwe_hash |
+//|   N * K  |  N * K  |     4    |     4    |
+//|   GLWE   |   GLWE  |
+//
+// The layout is the one of `VpbsStark`, and acc_init, acc_out and lwe_hash are the same for the
+// same PBS. bsk_hash is not: it is chained from the zero hash over the GLEVs of the dummy GGSW,
+// the BSK and the KSK, one GLEV per row, while `VpbsStark` chains it over the whole GGSWs.
+//
+// Same PBS as `VpbsStark`, with a step spread over K rows: row j of a step holds the GLEV j of
+// the GGSW and the decompositions of the polynomial j of the external product input, and
+// glev_flags[j] is set. That polynomial is committed in xprod_in_poly and the top ELL balanced
+// limbs of its decomposition in xprod_in_top_limbs. xprod_sum_in carries the partial sum of the
+// external product in the NTT domain over the rows of a step, and xprod_sum_out is the sum with
+// the product of the row, so the GGSW and bit decomposition columns are linear in K instead of
+// quadratic. cur_acc_in, the mask element and the flags of the step are kept over its rows, and
+// cur_acc_out of the last row of a step is the accumulator of the next step.
+//
+// Like in `VpbsStark`, acc_rotations holds the accumulator after each of the ROT = log2(N) + 2
+// selections of the rotation, and the intermediate values are committed so that no constraint
+// has a degree above 3. The constraints binding them are not filtered, they also hold on the
+// padding rows.
+//
+// lwe_hash is computed in every row and only chained at the start of a step.
+use crate::vtfhe::{
+    crypto::{ggsw::Ggsw, glwe::Glwe, poly::Poly},
+    eval_glwe_select, eval_glwe_select_ext, eval_rotate_glwe_stages, eval_rotate_glwe_stages_ext,
+    num_rotation_stages, rotate_glwe_stages_native,
+    starky_ct::{
+        generate_build_circuit_input,
+        ggsw_ct::GgswCtNative,
+        glwe_ct::GlweCtNative,
+        glwe_poly::{
+            canonical_bits_inv_native, decompose_native, eval_canonical_bits,
+            eval_canonical_bits_ext, num_limbs,
+        },
+        poseidon::{
+            eval_hash_no_pad, eval_hash_no_pad_ext, hash_no_pad_columns, hash_no_pad_native,
+        },
+        vpbs::vpbs_public_inputs,
+    },
+    NUM_BITS,
+};
+use std::{array::from_fn, marker::PhantomData};
+
+use itertools::Itertools;
+use plonky2::{
+    field::{
+        extension::{Extendable, FieldExtension},
+        packed::PackedField,
+        polynomial::PolynomialValues,
+        types::Field,
+    },
+    hash::hash_types::{HashOut, RichField, NUM_HASH_OUT_ELTS},
+    iop::ext_target::ExtensionTarget,
+    plonk::circuit_builder::CircuitBuilder,
+    util::transpose,
+};
+use starky::{
+    constraint_consumer::{ConstraintConsumer, RecursiveConstraintConsumer},
+    evaluation_frame::{StarkEvaluationFrame, StarkFrame},
+    stark::Stark,
+};
+
+use super::{
+    glwe_ct::decimal_to_binary, read_array, read_glev_ct, read_glwe_ct, read_glwe_poly,
+    write_array, write_glev_ct, write_glwe_ct, write_glwe_poly,
+};
+
+const LWE_HASH_COLUMNS: usize = hash_no_pad_columns(NUM_HASH_OUT_ELTS + 1);
+
+const fn glev_hash_columns<const N: usize, const K: usize, const ELL: usize>() -> usize {
+    hash_no_pad_columns(NUM_HASH_OUT_ELTS + K * N * ELL)
+}
+
+/// Width of the trace of `VpbsMultiRowStark` for ring dimension `N`, GLWE dimension `K` and
+/// `ELL` levels.
+pub const fn vpbs_multi_row_columns<const N: usize, const K: usize, const ELL: usize>() -> usize {
+    N * K
+        + K * N * ELL
+        + 1
+        + NUM_BITS
+        + num_rotation_stages::<N>() * N * K
+        + N
+        + 2 * NUM_BITS * N
+        + ELL * N
+        + 1
+        + 2 * N
+        + 3 * N * K
+        + NUM_HASH_OUT_ELTS
+        + glev_hash_columns::<N, K, ELL>()
+        + NUM_HASH_OUT_ELTS
+        + LWE_HASH_COLUMNS
+        + 1
+        + K
+        + 3
+}
+
+/// The `VpbsMultiRowStark` of the given `F, D, n, N, K, ELL, LOGB`, with the width of its trace
+/// and its number of public inputs computed from them. The parameters must be constants.
+// only the tests name a `VpbsMultiRowStark` in this binary
+#[allow(unused_macros)]
+macro_rules! vpbs_multi_row_stark {
+    ($F:ty, $D:tt, $n:tt, $N:tt, $K:tt, $ELL:tt, $LOGB:tt) => {
+        $crate::vtfhe::starky_ct::vpbs_multi_row::VpbsMultiRowStark<
+            $F,
+            $D,
+            $n,
+            $N,
+            $K,
+            $ELL,
+            $LOGB,
+            { $crate::vtfhe::starky_ct::vpbs_multi_row::vpbs_multi_row_columns::<$N, $K, $ELL>() },
+            { $crate::vtfhe::starky_ct::vpbs::vpbs_public_inputs::<$N, $K>() },
+        >
+    };
+}
+#[allow(unused_imports)]
+pub(crate) use vpbs_multi_row_stark;
+
+/// STARK of the same PBS as `VpbsStark` with K rows per step, which trades a K times longer
+/// trace for K times fewer GGSW and bit decomposition columns. The accumulator and its rotations
+/// are still in every row, and the bsk_hash of its public inputs is chained over GLEVs, see the
+/// layout above. `COLUMNS` and `PUBLIC_INPUTS` are the width of the trace,
+/// `vpbs_multi_row_columns`, and the number of public inputs, `vpbs_public_inputs`. They are
+/// derived from the other parameters, so name the type with `vpbs_multi_row_stark!` instead.
+#[derive(Clone, Copy)]
+pub struct VpbsMultiRowStark<
+    F: RichField + Extendable<D>,
+    const D: usize,
+    const n: usize,
+    const N: usize,
+    const K: usize,
+    const ELL: usize,
+    const LOGB: usize,
+    const COLUMNS: usize,
+    const PUBLIC_INPUTS: usize,
+> {
+    _phantom: PhantomData<F>,
+}
+
+impl<
+        F: RichField + Extendable<D>,
+        const D: usize,
+        const n: usize,
+        const N: usize,
+        const K: usize,
+        const ELL: usize,
+        const LOGB: usize,
+        const COLUMNS: usize,
+        const PUBLIC_INPUTS: usize,
+    > VpbsMultiRowStark<F, D, n, N, K, ELL, LOGB, COLUMNS, PUBLIC_INPUTS>
+{
+    const GLEV_HASH_COLUMNS: usize = glev_hash_columns::<N, K, ELL>();
+
+    const GLEV_COL: usize = N * K;
+    const MASK_ELE_COL: usize = Self::GLEV_COL + K * N * ELL;
+    const ROTATIONS_COL: usize = Self::MASK_ELE_COL + 1 + NUM_BITS;
+    const XPROD_IN_POLY_COL: usize = Self::ROTATIONS_COL + num_rotation_stages::<N>() * N * K;
+    const XPROD_IN_DEC_COL: usize = Self::XPROD_IN_POLY_COL + N;
+    const TOP_LIMBS_COL: usize = Self::XPROD_IN_DEC_COL + 2 * NUM_BITS * N;
+    const HI_DIFF_INV_COL: usize = Self::TOP_LIMBS_COL + ELL * N;
+    const XPROD_SUM_IN_COL: usize = Self::HI_DIFF_INV_COL + 1 + 2 * N;
+    const BSK_HASH_IN_COL: usize = Self::XPROD_SUM_IN_COL + 3 * N * K;
+    const LWE_HASH_IN_COL: usize =
+        Self::BSK_HASH_IN_COL + NUM_HASH_OUT_ELTS + Self::GLEV_HASH_COLUMNS;
+    const COUNTER_COL: usize = Self::LWE_HASH_IN_COL + NUM_HASH_OUT_ELTS + LWE_HASH_COLUMNS;
+    const GLEV_FLAGS_COL: usize = Self::COUNTER_COL + 1;
+    const IS_FIRST_STEP_COL: usize = Self::GLEV_FLAGS_COL + K;
+    const IS_LAST_STEP_COL: usize = Self::IS_FIRST_STEP_COL + 1;
+
+    pub fn new() -> Self {
+        assert_eq!(COLUMNS, vpbs_multi_row_columns::<N, K, ELL>());
+        assert_eq!(PUBLIC_INPUTS, vpbs_public_inputs::<N, K>());
+        Self {
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Columns kept over the rows of a step.
+    fn step_columns() -> impl Iterator<Item = usize> {
+        (0..Self::GLEV_COL)
+            .chain(Self::MASK_ELE_COL..Self::ROTATIONS_COL)
+            .chain(Self::LWE_HASH_IN_COL..Self::LWE_HASH_IN_COL + NUM_HASH_OUT_ELTS)
+            .chain([Self::IS_FIRST_STEP_COL, Self::IS_LAST_STEP_COL])
+    }
+
+    /// Fills the K rows of a step and returns the accumulator it computes.
+    #[allow(clippy::too_many_arguments)]
+    fn fill_step(
+        &self,
+        trace_rows: &mut Vec<Vec<F>>,
+        cur_acc_in: &GlweCtNative<F, D, N, K>,
+        ggsw_ct: &GgswCtNative<F, D, N, K, ELL>,
+        mask_ele: F,
+        bsk_hash: &mut HashOut<F>,
+        lwe_hash: &mut HashOut<F>,
+        counter: usize,
+    ) -> GlweCtNative<F, D, N, K> {
+        let is_first_step = counter == 1;
+        let is_last_step = counter == n + 2;
+
+        let (cur_acc_out, xprod_in_bit_dec, xprod_in_neg_bit_dec) =
+            generate_build_circuit_input::<F, D, n, N, K, ELL, LOGB>(
+                cur_acc_in,
+                ggsw_ct,
+                mask_ele,
+                F::from_canonical_usize(counter),
+            );
+
+        let neg_first_mask = if is_first_step { -mask_ele } else { mask_ele };
+        let mask_bit_dec = decimal_to_binary::<F, D>(neg_first_mask.to_canonical_u64());
+        let rotations = rotate_glwe_stages_native(cur_acc_in, mask_bit_dec);
+        let shifted_glwe = rotations.last().unwrap();
+        let xprod_in = if is_last_step {
+            cur_acc_in.clone()
+        } else {
+            shifted_glwe.sub(cur_acc_in)
+        };
+        let num_limbs = num_limbs::<LOGB>();
+
+        let (lwe_hash_out, lwe_hash_columns) =
+            hash_no_pad_native(&[&lwe_hash.elements[..], &[mask_ele]].concat());
+        let mut xprod_sum = GlweCtNative::<F, D, N, K>::dummy_ct();
+
+        for (j, glev_ct) in ggsw_ct.glev_cts.iter().enumerate() {
+            let mut lv = vec![F::ZERO; COLUMNS];
+            let cur_col = &mut 0;
+
+            write_glwe_ct(&mut lv, cur_acc_in, cur_col);
+            assert_eq!(*cur_col, Self::GLEV_COL);
+
+            write_glev_ct(&mut lv, glev_ct, cur_col);
+            assert_eq!(*cur_col, Self::MASK_ELE_COL);
+
+            lv[*cur_col] = mask_ele;
+            *cur_col += 1;
+            write_array(&mut lv, cur_col, &mask_bit_dec);
+            assert_eq!(*cur_col, Self::ROTATIONS_COL);
+
+            for glwe in &rotations {
+                write_glwe_ct(&mut lv, glwe, cur_col);
+            }
+            assert_eq!(*cur_col, Self::XPROD_IN_POLY_COL);
+
+            write_glwe_poly(&mut lv, &xprod_in.polys[j], cur_col);
+            assert_eq!(*cur_col, Self::XPROD_IN_DEC_COL);
+
+            for coeff_bit in xprod_in_bit_dec[j].iter().chain(&xprod_in_neg_bit_dec[j]) {
+                write_array(&mut lv, cur_col, coeff_bit);
+            }
+            assert_eq!(*cur_col, Self::TOP_LIMBS_COL);
+
+            let limbs = xprod_in_bit_dec[j]
+                .iter()
+                .zip(&xprod_in_neg_bit_dec[j])
+                .map(|(bit_dec, neg_bit_dec)| {
+                    decompose_native::<F, D, LOGB>(*bit_dec, *neg_bit_dec)
+                })
+                .collect_vec();
+            for l in num_limbs - ELL..num_limbs {
+                for coeff_limbs in &limbs {
+                    lv[*cur_col] = coeff_limbs[l];
+                    *cur_col += 1;
+                }
+            }
+            assert_eq!(*cur_col, Self::HI_DIFF_INV_COL);
+
+            for bit_dec in [&mask_bit_dec]
+                .into_iter()
+                .chain(&xprod_in_bit_dec[j])
+                .chain(&xprod_in_neg_bit_dec[j])
+            {
+                lv[*cur_col] = canonical_bits_inv_native(bit_dec);
+                *cur_col += 1;
+            }
+            assert_eq!(*cur_col, Self::XPROD_SUM_IN_COL);
+
+            write_glwe_ct(&mut lv, &xprod_sum, cur_col);
+            let glev_mul = glev_ct.mul::<LOGB>(
+                &xprod_in.polys[j],
+                &xprod_in_bit_dec[j],
+                &xprod_in_neg_bit_dec[j],
+            );
+            xprod_sum = if j == K - 1 {
+                xprod_sum.add(&glev_mul)
+            } else {
+                xprod_sum.sub(&glev_mul)
+            };
+            write_glwe_ct(&mut lv, &xprod_sum, cur_col);
+
+            // the output of the partial sum, which is the accumulator of the next step in the last
+            // row of the step
+            let xprod_out = xprod_sum.ntt_backward();
+            let acc_out = if is_first_step {
+                shifted_glwe.clone()
+            } else if is_last_step {
+                xprod_out
+            } else {
+                xprod_out.add(cur_acc_in)
+            };
+            if j == K - 1 {
+                assert_eq!(acc_out, cur_acc_out);
+            }
+            write_glwe_ct(&mut lv, &acc_out, cur_col);
+            assert_eq!(*cur_col, Self::BSK_HASH_IN_COL);
+
+            let (bsk_hash_out, bsk_hash_columns) = hash_no_pad_native(
+                &[
+                    &bsk_hash.elements[..],
+                    &lv[Self::GLEV_COL..Self::MASK_ELE_COL],
+                ]
+                .concat(),
+            );
+            write_array(&mut lv, cur_col, &bsk_hash.elements);
+            lv[*cur_col..*cur_col + bsk_hash_columns.len()].copy_from_slice(&bsk_hash_columns);
+            *cur_col += bsk_hash_columns.len();
+            *bsk_hash = bsk_hash_out;
+            assert_eq!(*cur_col, Self::LWE_HASH_IN_COL);
+
+            write_array(&mut lv, cur_col, &lwe_hash.elements);
+            lv[*cur_col..*cur_col + lwe_hash_columns.len()].copy_from_slice(&lwe_hash_columns);
+            *cur_col += lwe_hash_columns.len();
+            assert_eq!(*cur_col, Self::COUNTER_COL);
+
+            lv[*cur_col] = F::from_canonical_usize(counter);
+            *cur_col += 1;
+            lv[*cur_col + j] = F::ONE;
+            *cur_col += K;
+            lv[*cur_col] = F::from_bool(is_first_step);
+            lv[*cur_col + 1] = F::from_bool(is_last_step);
+            lv[*cur_col + 2] = F::from_bool(is_last_step && j == K - 1);
+            *cur_col += 3;
+            assert_eq!(*cur_col, COLUMNS);
+
+            trace_rows.push(lv);
+        }
+        *lwe_hash = lwe_hash_out;
+
+        cur_acc_out
+    }
+
+    /// Computes the trace of a PBS of `ct` with `testv`, `bsk` and `ksk`, the same inputs as
+    /// `VpbsStark::generate_trace`, together with the public inputs and the output GLWE
+    /// ciphertext.
+    pub fn generate_trace(
+        &self,
+        ct: &[F],
+        testv: &Poly<F, D, N>,
+        bsk: &[Ggsw<F, D, N, K, ELL>],
+        ksk: &Ggsw<F, D, N, K, ELL>,
+    ) -> (Vec<PolynomialValues<F>>, Vec<F>, Glwe<F, D, N, K>) {
+        assert_eq!(ct.len(), n + 1, "LWE ciphertext does not match n={n}.");
+        assert_eq!(bsk.len(), n, "BSK does not match n={n}.");
+
+        let num_rows = ((n + 2) * K).next_power_of_two().max(8);
+        let mut trace_rows = Vec::with_capacity(num_rows);
+
+        let coeffs = vec![F::ZERO; N * (K - 1)]
+            .into_iter()
+            .chain(testv.coeffs)
+            .collect_vec();
+        let acc_init = GlweCtNative::new_from_slice(&coeffs);
+        let mut bsk_hash = HashOut::ZERO;
+        let mut lwe_hash = HashOut::ZERO;
+
+        // body rotation, n CMUX steps and the key switch
+        let steps = [(GgswCtNative::dummy_ct(), ct[n])]
+            .into_iter()
+            .chain(
+                bsk.iter()
+                    .zip(ct)
+                    .map(|(ggsw, mask_ele)| (GgswCtNative::from_ggsw(ggsw), *mask_ele)),
+            )
+            .chain([(GgswCtNative::from_ggsw(ksk), F::ZERO)]);
+
+        let mut acc = acc_init.clone();
+        for (i, (ggsw_ct, mask_ele)) in steps.enumerate() {
+            acc = self.fill_step(
+                &mut trace_rows,
+                &acc,
+                &ggsw_ct,
+                mask_ele,
+                &mut bsk_hash,
+                &mut lwe_hash,
+                i + 1,
+            );
+        }
+        assert_eq!(trace_rows.len(), (n + 2) * K);
+
+        // the padding rows keep the counter of the last step and hash zero inputs, as the hash
+        // constraints are not filtered
+        let mut padding_row = vec![F::ZERO; COLUMNS];
+        let bsk_hash_col = Self::BSK_HASH_IN_COL + NUM_HASH_OUT_ELTS;
+        padding_row[bsk_hash_col..Self::LWE_HASH_IN_COL].copy_from_slice(
+            &hash_no_pad_native(&vec![F::ZERO; NUM_HASH_OUT_ELTS + K * N * ELL]).1,
+        );
+        let lwe_hash_col = Self::LWE_HASH_IN_COL + NUM_HASH_OUT_ELTS;
+        padding_row[lwe_hash_col..Self::COUNTER_COL]
+            .copy_from_slice(&hash_no_pad_native(&[F::ZERO; NUM_HASH_OUT_ELTS + 1]).1);
+        padding_row[Self::COUNTER_COL] = F::from_canonical_usize(n + 2);
+        trace_rows.resize(num_rows, padding_row);
+
+        let public_inputs = acc_init
+            .flatten()
+            .into_iter()
+            .chain(acc.flatten())
+            .chain(bsk_hash.elements)
+            .chain(lwe_hash.elements)
+            .collect_vec();
+
+        (
+            transpose(&trace_rows)
+                .into_iter()
+                .map(PolynomialValues::new)
+                .collect(),
+            public_inputs,
+            Glwe::from_slice(&acc.flatten()),
+        )
+    }
+}
+
+impl<
+        F: RichField + Extendable<D>,
+        const D: usize,
+        const n: usize,
+        const N: usize,
+        const K: usize,
+        const ELL: usize,
+        const LOGB: usize,
+        const COLUMNS: usize,
+        const PUBLIC_INPUTS: usize,
+    > Stark<F, D> for VpbsMultiRowStark<F, D, n, N, K, ELL, LOGB, COLUMNS, PUBLIC_INPUTS>
+{
+    type EvaluationFrame<FE, P, const D2: usize>
+        = StarkFrame<P, P::Scalar, COLUMNS, PUBLIC_INPUTS>
+    where
+        FE: FieldExtension<D2, BaseField = F>,
+        P: PackedField<Scalar = FE>;
+
+    type EvaluationFrameTarget =
+        StarkFrame<ExtensionTarget<D>, ExtensionTarget<D>, COLUMNS, PUBLIC_INPUTS>;
+
+    fn eval_packed_generic<FE, P, const D2: usize>(
+        &self,
+        vars: &Self::EvaluationFrame<FE, P, D2>,
+        yield_constr: &mut ConstraintConsumer<P>,
+    ) where
+        FE: FieldExtension<D2, BaseField = F>,
+        P: PackedField<Scalar = FE>,
+    {
+        let lv = vars.get_local_values();
+        let nv = vars.get_next_values();
+        let public_inputs = vars.get_public_inputs();
+        let mut cur_col = 0;
+
+        let current_acc_in = read_glwe_ct::<P, N, K>(lv, &mut cur_col);
+        let glev_ct = read_glev_ct::<P, N, K, ELL>(lv, &mut cur_col);
+        let mask_element = lv[cur_col];
+        cur_col += 1;
+
+        let mask_ele_bit_dec = read_array(lv, &mut cur_col);
+        let rotations = (0..num_rotation_stages::<N>())
+            .map(|_| read_glwe_ct::<P, N, K>(lv, &mut cur_col))
+            .collect_vec();
+        let xprod_in_poly = read_glwe_poly::<P, N>(lv, &mut cur_col);
+        let xprod_in_bit_dec: [[P; NUM_BITS]; N] =
+            from_fn(|_| read_array::<P, NUM_BITS>(lv, &mut cur_col));
+        let xprod_in_neg_bit_dec: [[P; NUM_BITS]; N] =
+            from_fn(|_| read_array::<P, NUM_BITS>(lv, &mut cur_col));
+        let top_limbs = &lv[cur_col..cur_col + ELL * N];
+        cur_col += top_limbs.len();
+
+        let hi_diff_invs = &lv[cur_col..cur_col + 1 + 2 * N];
+        cur_col += hi_diff_invs.len();
+
+        let xprod_sum_in = read_glwe_ct::<P, N, K>(lv, &mut cur_col);
+        let xprod_sum_out = read_glwe_ct::<P, N, K>(lv, &mut cur_col);
+        let current_acc_out = read_glwe_ct::<P, N, K>(lv, &mut cur_col);
+
+        let bsk_hash_in: [P; NUM_HASH_OUT_ELTS] = read_array(lv, &mut cur_col);
+        let bsk_hash_columns = &lv[cur_col..cur_col + Self::GLEV_HASH_COLUMNS];
+        cur_col += Self::GLEV_HASH_COLUMNS;
+
+        let lwe_hash_in: [P; NUM_HASH_OUT_ELTS] = read_array(lv, &mut cur_col);
+        let lwe_hash_columns = &lv[cur_col..cur_col + LWE_HASH_COLUMNS];
+        cur_col += LWE_HASH_COLUMNS;
+
+        let counter = lv[cur_col];
+        cur_col += 1;
+
+        let glev_flags = &lv[cur_col..cur_col + K];
+        cur_col += K;
+
+        let is_first_step = lv[cur_col];
+        cur_col += 1;
+
+        let is_last_step = lv[cur_col];
+        cur_col += 1;
+
+        let is_last_non_pad_row = lv[cur_col];
+
+        assert_eq!(cur_col + 1, COLUMNS);
+
+        let one = P::ONES;
+        let num_steps = P::Scalar::from_canonical_usize(n + 2);
+
+        // a row is in at most one GLEV row of a step, and padding rows are in none
+        let non_pad_flag: P = glev_flags.iter().copied().sum();
+        let next_glev_flags = &nv[Self::GLEV_FLAGS_COL..Self::GLEV_FLAGS_COL + K];
+        let next_non_pad_flag: P = next_glev_flags.iter().copied().sum();
+        let next_is_step_start = next_glev_flags[0];
+        let next_is_same_step = next_non_pad_flag - next_is_step_start;
+
+        for flag in glev_flags.iter().chain([
+            &non_pad_flag,
+            &is_first_step,
+            &is_last_step,
+            &is_last_non_pad_row,
+        ]) {
+            yield_constr.constraint(*flag * *flag - *flag);
+        }
+
+        // the GLEV rows of a step follow each other, and a step may only be followed by padding
+        // once it is complete
+        yield_constr.constraint_first_row(glev_flags[0] - one);
+        for j in 1..K {
+            yield_constr.constraint_first_row(glev_flags[j]);
+            yield_constr.constraint_transition(next_glev_flags[j] - glev_flags[j - 1]);
+        }
+        yield_constr
+            .constraint_transition(next_is_step_start - glev_flags[K - 1] * next_non_pad_flag);
+
+        for col in Self::step_columns() {
+            yield_constr.constraint_transition(next_is_same_step * (nv[col] - lv[col]));
+        }
+
+        yield_constr.constraint_first_row(counter - one);
+        yield_constr.constraint_transition(nv[Self::COUNTER_COL] - counter - next_is_step_start);
+
+        // only the first step does the body rotation, and only the last one the key switch
+        yield_constr.constraint_first_row(is_first_step - one);
+        yield_constr.constraint(is_first_step * (counter - one));
+        yield_constr.constraint(is_last_step * (counter - num_steps));
+
+        // the last non padding row ends the last step
+        yield_constr
+            .constraint_transition(is_last_non_pad_row - non_pad_flag * (one - next_non_pad_flag));
+        yield_constr.constraint_last_row(is_last_non_pad_row - non_pad_flag);
+        yield_constr.constraint(is_last_non_pad_row * (one - is_last_step));
+        yield_constr.constraint(is_last_non_pad_row * (one - glev_flags[K - 1]));
+
+        // a 64 bit decomposition of a field element is only unique if it encodes a value < p
+        for (bit_dec, hi_diff_inv) in [&mask_ele_bit_dec]
+            .into_iter()
+            .chain(&xprod_in_bit_dec)
+            .chain(&xprod_in_neg_bit_dec)
+            .zip_eq(hi_diff_invs)
+        {
+            eval_canonical_bits(yield_constr, bit_dec, *hi_diff_inv);
+        }
+
+        let neg_mask = -mask_element;
+        let first_negated_mask = is_first_step * (neg_mask - mask_element) + mask_element;
+        let shifted_glwe = eval_rotate_glwe_stages(
+            yield_constr,
+            non_pad_flag,
+            &current_acc_in,
+            first_negated_mask,
+            mask_ele_bit_dec,
+            &rotations,
+        );
+        let diff_glwe = shifted_glwe.sub(&current_acc_in);
+        let xprod_in = eval_glwe_select(is_last_step, &current_acc_in, &diff_glwe);
+
+        // the polynomial of the external product input of this row
+        for (flag, poly) in glev_flags.iter().zip(&xprod_in.polys) {
+            for (committed, coeff) in xprod_in_poly.coeffs.iter().zip(&poly.coeffs) {
+                yield_constr.constraint(*flag * (*committed - *coeff));
+            }
+        }
+        let glev_mul = glev_ct.eval_mul::<LOGB>(
+            yield_constr,
+            non_pad_flag,
+            &xprod_in_poly,
+            &xprod_in_bit_dec,
+            &xprod_in_neg_bit_dec,
+            top_limbs,
+        );
+        let cal_xprod_sum_out = eval_glwe_select(
+            glev_flags[K - 1],
+            &xprod_sum_in.add(&glev_mul),
+            &xprod_sum_in.sub(&glev_mul),
+        );
+
+        let xprod_out = xprod_sum_out.ntt_backward();
+        let cmux_out = xprod_out.add(&current_acc_in);
+        // in the last step we don't do a cmux, but just an external product for key switch
+        let cmux_or_exprod = eval_glwe_select(is_last_step, &xprod_out, &cmux_out);
+        // in the first step (body) we don't apply the full CMUX, just the rotation
+        let cal_acc_out = eval_glwe_select(is_first_step, &shifted_glwe, &cmux_or_exprod);
+
+        // the committed partial sum and accumulator, on every row
+        for (committed, expected) in [
+            (&xprod_sum_out, &cal_xprod_sum_out),
+            (&current_acc_out, &cal_acc_out),
+        ] {
+            for (c, e) in committed.flatten().into_iter().zip(expected.flatten()) {
+                yield_constr.constraint(c - e);
+            }
+        }
+
+        // the partial sum starts from zero in a step and is carried over its rows
+        let next_xprod_sum_in = read_glwe_ct::<P, N, K>(nv, &mut { Self::XPROD_SUM_IN_COL });
+        for (sum_in, (next_in, out)) in xprod_sum_in.flatten().into_iter().zip(
+            next_xprod_sum_in
+                .flatten()
+                .into_iter()
+                .zip(xprod_sum_out.flatten()),
+        ) {
+            yield_constr.constraint(glev_flags[0] * sum_in);
+            yield_constr.constraint_transition(next_is_same_step * (next_in - out));
+        }
+
+        let next_acc_in = read_glwe_ct::<P, N, K>(nv, &mut 0);
+        let (acc_init, rest) = public_inputs.split_at(N * K);
+        let (acc_out, rest) = rest.split_at(N * K);
+        let (expected_bsk_hash, expected_lwe_hash) = rest.split_at(NUM_HASH_OUT_ELTS);
+
+        // the accumulator starts from the test vector
+        for (coeff, init) in current_acc_in.flatten().into_iter().zip(acc_init) {
+            yield_constr.constraint_first_row(coeff - *init);
+        }
+
+        let bsk_hash_inputs = [&bsk_hash_in[..], &lv[Self::GLEV_COL..Self::MASK_ELE_COL]].concat();
+        let bsk_hash =
+            eval_hash_no_pad::<F, D, FE, P, D2>(yield_constr, &bsk_hash_inputs, bsk_hash_columns);
+        let lwe_hash = eval_hash_no_pad::<F, D, FE, P, D2>(
+            yield_constr,
+            &[&lwe_hash_in[..], &[mask_element]].concat(),
+            lwe_hash_columns,
+        );
+
+        // the hashes are chained from the zero hash, over the rows for the BSK and over the steps
+        // for the LWE ciphertext, and the last non padding row holds the result
+        for (hash_in_col, hash, next_chained, expected) in [
+            (
+                Self::BSK_HASH_IN_COL,
+                bsk_hash,
+                next_non_pad_flag,
+                expected_bsk_hash,
+            ),
+            (
+                Self::LWE_HASH_IN_COL,
+                lwe_hash,
+                next_is_step_start,
+                expected_lwe_hash,
+            ),
+        ] {
+            for i in 0..NUM_HASH_OUT_ELTS {
+                yield_constr.constraint_first_row(lv[hash_in_col + i]);
+                yield_constr.constraint_transition(next_chained * (nv[hash_in_col + i] - hash[i]));
+                yield_constr.constraint(is_last_non_pad_row * (hash[i] - expected[i]));
+            }
+        }
+
+        // the accumulator computed in the last row of a step is the input of the next one
+        for (next_in, out) in next_acc_in
+            .flatten()
+            .into_iter()
+            .zip(current_acc_out.flatten())
+        {
+            yield_constr.constraint_transition(next_is_step_start * (next_in - out));
+        }
+
+        // the last non padding row holds the result of the PBS
+        for (out, expected) in current_acc_out.flatten().into_iter().zip(acc_out) {
+            yield_constr.constraint(is_last_non_pad_row * (out - *expected));
+        }
+    }
+
+    fn eval_ext_circuit(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        vars: &Self::EvaluationFrameTarget,
+        yield_constr: &mut RecursiveConstraintConsumer<F, D>,
+    ) {
+        let lv = vars.get_local_values();
+        let nv = vars.get_next_values();
+        let public_inputs = vars.get_public_inputs();
+        let mut cur_col = 0;
+
+        let current_acc_in = read_glwe_ct::<ExtensionTarget<D>, N, K>(lv, &mut cur_col);
+        let glev_ct = read_glev_ct::<ExtensionTarget<D>, N, K, ELL>(lv, &mut cur_col);
+        let mask_element = lv[cur_col];
+        cur_col += 1;
+
+        let mask_ele_bit_dec = read_array(lv, &mut cur_col);
+        let rotations = (0..num_rotation_stages::<N>())
+            .map(|_| read_glwe_ct::<ExtensionTarget<D>, N, K>(lv, &mut cur_col))
+            .collect_vec();
+        let xprod_in_poly = read_glwe_poly::<ExtensionTarget<D>, N>(lv, &mut cur_col);
+        let xprod_in_bit_dec: [[ExtensionTarget<D>; NUM_BITS]; N] =
+            from_fn(|_| read_array::<ExtensionTarget<D>, NUM_BITS>(lv, &mut cur_col));
+        let xprod_in_neg_bit_dec: [[ExtensionTarget<D>; NUM_BITS]; N] =
+            from_fn(|_| read_array::<ExtensionTarget<D>, NUM_BITS>(lv, &mut cur_col));
+        let top_limbs = &lv[cur_col..cur_col + ELL * N];
+        cur_col += top_limbs.len();
+
+        let hi_diff_invs = &lv[cur_col..cur_col + 1 + 2 * N];
+        cur_col += hi_diff_invs.len();
+
+        let xprod_sum_in = read_glwe_ct::<ExtensionTarget<D>, N, K>(lv, &mut cur_col);
+        let xprod_sum_out = read_glwe_ct::<ExtensionTarget<D>, N, K>(lv, &mut cur_col);
+        let current_acc_out = read_glwe_ct::<ExtensionTarget<D>, N, K>(lv, &mut cur_col);
+
+        let bsk_hash_in: [ExtensionTarget<D>; NUM_HASH_OUT_ELTS] = read_array(lv, &mut cur_col);
+        let bsk_hash_columns = &lv[cur_col..cur_col + Self::GLEV_HASH_COLUMNS];
+        cur_col += Self::GLEV_HASH_COLUMNS;
+
+        let lwe_hash_in: [ExtensionTarget<D>; NUM_HASH_OUT_ELTS] = read_array(lv, &mut cur_col);
+        let lwe_hash_columns = &lv[cur_col..cur_col + LWE_HASH_COLUMNS];
+        cur_col += LWE_HASH_COLUMNS;
+
+        let counter = lv[cur_col];
+        cur_col += 1;
+
+        let glev_flags = &lv[cur_col..cur_col + K];
+        cur_col += K;
+
+        let is_first_step = lv[cur_col];
+        cur_col += 1;
+
+        let is_last_step = lv[cur_col];
+        cur_col += 1;
+
+        let is_last_non_pad_row = lv[cur_col];
+
+        assert_eq!(cur_col + 1, COLUMNS);
+
+        let one = builder.one_extension();
+        let num_steps = builder.constant_extension(F::Extension::from_canonical_usize(n + 2));
+
+        // a row is in at most one GLEV row of a step, and padding rows are in none
+        let non_pad_flag = builder.add_many_extension(glev_flags);
+        let next_glev_flags = &nv[Self::GLEV_FLAGS_COL..Self::GLEV_FLAGS_COL + K];
+        let next_non_pad_flag = builder.add_many_extension(next_glev_flags);
+        let next_is_step_start = next_glev_flags[0];
+        let next_is_same_step = builder.sub_extension(next_non_pad_flag, next_is_step_start);
+
+        for flag in glev_flags.iter().chain([
+            &non_pad_flag,
+            &is_first_step,
+            &is_last_step,
+            &is_last_non_pad_row,
+        ]) {
+            let constr = builder.mul_sub_extension(*flag, *flag, *flag);
+            yield_constr.constraint(builder, constr);
+        }
+
+        // the GLEV rows of a step follow each other, and a step may only be followed by padding
+        // once it is complete
+        let constr = builder.sub_extension(glev_flags[0], one);
+        yield_constr.constraint_first_row(builder, constr);
+        for j in 1..K {
+            yield_constr.constraint_first_row(builder, glev_flags[j]);
+            let constr = builder.sub_extension(next_glev_flags[j], glev_flags[j - 1]);
+            yield_constr.constraint_transition(builder, constr);
+        }
+        let next_step = builder.mul_extension(glev_flags[K - 1], next_non_pad_flag);
+        let constr = builder.sub_extension(next_is_step_start, next_step);
+        yield_constr.constraint_transition(builder, constr);
+
+        for col in Self::step_columns() {
+            let diff = builder.sub_extension(nv[col], lv[col]);
+            let constr = builder.mul_extension(next_is_same_step, diff);
+            yield_constr.constraint_transition(builder, constr);
+        }
+
+        let constr = builder.sub_extension(counter, one);
+        yield_constr.constraint_first_row(builder, constr);
+        let diff = builder.sub_extension(nv[Self::COUNTER_COL], counter);
+        let constr = builder.sub_extension(diff, next_is_step_start);
+        yield_constr.constraint_transition(builder, constr);
+
+        // only the first step does the body rotation, and only the last one the key switch
+        let constr = builder.sub_extension(is_first_step, one);
+        yield_constr.constraint_first_row(builder, constr);
+        let diff = builder.sub_extension(counter, one);
+        let constr = builder.mul_extension(is_first_step, diff);
+        yield_constr.constraint(builder, constr);
+        let diff = builder.sub_extension(counter, num_steps);
+        let constr = builder.mul_extension(is_last_step, diff);
+        yield_constr.constraint(builder, constr);
+
+        // the last non padding row ends the last step
+        let next_pad_flag = builder.sub_extension(one, next_non_pad_flag);
+        let last = builder.mul_extension(non_pad_flag, next_pad_flag);
+        let constr = builder.sub_extension(is_last_non_pad_row, last);
+        yield_constr.constraint_transition(builder, constr);
+        let constr = builder.sub_extension(is_last_non_pad_row, non_pad_flag);
+        yield_constr.constraint_last_row(builder, constr);
+        for flag in [is_last_step, glev_flags[K - 1]] {
+            let diff = builder.sub_extension(one, flag);
+            let constr = builder.mul_extension(is_last_non_pad_row, diff);
+            yield_constr.constraint(builder, constr);
+        }
+
+        // a 64 bit decomposition of a field element is only unique if it encodes a value < p
+        for (bit_dec, hi_diff_inv) in [&mask_ele_bit_dec]
+            .into_iter()
+            .chain(&xprod_in_bit_dec)
+            .chain(&xprod_in_neg_bit_dec)
+            .zip_eq(hi_diff_invs)
+        {
+            eval_canonical_bits_ext(builder, yield_constr, bit_dec, *hi_diff_inv);
+        }
+
+        let neg_one = builder.neg_one_extension();
+        let neg_mask = builder.mul_extension(neg_one, mask_element);
+        let diff = builder.sub_extension(neg_mask, mask_element);
+        let first_negated_mask = builder.mul_add_extension(is_first_step, diff, mask_element);
+        let shifted_glwe = eval_rotate_glwe_stages_ext(
+            builder,
+            yield_constr,
+            non_pad_flag,
+            &current_acc_in,
+            first_negated_mask,
+            mask_ele_bit_dec,
+            &rotations,
+        );
+        let diff_glwe = shifted_glwe.sub_ext(builder, &current_acc_in);
+        let xprod_in = eval_glwe_select_ext(builder, is_last_step, &current_acc_in, &diff_glwe);
+
+        // the polynomial of the external product input of this row
+        for (flag, poly) in glev_flags.iter().zip(&xprod_in.polys) {
+            for (committed, coeff) in xprod_in_poly.coeffs.iter().zip(&poly.coeffs) {
+                let diff = builder.sub_extension(*committed, *coeff);
+                let constr = builder.mul_extension(*flag, diff);
+                yield_constr.constraint(builder, constr);
+            }
+        }
+        let glev_mul = glev_ct.eval_mul_ext::<F, LOGB>(
+            builder,
+            yield_constr,
+            non_pad_flag,
+            &xprod_in_poly,
+            &xprod_in_bit_dec,
+            &xprod_in_neg_bit_dec,
+            top_limbs,
+        );
+        let sum_add = xprod_sum_in.add_ext(builder, &glev_mul);
+        let sum_sub = xprod_sum_in.sub_ext(builder, &glev_mul);
+        let cal_xprod_sum_out =
+            eval_glwe_select_ext(builder, glev_flags[K - 1], &sum_add, &sum_sub);
+
+        let xprod_out = xprod_sum_out.ntt_backward_ext(builder);
+        let cmux_out = xprod_out.add_ext(builder, &current_acc_in);
+        // in the last step we don't do a cmux, but just an external product for key switch
+        let cmux_or_exprod = eval_glwe_select_ext(builder, is_last_step, &xprod_out, &cmux_out);
+        // in the first step (body) we don't apply the full CMUX, just the rotation
+        let cal_acc_out =
+            eval_glwe_select_ext(builder, is_first_step, &shifted_glwe, &cmux_or_exprod);
+
+        // the committed partial sum and accumulator, on every row
+        for (committed, expected) in [
+            (&xprod_sum_out, &cal_xprod_sum_out),
+            (&current_acc_out, &cal_acc_out),
+        ] {
+            for (c, e) in committed
+                .flatten_ext()
+                .into_iter()
+                .zip(expected.flatten_ext())
+            {
+                let constr = builder.sub_extension(c, e);
+                yield_constr.constraint(builder, constr);
+            }
+        }
+
+        // the partial sum starts from zero in a step and is carried over its rows
+        let next_xprod_sum_in =
+            read_glwe_ct::<ExtensionTarget<D>, N, K>(nv, &mut { Self::XPROD_SUM_IN_COL });
+        for (sum_in, (next_in, out)) in xprod_sum_in.flatten_ext().into_iter().zip(
+            next_xprod_sum_in
+                .flatten_ext()
+                .into_iter()
+                .zip(xprod_sum_out.flatten_ext()),
+        ) {
+            let constr = builder.mul_extension(glev_flags[0], sum_in);
+            yield_constr.constraint(builder, constr);
+            let diff = builder.sub_extension(next_in, out);
+            let constr = builder.mul_extension(next_is_same_step, diff);
+            yield_constr.constraint_transition(builder, constr);
+        }
+
+        let next_acc_in = read_glwe_ct::<ExtensionTarget<D>, N, K>(nv, &mut 0);
+        let (acc_init, rest) = public_inputs.split_at(N * K);
+        let (acc_out, rest) = rest.split_at(N * K);
+        let (expected_bsk_hash, expected_lwe_hash) = rest.split_at(NUM_HASH_OUT_ELTS);
+
+        // the accumulator starts from the test vector
+        for (coeff, init) in current_acc_in.flatten_ext().into_iter().zip(acc_init) {
+            let constr = builder.sub_extension(coeff, *init);
+            yield_constr.constraint_first_row(builder, constr);
+        }
+
+        let bsk_hash_inputs = [&bsk_hash_in[..], &lv[Self::GLEV_COL..Self::MASK_ELE_COL]].concat();
+        let bsk_hash =
+            eval_hash_no_pad_ext(builder, yield_constr, &bsk_hash_inputs, bsk_hash_columns);
+        let lwe_hash = eval_hash_no_pad_ext(
+            builder,
+            yield_constr,
+            &[&lwe_hash_in[..], &[mask_element]].concat(),
+            lwe_hash_columns,
+        );
+
+        // the hashes are chained from the zero hash, over the rows for the BSK and over the steps
+        // for the LWE ciphertext, and the last non padding row holds the result
+        for (hash_in_col, hash, next_chained, expected) in [
+            (
+                Self::BSK_HASH_IN_COL,
+                bsk_hash,
+                next_non_pad_flag,
+                expected_bsk_hash,
+            ),
+            (
+                Self::LWE_HASH_IN_COL,
+                lwe_hash,
+                next_is_step_start,
+                expected_lwe_hash,
+            ),
+        ] {
+            for i in 0..NUM_HASH_OUT_ELTS {
+                yield_constr.constraint_first_row(builder, lv[hash_in_col + i]);
+                let diff = builder.sub_extension(nv[hash_in_col + i], hash[i]);
+                let constr = builder.mul_extension(next_chained, diff);
+                yield_constr.constraint_transition(builder, constr);
+                let diff = builder.sub_extension(hash[i], expected[i]);
+                let constr = builder.mul_extension(is_last_non_pad_row, diff);
+                yield_constr.constraint(builder, constr);
+            }
+        }
+
+        // the accumulator computed in the last row of a step is the input of the next one
+        for (next_in, out) in next_acc_in
+            .flatten_ext()
+            .into_iter()
+            .zip(current_acc_out.flatten_ext())
+        {
+            let diff = builder.sub_extension(next_in, out);
+            let constr = builder.mul_extension(next_is_step_start, diff);
+            yield_constr.constraint_transition(builder, constr);
+        }
+
+        // the last non padding row holds the result of the PBS
+        for (out, expected) in current_acc_out.flatten_ext().into_iter().zip(acc_out) {
+            let diff = builder.sub_extension(out, *expected);
+            let constr = builder.mul_extension(is_last_non_pad_row, diff);
+            yield_constr.constraint(builder, constr);
+        }
+    }
+
+    fn constraint_degree(&self) -> usize {
+        3
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vtfhe::{
+        crypto::{compute_bsk, get_testv, lwe::encrypt},
+        starky_ct::vpbs::vpbs_stark,
+    };
+    use plonky2::{
+        iop::witness::PartialWitness,
+        plonk::{
+            circuit_data::CircuitConfig,
+            config::{GenericConfig, PoseidonGoldilocksConfig},
+        },
+        util::{log2_ceil, timing::TimingTree},
+    };
+    use rand::random;
+    use starky::{
+        config::StarkConfig,
+        prover::prove,
+        recursive_verifier::{
+            add_virtual_stark_proof_with_pis, set_stark_proof_with_pis_target,
+            verify_stark_proof_circuit,
+        },
+        stark_testing::test_stark_low_degree,
+        verifier::verify_stark_proof,
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    #[test]
+    fn test_vpbs_multi_row() {
+        const LOGB: usize = 5;
+        const ELL: usize = 4;
+        const N: usize = 8;
+        const K: usize = 2;
+        const n: usize = 4;
+        const p: usize = 2;
+        let stark = <vpbs_multi_row_stark!(F, D, n, N, K, ELL, LOGB)>::new();
+
+        // partial GLWE key corresponding to LWE key
+        let s_to = Glwe::<F, D, N, K>::partial_key(n);
+        let s_lwe = Glwe::<F, D, N, K>::flatten_partial_key(&s_to, n);
+
+        let s_glwe = Glwe::<F, D, N, K>::key_gen();
+        let bsk = compute_bsk::<F, D, N, K, ELL, LOGB>(&s_lwe, &s_glwe, 0f64);
+
+        let ksk = Ggsw::<F, D, N, K, ELL>::compute_ksk::<LOGB>(&s_to, &s_glwe, 0f64);
+
+        let delta = F::from_noncanonical_biguint(F::order() >> log2_ceil(2 * p));
+
+        let testv = get_testv::<F, D, N>(p, delta);
+
+        let m = F::from_canonical_usize(random::<usize>() % p);
+        let ct = encrypt::<F, D, n>(&s_lwe, &(delta * m), 0f64);
+
+        let config = StarkConfig::standard_fast_config();
+        let (trace, public_inputs, out_ct) = stark.generate_trace(&ct, &testv, &bsk, &ksk);
+        let num_rows = ((n + 2) * K).next_power_of_two();
+        assert_eq!(trace.len(), vpbs_multi_row_columns::<N, K, ELL>());
+        assert_eq!(trace[0].len(), num_rows);
+
+        // same PBS as the single row layout, with the same public inputs but bsk_hash
+        let (_, single_row_public_inputs, single_row_out_ct) =
+            <vpbs_stark!(F, D, n, N, K, ELL, LOGB, false)>::new()
+                .generate_trace(&ct, &testv, &bsk, &ksk);
+        assert_eq!(out_ct, single_row_out_ct);
+        assert_eq!(public_inputs.len(), single_row_public_inputs.len());
+        assert_eq!(
+            public_inputs[..2 * N * K],
+            single_row_public_inputs[..2 * N * K]
+        );
+        assert_eq!(public_inputs[N * K..2 * N * K], out_ct.flatten());
+        let lwe_hash_col = 2 * N * K + NUM_HASH_OUT_ELTS;
+        assert_eq!(
+            public_inputs[lwe_hash_col..],
+            single_row_public_inputs[lwe_hash_col..]
+        );
+
+        // bsk_hash is chained over the GLEVs of the dummy GGSW, the BSK and the KSK
+        let ggsw_cts = [GgswCtNative::dummy_ct()]
+            .into_iter()
+            .chain(bsk.iter().map(GgswCtNative::from_ggsw))
+            .chain([GgswCtNative::from_ggsw(&ksk)]);
+        let bsk_hash =
+            ggsw_cts
+                .flat_map(|ggsw_ct| ggsw_ct.glev_cts)
+                .fold(HashOut::ZERO, |hash, glev_ct| {
+                    hash_no_pad_native(&[&hash.elements[..], &glev_ct.flatten()].concat()).0
+                });
+        assert_eq!(public_inputs[2 * N * K..lwe_hash_col], bsk_hash.elements);
+
+        let inner_proof = prove::<F, C, _, D>(
+            stark,
+            &config,
+            trace,
+            &public_inputs,
+            &mut TimingTree::default(),
+        )
+        .unwrap();
+        verify_stark_proof(stark, inner_proof.clone(), &config).unwrap();
+        let degree_bits = inner_proof.proof.recover_degree_bits(&config);
+        assert_eq!(degree_bits, log2_ceil(num_rows));
+
+        let circuit_config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(circuit_config);
+        let mut pw = PartialWitness::new();
+        let pt = add_virtual_stark_proof_with_pis(&mut builder, &stark, &config, degree_bits, 0, 0);
+        set_stark_proof_with_pis_target(&mut pw, &pt, &inner_proof, builder.zero());
+        verify_stark_proof_circuit::<F, C, _, D>(&mut builder, stark, pt, &config);
+        let data = builder.build::<C>();
+        let proof = data.prove(pw).unwrap();
+        data.verify(proof).unwrap();
+    }
+
+    #[test]
+    fn test_vpbs_multi_row_low_degree() {
+        test_stark_low_degree(<vpbs_multi_row_stark!(F, D, 4, 8, 2, 4, 5)>::new()).unwrap();
+    }
+}